@@ -0,0 +1,102 @@
+//! Benchmarks for transaction selection in the transaction pool
+//!
+//! Run with: cargo bench --bench pool_benchmarks
+//!
+//! Compares `TransactionPool::select_transactions` against a rescan of
+//! every pooled transaction per selected slot, the approach it replaced,
+//! on pools of several thousand transactions.
+
+use blocana::{
+    crypto::KeyPair,
+    state::BlockchainState,
+    transaction::{
+        pool::{TransactionPool, TransactionPoolConfig},
+        Transaction,
+    },
+    types::PublicKeyBytes,
+};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::collections::HashMap;
+
+/// Transactions per sender
+const NONCES_PER_SENDER: u64 = 10;
+
+/// Build a pool of `tx_count` transactions from senders with consecutive nonces
+fn build_pool(tx_count: usize) -> (TransactionPool, BlockchainState) {
+    let config = TransactionPoolConfig {
+        max_size: tx_count,
+        max_memory: 1024 * 1024 * 1024,
+        min_fee_per_byte: 0,
+        ..Default::default()
+    };
+    let mut pool = TransactionPool::with_config(config);
+    let mut state = BlockchainState::new();
+    let recipient = [2u8; 32];
+
+    let sender_count = tx_count / NONCES_PER_SENDER as usize;
+    for i in 0..sender_count {
+        let sender = KeyPair::generate().unwrap();
+        state.get_account_state(&sender.public_key).balance = u64::MAX / 2;
+        for nonce in 0..NONCES_PER_SENDER {
+            let fee = 1_000 + ((i as u64 * 7919 + nonce * 104_729) % 50_000);
+            let mut tx = Transaction::new(sender.public_key, recipient, 100, fee, nonce, vec![]);
+            tx.sign(&sender.private_key).unwrap();
+            pool.add_transaction(tx, &mut state).unwrap();
+        }
+    }
+
+    (pool, state)
+}
+
+/// Select by rescanning every transaction for each slot
+fn select_by_rescan(pool: &TransactionPool, state: &BlockchainState, max_count: usize) -> Vec<Transaction> {
+    let mut remaining: Vec<&Transaction> = pool.get_all_transactions().collect();
+    let mut accounts: HashMap<PublicKeyBytes, (u64, u64)> = HashMap::new();
+    for tx in &remaining {
+        let account = &state.accounts[&tx.sender];
+        accounts.insert(tx.sender, (account.balance, account.nonce));
+    }
+
+    let mut result = Vec::new();
+    while result.len() < max_count {
+        let best = remaining
+            .iter()
+            .enumerate()
+            .filter(|(_, tx)| {
+                let (balance, nonce) = accounts[&tx.sender];
+                tx.nonce == nonce && balance >= tx.balance_cost()
+            })
+            .max_by_key(|(_, tx)| tx.fee / tx.estimate_size() as u64)
+            .map(|(index, _)| index);
+        let Some(index) = best else { break };
+
+        let tx = remaining.swap_remove(index);
+        let (balance, nonce) = accounts.get_mut(&tx.sender).unwrap();
+        *balance -= tx.balance_cost();
+        *nonce += 1;
+        result.push(tx.clone());
+    }
+    result
+}
+
+fn bench_selection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("select_transactions");
+    group.sample_size(10);
+
+    for tx_count in [1_000, 5_000, 10_000] {
+        let (mut pool, state) = build_pool(tx_count);
+        let max_count = tx_count / 2;
+
+        group.bench_with_input(BenchmarkId::new("heap", tx_count), &max_count, |b, &max_count| {
+            b.iter(|| black_box(pool.select_transactions(max_count, &state)))
+        });
+        group.bench_with_input(BenchmarkId::new("rescan", tx_count), &max_count, |b, &max_count| {
+            b.iter(|| black_box(select_by_rescan(&pool, &state, max_count)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_selection);
+criterion_main!(benches);
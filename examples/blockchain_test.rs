@@ -15,8 +15,9 @@ use std::collections::HashMap;
 use std::path::Path;
 use blocana::{
     block::Block,
+    consensus::ConsensusConfig,
     crypto::KeyPair,
-    state::BlockchainState,
+    state::{BlockchainState, RewardConfig},
     storage::{BlockchainStorage, StorageConfig},
     transaction::Transaction,
 };
@@ -114,7 +115,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("✅ Block 1 successfully stored");
     
     // 14. Apply the block to update the state
-    state.apply_block(&block1)?;
+    state.apply_block(&block1, &RewardConfig::default(), &ConsensusConfig::default())?;
     println!("✅ State updated with block 1 transactions");
     
     // 15. Verify the resulting state
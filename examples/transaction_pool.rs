@@ -22,6 +22,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         max_memory: 10 * 1024 * 1024, // 10 MB
         min_fee_per_byte: 1,
         replacement_fee_bump: 10, // 10% fee bump for replacements
+        network_id: blocana::DEFAULT_NETWORK_ID,
+        max_queued_per_sender: 16,
+        max_queued: 1024,
+        max_pending_per_sender: 256,
+        max_bytes_per_sender: 512 * 1024,
+        max_submissions_per_sender: 120,
+        submission_window: 60,
     };
     
     let mut pool = TransactionPool::with_config(config);
@@ -86,7 +93,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     // Select transactions for a block (should select in fee order)
     println!("\nSelecting transactions for block:");
-    let selected = pool.select_transactions(2, &mut state);
+    let selected = pool.select_transactions(2, &state);
     
     println!("Selected {} transactions:", selected.len());
     for (i, tx) in selected.iter().enumerate() {
@@ -106,7 +113,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("- Bob: {}", state.get_account_state(&bob.public_key).balance);
     
     // Select transactions again (should now select different ones)
-    let selected_after = pool.select_transactions(2, &mut state);
+    let selected_after = pool.select_transactions(2, &state);
     println!("\nSelecting transactions again:");
     println!("Selected {} transactions:", selected_after.len());
     for (i, tx) in selected_after.iter().enumerate() {
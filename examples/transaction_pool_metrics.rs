@@ -69,7 +69,7 @@ fn main() {
     
     // 2. Select transactions
     println!("\nSelecting transactions...");
-    let selected = pool.select_transactions(10, &mut state);
+    let selected = pool.select_transactions(10, &state);
     println!("Selected {} transactions", selected.len());
     
     // 3. Remove some transactions
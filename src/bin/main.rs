@@ -1,10 +1,15 @@
 use blocana::{Blockchain, BlockchainConfig}; // Quitamos Transaction ya que no se usa
+use blocana::consensus::BlockProducer;
+use blocana::network::NetworkManager;
 use std::process;
 use std::io::{self, BufRead, Write};
 use clap::{Command, Arg}; // Quitamos SubCommand ya que no se usa
 use std::sync::{Arc, Mutex};
 use std::thread;
 
+/// Longest ban the CLI accepts, one year
+const MAX_BAN_MINUTES: u64 = 365 * 24 * 60;
+
 fn main() {
     // Parse command line arguments
     let matches = Command::new("Blocana")
@@ -25,7 +30,15 @@ fn main() {
         .arg(Arg::new("connect")
             .long("connect")
             .value_name("ADDRESS")
-            .help("Address of node to connect to"))
+            .help("Multiaddress of node to connect to, e.g. /ip4/127.0.0.1/tcp/8080"))
+        .arg(Arg::new("genesis")
+            .long("genesis")
+            .value_name("FILE")
+            .help("JSON genesis file describing the network"))
+        .arg(Arg::new("validator")
+            .long("validator")
+            .help("Produce blocks whenever the consensus schedule gives this node its turn")
+            .action(clap::ArgAction::SetTrue))
         .arg(Arg::new("interactive")
             .long("interactive")
             .short('i') // Cambiado de "i" a 'i' para corregir el error
@@ -46,8 +59,29 @@ fn main() {
         }
     }
     
-    // Store the port before moving config
+    if let Some(address) = matches.get_one::<String>("connect") {
+        config.network_config.bootstrap_nodes.push(address.clone());
+    }
+    
+    if let Some(path) = matches.get_one::<String>("genesis") {
+        match blocana::genesis::GenesisConfig::load(path) {
+            Ok(genesis) => config = config.with_genesis(genesis),
+            Err(e) => {
+                eprintln!("Failed to load genesis file {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+    }
+    
+    // Store the network settings before moving config
     let listen_port = config.network_config.listen_port;
+    let mut network = match NetworkManager::new(&config.network_config) {
+        Ok(network) => network,
+        Err(e) => {
+            eprintln!("Failed to initialize network: {:?}", e);
+            process::exit(1);
+        }
+    };
     
     // Create and start the blockchain
     match Blockchain::new(config) {
@@ -57,21 +91,33 @@ fn main() {
             // Wrap the blockchain in an Arc<Mutex> so it can be shared between threads
             let blockchain = Arc::new(Mutex::new(blockchain));
             
-            // Start the blockchain in a separate thread
-            let blockchain_clone = blockchain.clone();
-            thread::spawn(move || {
-                let mut bc = blockchain_clone.lock().unwrap();
-                if let Err(e) = (&mut *bc).start() {
-                    eprintln!("Failed to start blockchain: {:?}", e);
+            if let Err(e) = network.start(blockchain.clone()) {
+                eprintln!("Failed to start network: {:?}", e);
+                process::exit(1);
+            }
+            let network = Arc::new(network);
+            
+            println!("Blocana node running on port {} as peer {}", listen_port, network.local_peer_id());
+            
+            // Produce blocks on schedule and announce them to the network
+            let mut producer = BlockProducer::new();
+            if matches.get_flag("validator") {
+                let publisher = network.clone();
+                let started = producer.start(blockchain.clone(), move |block| {
+                    if let Err(e) = publisher.publish_block(block) {
+                        eprintln!("Failed to publish block: {:?}", e);
+                    }
+                });
+                if let Err(e) = started {
+                    eprintln!("Failed to start block production: {:?}", e);
                     process::exit(1);
                 }
-            });
-            
-            println!("Blocana node running on port {}", listen_port);
+                println!("Producing blocks as validator");
+            }
             
             // If interactive mode is enabled, start the CLI
             if matches.get_flag("interactive") {
-                run_interactive_cli(blockchain);
+                run_interactive_cli(blockchain, &network);
             } else {
                 // Keep the main thread alive
                 loop {
@@ -87,7 +133,7 @@ fn main() {
 }
 
 // Interactive CLI for Blocana
-fn run_interactive_cli(blockchain: Arc<Mutex<Blockchain>>) {
+fn run_interactive_cli(blockchain: Arc<Mutex<Blockchain>>, network: &NetworkManager) {
     println!("Welcome to Blocana Interactive CLI");
     println!("Type 'help' for available commands");
     
@@ -112,7 +158,9 @@ fn run_interactive_cli(blockchain: Arc<Mutex<Blockchain>>) {
                 println!("  block create                - Generate a new block");
                 println!("  tx create <to> <amount>     - Create a new transaction");
                 println!("  status                      - Show blockchain status");
-                println!("  peers                       - List connected peers");
+                println!("  peers                       - List peers and their scores");
+                println!("  ban <peer> [minutes]        - Disconnect and refuse a peer (default 60 minutes)");
+                println!("  unban <peer>                - Accept a banned peer again");
                 println!("  quit                        - Exit the program");
             }
             "block" => {
@@ -120,8 +168,13 @@ fn run_interactive_cli(blockchain: Arc<Mutex<Blockchain>>) {
                     println!("Creating a new block...");
                     let mut bc = blockchain.lock().unwrap();
                     match bc.generate_block() {
-                        Ok(block) => println!("Block created: height={}, transactions={}", 
-                            block.header.height, block.transactions.len()),
+                        Ok(block) => {
+                            println!("Block created: height={}, transactions={}", 
+                                block.header.height, block.transactions.len());
+                            if let Err(e) = network.publish_block(&block) {
+                                println!("Failed to announce block: {:?}", e);
+                            }
+                        }
                         Err(e) => println!("Failed to create block: {:?}", e),
                     }
                 } else {
@@ -132,18 +185,25 @@ fn run_interactive_cli(blockchain: Arc<Mutex<Blockchain>>) {
                 if parts.len() >= 4 && parts[1] == "create" {
                     let to = parts[2];
                     if let Ok(amount) = parts[3].parse::<u64>() {
+                        let recipient = match blocana::crypto::hex_to_hash(to) {
+                            Ok(address) => address,
+                            Err(_) => {
+                                println!("Invalid recipient address, expected 64 hex characters");
+                                continue;
+                            }
+                        };
                         println!("Creating transaction to {} with amount {}", to, amount);
                         
-                        // Create a placeholder recipient (in a real app, we'd parse an address)
-                        let mut recipient = [0u8; 32];
-                        let bytes = to.as_bytes();
-                        for (i, &byte) in bytes.iter().enumerate().take(32) {
-                            recipient[i] = byte;
-                        }
-                        
                         let mut bc = blockchain.lock().unwrap();
                         match bc.create_transaction(recipient, amount) {
-                            Ok(_) => println!("Transaction created successfully"),
+                            Ok(hash) => {
+                                println!("Transaction created: {}", hex::encode(hash));
+                                if let Some(tx) = bc.pool().get_transaction(&hash) {
+                                    if let Err(e) = network.publish_transaction(tx) {
+                                        println!("Failed to announce transaction: {:?}", e);
+                                    }
+                                }
+                            }
                             Err(e) => println!("Failed to create transaction: {:?}", e),
                         }
                     } else {
@@ -158,8 +218,51 @@ fn run_interactive_cli(blockchain: Arc<Mutex<Blockchain>>) {
                 bc.print_status();
             }
             "peers" => {
-                let bc = blockchain.lock().unwrap();
-                bc.print_peers();
+                let connected = network.connected_peers();
+                if connected.is_empty() {
+                    println!("Connected Peers: None");
+                } else {
+                    println!("Connected Peers: {}", connected.len());
+                }
+                for entry in network.peer_scores() {
+                    let state = match entry.banned_for {
+                        Some(left) => format!("banned for {}s", left.as_secs()),
+                        None if connected.contains(&entry.peer) => "connected".to_string(),
+                        None => "disconnected".to_string(),
+                    };
+                    println!("  {}  score {:.1}  {}", entry.peer, entry.score, state);
+                }
+                for address in network.listen_addresses() {
+                    println!("  Listening on {}", address);
+                }
+            }
+            "ban" => {
+                if parts.len() < 2 {
+                    println!("Usage: ban <peer> [minutes]");
+                    continue;
+                }
+                let minutes = match parts.get(2).map(|minutes| minutes.parse::<u64>()) {
+                    None => 60,
+                    Some(Ok(minutes)) if minutes <= MAX_BAN_MINUTES => minutes,
+                    Some(_) => {
+                        println!("Invalid number of minutes");
+                        continue;
+                    }
+                };
+                match network.ban_peer(parts[1], std::time::Duration::from_secs(minutes * 60)) {
+                    Ok(()) => println!("Banned {} for {} minutes", parts[1], minutes),
+                    Err(e) => println!("Failed to ban peer: {:?}", e),
+                }
+            }
+            "unban" => {
+                if parts.len() < 2 {
+                    println!("Usage: unban <peer>");
+                    continue;
+                }
+                match network.unban_peer(parts[1]) {
+                    Ok(()) => println!("Unbanned {}", parts[1]),
+                    Err(e) => println!("Failed to unban peer: {:?}", e),
+                }
             }
             "quit" => {
                 println!("Exiting Blocana");
@@ -3,14 +3,41 @@
 //! This module contains the core block structures and related functionality.
 
 use serde_big_array::BigArray;
+use crate::crypto::MerkleProof;
 use crate::transaction::Transaction;
 use crate::types::{Hash, PublicKeyBytes, SignatureBytes};
 use std::time::{SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
+use bincode::de::{Decode, Decoder};
+use bincode::enc::{Encode, Encoder};
+use bincode::error::{DecodeError, EncodeError};
 
+/// Header version produced by this node
+pub const BLOCK_VERSION: u8 = 3;
+
+/// First header version that commits to the account state root
+pub const STATE_ROOT_BLOCK_VERSION: u8 = 2;
+
+/// First header version that carries a wait certificate
+pub const WAIT_CERTIFICATE_BLOCK_VERSION: u8 = 3;
+
+/// Proof that a validator waited its turn before producing a block
+///
+/// The proof is the validator's signature over the parent block hash, and
+/// the wait is derived from the hash of that signature, so anyone holding
+/// the validator's public key can recompute it. See
+/// [`crate::consensus::PoETConsensus`] for how it is produced and checked.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+pub struct WaitCertificate {
+    /// Validator signature over the parent block hash
+    #[serde(with = "BigArray")]
+    pub proof: SignatureBytes,
+    /// Claimed wait after the parent block's timestamp in milliseconds
+    pub wait_ms: u64,
+}
 
 /// Block header containing metadata
-#[derive(Clone, Debug, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BlockHeader {
     /// Protocol version
     pub version: u8,
@@ -18,12 +45,23 @@ pub struct BlockHeader {
     pub prev_hash: Hash,
     /// Merkle root of transactions
     pub merkle_root: Hash,
+    /// Root of the account state after applying this block
+    ///
+    /// Only present from [`STATE_ROOT_BLOCK_VERSION`] on; older headers
+    /// carry all zeros and do not commit to it.
+    #[serde(default)]
+    pub state_root: Hash,
     /// Block timestamp (ms since UNIX epoch)
     pub timestamp: u64,
     /// Block height
     pub height: u64,
     /// Validator public key
     pub validator: PublicKeyBytes,
+    /// Proof of the validator's wait before producing this block
+    ///
+    /// Only present from [`WAIT_CERTIFICATE_BLOCK_VERSION`] on.
+    #[serde(default)]
+    pub wait_certificate: Option<WaitCertificate>,
     /// Validator signature
     #[serde(with = "BigArray")]
     pub signature: SignatureBytes,
@@ -47,13 +85,25 @@ impl BlockHeader {
             version,
             prev_hash,
             merkle_root,
+            state_root: [0u8; 32],
             timestamp,
             height,
             validator,
+            wait_certificate: None,
             signature: [0u8; 64],
         }
     }
     
+    /// Check whether this header commits to the account state root
+    pub fn has_state_root(&self) -> bool {
+        self.version >= STATE_ROOT_BLOCK_VERSION
+    }
+    
+    /// Check whether this header version carries a wait certificate
+    pub fn has_wait_certificate(&self) -> bool {
+        self.version >= WAIT_CERTIFICATE_BLOCK_VERSION
+    }
+    
     /// Sign the block header with the given private key
     pub fn sign(&mut self, private_key: &crate::types::PrivateKeyBytes) -> Result<(), crate::Error> {
         // Get bytes to sign (without the signature field)
@@ -81,18 +131,33 @@ impl BlockHeader {
             1 + // version
             32 + // prev_hash
             32 + // merkle_root
+            32 + // state_root
             8 + // timestamp
             8 + // height
-            32   // validator
+            32 + // validator
+            1 + 64 + 8 // wait certificate
         );
         
         // Append fields in canonical order
         bytes.push(self.version);
         bytes.extend_from_slice(&self.prev_hash);
         bytes.extend_from_slice(&self.merkle_root);
+        if self.has_state_root() {
+            bytes.extend_from_slice(&self.state_root);
+        }
         bytes.extend_from_slice(&self.timestamp.to_le_bytes());
         bytes.extend_from_slice(&self.height.to_le_bytes());
         bytes.extend_from_slice(&self.validator);
+        if self.has_wait_certificate() {
+            match &self.wait_certificate {
+                Some(certificate) => {
+                    bytes.push(1);
+                    bytes.extend_from_slice(&certificate.proof);
+                    bytes.extend_from_slice(&certificate.wait_ms.to_le_bytes());
+                }
+                None => bytes.push(0),
+            }
+        }
         
         bytes
     }
@@ -106,8 +171,76 @@ impl BlockHeader {
     pub fn hash(&self) -> Hash {
         crate::crypto::hash_data(&self.serialize_for_hashing())
     }
+    
+    /// Verify that a transaction is included in the block with this header
+    ///
+    /// Only needs the header, so light clients can check inclusion without
+    /// downloading the block.
+    pub fn verify_transaction_proof(&self, tx: &Transaction, proof: &MerkleProof) -> Result<(), crate::Error> {
+        if !crate::crypto::verify_merkle_proof(&tx.hash(), proof, &self.merkle_root) {
+            return Err(crate::Error::Validation("Invalid transaction inclusion proof".into()));
+        }
+        
+        Ok(())
+    }
+}
+
+// The binary layout depends on the version byte, so headers stored before the
+// state root or the wait certificate existed keep decoding.
+impl Encode for BlockHeader {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.version.encode(encoder)?;
+        self.prev_hash.encode(encoder)?;
+        self.merkle_root.encode(encoder)?;
+        if self.has_state_root() {
+            self.state_root.encode(encoder)?;
+        }
+        self.timestamp.encode(encoder)?;
+        self.height.encode(encoder)?;
+        self.validator.encode(encoder)?;
+        if self.has_wait_certificate() {
+            self.wait_certificate.encode(encoder)?;
+        }
+        self.signature.encode(encoder)
+    }
+}
+
+impl<Context> Decode<Context> for BlockHeader {
+    fn decode<D: Decoder<Context = Context>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let version = u8::decode(decoder)?;
+        let prev_hash = Hash::decode(decoder)?;
+        let merkle_root = Hash::decode(decoder)?;
+        let state_root = if version >= STATE_ROOT_BLOCK_VERSION {
+            Hash::decode(decoder)?
+        } else {
+            [0u8; 32]
+        };
+
+        let timestamp = u64::decode(decoder)?;
+        let height = u64::decode(decoder)?;
+        let validator = PublicKeyBytes::decode(decoder)?;
+        let wait_certificate = if version >= WAIT_CERTIFICATE_BLOCK_VERSION {
+            Option::<WaitCertificate>::decode(decoder)?
+        } else {
+            None
+        };
+
+        Ok(Self {
+            version,
+            prev_hash,
+            merkle_root,
+            state_root,
+            timestamp,
+            height,
+            validator,
+            wait_certificate,
+            signature: SignatureBytes::decode(decoder)?,
+        })
+    }
 }
 
+bincode::impl_borrow_decode!(BlockHeader);
+
 /// A full block in the Blocana blockchain
 #[derive(Clone, Debug, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
 pub struct Block {
@@ -129,7 +262,7 @@ impl Block {
         let merkle_root = compute_merkle_root(&transactions)?;
         
         let header = BlockHeader::new(
-            1, // Current version
+            BLOCK_VERSION,
             prev_hash,
             merkle_root,
             height,
@@ -152,7 +285,17 @@ impl Block {
     }
     
     /// Validate the block structure and signatures
+    ///
+    /// The state root can only be checked against the parent state, which is
+    /// done when the block is applied.
     pub fn validate(&self) -> Result<(), crate::Error> {
+        if self.header.version == 0 || self.header.version > BLOCK_VERSION {
+            return Err(crate::Error::Validation(format!(
+                "Unsupported block version {}",
+                self.header.version
+            )));
+        }
+        
         // Verify merkle root matches transactions
         let computed_root = compute_merkle_root(&self.transactions)?;
         if computed_root != self.header.merkle_root {
@@ -170,6 +313,15 @@ impl Block {
         Ok(())
     }
     
+    /// Build the inclusion proof for the transaction at `index`
+    ///
+    /// # Returns
+    /// The proof, or `None` if the block has no transaction at `index`
+    pub fn transaction_proof(&self, index: usize) -> Option<MerkleProof> {
+        let hashes: Vec<Hash> = self.transactions.iter().map(|tx| tx.hash()).collect();
+        crate::crypto::compute_merkle_proof(&hashes, index)
+    }
+    
     /// Get the serialized size of this block in bytes
     pub fn serialized_size(&self) -> usize {
         use bincode::config::standard;
@@ -183,6 +335,148 @@ impl Block {
     }
 }
 
+/// What happened to a block accepted by [`crate::Blockchain::import_block`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockImportOutcome {
+    /// The block extended the main chain
+    Extended,
+    /// The block was stored on a side chain that is not heavier than the main chain
+    SideChain,
+    /// The block's branch became the main chain
+    Reorganized {
+        /// Number of blocks that left the main chain
+        disconnected: usize,
+        /// Number of blocks that joined the main chain
+        connected: usize,
+    },
+}
+
+/// Reasons a block can be refused by [`crate::Blockchain::import_block`]
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum BlockImportError {
+    /// The block is already stored
+    #[error("Block {} is already known", hex::encode(.hash))]
+    AlreadyKnown {
+        /// Hash of the block
+        hash: Hash,
+    },
+
+    /// The parent block is not stored
+    #[error("Unknown parent block {}", hex::encode(.prev_hash))]
+    UnknownParent {
+        /// Hash of the missing parent
+        prev_hash: Hash,
+    },
+
+    /// The parent block, or one of its ancestors, failed to apply
+    #[error("Parent block {} is invalid", hex::encode(.prev_hash))]
+    InvalidParent {
+        /// Hash of the invalid parent
+        prev_hash: Hash,
+    },
+
+    /// The block height does not follow its parent
+    #[error("Invalid block height: expected {expected}, got {actual}")]
+    InvalidHeight {
+        /// Height following the parent
+        expected: u64,
+        /// Height in the block header
+        actual: u64,
+    },
+
+    /// The block is older than its parent
+    #[error("Block timestamp {timestamp} is before parent timestamp {parent_timestamp}")]
+    TimestampBeforeParent {
+        /// Timestamp in the block header
+        timestamp: u64,
+        /// Timestamp of the parent block
+        parent_timestamp: u64,
+    },
+
+    /// The block claims to come from too far in the future
+    #[error("Block timestamp {timestamp} is too far ahead of local time {now}")]
+    TimestampInFuture {
+        /// Timestamp in the block header
+        timestamp: u64,
+        /// Local time when the block was checked
+        now: u64,
+    },
+
+    /// The block exceeds the configured size limit
+    #[error("Block too large: {size} bytes, maximum is {max_size}")]
+    TooLarge {
+        /// Serialized block size
+        size: usize,
+        /// Maximum allowed size
+        max_size: usize,
+    },
+
+    /// The block holds more transactions than allowed
+    #[error("Too many transactions: {count}, maximum is {max_count}")]
+    TooManyTransactions {
+        /// Number of transactions in the block
+        count: usize,
+        /// Maximum allowed transactions
+        max_count: usize,
+    },
+
+    /// The block failed structural or consensus validation
+    #[error("Invalid block: {0}")]
+    Invalid(String),
+
+    /// A transaction does not apply cleanly to the parent state
+    #[error("Invalid transaction at index {index}: {reason}")]
+    InvalidTransaction {
+        /// Position of the transaction in the block
+        index: usize,
+        /// Why the transaction was refused
+        reason: String,
+    },
+
+    /// The account state after applying the block differs from the header
+    #[error("State root mismatch: header has {}, computed {}", hex::encode(.expected), hex::encode(.actual))]
+    StateRootMismatch {
+        /// State root committed in the block header
+        expected: Hash,
+        /// State root computed after applying the block
+        actual: Hash,
+    },
+
+    /// The block could not be read or written
+    #[error("Storage error: {0}")]
+    Storage(String),
+}
+
+impl BlockImportError {
+    /// Checks if the block was refused because of its content rather than a local failure
+    ///
+    /// Blocks refused for local reasons (storage failures, duplicates, missing
+    /// parents) say nothing about the peer that sent them.
+    pub fn is_invalid_block(&self) -> bool {
+        !matches!(
+            self,
+            BlockImportError::AlreadyKnown { .. }
+                | BlockImportError::UnknownParent { .. }
+                | BlockImportError::Storage(_)
+        )
+    }
+}
+
+impl From<crate::storage::Error> for BlockImportError {
+    fn from(err: crate::storage::Error) -> Self {
+        BlockImportError::Storage(err.to_string())
+    }
+}
+
+impl From<BlockImportError> for crate::Error {
+    fn from(err: BlockImportError) -> Self {
+        match err {
+            BlockImportError::Storage(msg) => crate::Error::DB(msg),
+            _ => crate::Error::Validation(err.to_string()),
+        }
+    }
+}
+
 /// Compute the Merkle root from a list of transactions
 pub fn compute_merkle_root(transactions: &[Transaction]) -> Result<Hash, crate::Error> {
     if transactions.is_empty() {
@@ -233,7 +527,7 @@ mod tests {
         assert!(block.is_ok());
         let block = block.unwrap();
         
-        assert_eq!(block.header.version, 1);
+        assert_eq!(block.header.version, BLOCK_VERSION);
         assert_eq!(block.header.height, 1);
         assert_eq!(block.header.validator, validator);
         assert_eq!(block.transactions.len(), 1);
@@ -249,7 +543,7 @@ mod tests {
         assert!(genesis.is_ok());
         let genesis = genesis.unwrap();
         
-        assert_eq!(genesis.header.version, 1);
+        assert_eq!(genesis.header.version, BLOCK_VERSION);
         assert_eq!(genesis.header.height, 0);
         assert_eq!(genesis.header.prev_hash, [0u8; 32]);
         assert_eq!(genesis.transactions.len(), 1);
@@ -269,4 +563,79 @@ mod tests {
         // Hash should not be all zeros
         assert_ne!(hash, [0u8; 32]);
     }
+    
+    #[test]
+    fn test_state_root_is_covered_by_hash() {
+        let mut block = Block::new([0u8; 32], 1, vec![], [5u8; 32]).unwrap();
+        let hash = block.header.hash();
+        
+        block.header.state_root = [9u8; 32];
+        assert_ne!(block.header.hash(), hash);
+    }
+    
+    #[test]
+    fn test_header_encoding_follows_version() {
+        use bincode::config::standard;
+        
+        let mut header = BlockHeader::new(STATE_ROOT_BLOCK_VERSION, [1u8; 32], [2u8; 32], 3, [4u8; 32]);
+        header.state_root = [5u8; 32];
+        let encoded = bincode::encode_to_vec(&header, standard()).unwrap();
+        let (decoded, _): (BlockHeader, usize) = bincode::decode_from_slice(&encoded, standard()).unwrap();
+        assert_eq!(decoded.state_root, [5u8; 32]);
+        assert_eq!(decoded.hash(), header.hash());
+        
+        // Version 1 headers carry no state root on disk or in their hash
+        let mut legacy = BlockHeader::new(1, [1u8; 32], [2u8; 32], 3, [4u8; 32]);
+        let legacy_encoded = bincode::encode_to_vec(&legacy, standard()).unwrap();
+        assert_eq!(legacy_encoded.len() + 32, encoded.len());
+        let legacy_hash = legacy.hash();
+        legacy.state_root = [5u8; 32];
+        assert_eq!(legacy.hash(), legacy_hash);
+        
+        let (decoded, _): (BlockHeader, usize) = bincode::decode_from_slice(&legacy_encoded, standard()).unwrap();
+        assert_eq!(decoded.version, 1);
+        assert_eq!(decoded.state_root, [0u8; 32]);
+    }
+    
+    #[test]
+    fn test_wait_certificate_follows_version() {
+        use bincode::config::standard;
+        
+        let mut header = BlockHeader::new(BLOCK_VERSION, [1u8; 32], [2u8; 32], 3, [4u8; 32]);
+        let unproven_hash = header.hash();
+        header.wait_certificate = Some(WaitCertificate { proof: [6u8; 64], wait_ms: 250 });
+        assert_ne!(header.hash(), unproven_hash);
+        
+        let encoded = bincode::encode_to_vec(&header, standard()).unwrap();
+        let (decoded, _): (BlockHeader, usize) = bincode::decode_from_slice(&encoded, standard()).unwrap();
+        assert_eq!(decoded.wait_certificate, header.wait_certificate);
+        assert_eq!(decoded.hash(), header.hash());
+        
+        // Older headers have no room for a certificate
+        let mut legacy = BlockHeader::new(STATE_ROOT_BLOCK_VERSION, [1u8; 32], [2u8; 32], 3, [4u8; 32]);
+        let legacy_hash = legacy.hash();
+        legacy.wait_certificate = header.wait_certificate.clone();
+        assert_eq!(legacy.hash(), legacy_hash);
+        let legacy_encoded = bincode::encode_to_vec(&legacy, standard()).unwrap();
+        let (decoded, _): (BlockHeader, usize) = bincode::decode_from_slice(&legacy_encoded, standard()).unwrap();
+        assert_eq!(decoded.wait_certificate, None);
+    }
+    
+    #[test]
+    fn test_transaction_proof() {
+        let transactions: Vec<Transaction> = (0..3)
+            .map(|nonce| Transaction::new([1u8; 32], [2u8; 32], 100, 5, nonce, vec![]))
+            .collect();
+        let block = Block::new([0u8; 32], 1, transactions, [5u8; 32]).unwrap();
+        
+        for (index, tx) in block.transactions.iter().enumerate() {
+            let proof = block.transaction_proof(index).unwrap();
+            assert!(block.header.verify_transaction_proof(tx, &proof).is_ok());
+        }
+        
+        // A proof for one transaction does not prove another
+        let proof = block.transaction_proof(0).unwrap();
+        assert!(block.header.verify_transaction_proof(&block.transactions[1], &proof).is_err());
+        assert!(block.transaction_proof(3).is_none());
+    }
 }
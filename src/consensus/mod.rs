@@ -1,19 +1,34 @@
 //! Consensus mechanisms for Blocana blockchain
 //!
-//! This module contains the consensus interface and implementations.
+//! This module contains the consensus interface and its implementations,
+//! Proof of Elapsed Time and Proof of Authority, [`new_consensus`] to build
+//! the one a configuration selects, the
+//! [`BlockProducer`] that builds blocks whenever the consensus engine says
+//! it is this node's turn, and the [`EquivocationDetector`] that catches
+//! validators signing two blocks at one height.
 
+mod poa;
 mod poet;
+mod producer;
+mod slashing;
 
+pub use poa::PoAConsensus;
 pub use poet::PoETConsensus;
-use crate::block::Block;
+pub use producer::BlockProducer;
+pub use slashing::{EquivocationDetector, EquivocationEvidence};
+use crate::block::{Block, BlockHeader};
+use crate::state::BlockchainState;
 use crate::storage::BlockchainStorage;
 use crate::transaction::Transaction;
+use crate::types::{PrivateKeyBytes, PublicKeyBytes};
 
 /// Supported consensus algorithms
 #[derive(Debug, Clone, Copy)]
 pub enum ConsensusAlgorithm {
     /// Proof of Elapsed Time
     PoET,
+    /// Proof of Authority, with the configured authorities taking turns
+    PoA,
 }
 
 /// Configuration for consensus mechanisms
@@ -27,6 +42,10 @@ pub struct ConsensusConfig {
     pub max_validators: u32,
     /// Minimum stake amount (if applicable)
     pub min_stake: u64,
+    /// Number of blocks per validator epoch, 0 to never change the validator set
+    pub epoch_length: u64,
+    /// Authorities taking turns producing blocks, in slot order (PoA only)
+    pub authorities: Vec<PublicKeyBytes>,
 }
 
 impl Default for ConsensusConfig {
@@ -36,6 +55,8 @@ impl Default for ConsensusConfig {
             target_block_time_ms: 500,
             max_validators: 100,
             min_stake: 1000,
+            epoch_length: 100,
+            authorities: Vec::new(),
         }
     }
 }
@@ -70,15 +91,79 @@ pub trait Consensus: Send + Sync {
     /// Stop the consensus process
     fn stop(&mut self) -> Result<(), Error>;
     
-    /// Generate a new block with the given transactions
-    fn generate_block(&self, txs: Vec<Transaction>, previous_hash: [u8; 32], height: u64) -> Result<Block, Error>;
+    /// Generate a new block on top of `parent` with the given transactions
+    ///
+    /// `state_root` is the account state root after applying `txs` on top of
+    /// the parent block, and is committed in the signed header.
+    fn generate_block(&self, txs: Vec<Transaction>, parent: &BlockHeader, state_root: [u8; 32]) -> Result<Block, Error>;
     
     /// Validate a block according to consensus rules
-    fn validate_block(&self, block: &Block) -> Result<(), Error>;
+    ///
+    /// `parent` is the header of the block's parent, which the caller has
+    /// already looked up.
+    fn validate_block(&self, block: &Block, parent: &BlockHeader) -> Result<(), Error>;
+    
+    /// Check that `validator` may produce a block on top of `state`
+    ///
+    /// `state` is the account state after the parent block.
+    fn check_validator(&self, validator: &PublicKeyBytes, state: &BlockchainState) -> Result<(), Error>;
     
     /// Check if consensus is currently running
     fn is_running(&self) -> bool;
     
-    /// Check if this node should produce a block now
-    fn should_produce_block(&self) -> bool;
+    /// Check if this node should produce a block on top of `parent` now
+    fn should_produce_block(&self, parent: &BlockHeader) -> bool;
+}
+
+/// Create the consensus engine selected by `config`
+///
+/// # Parameters
+/// * `config` - Consensus configuration
+/// * `validator_key` - Public key identifying this validator in block headers
+/// * `signing_key` - Private key used to sign produced blocks
+pub fn new_consensus(
+    config: &ConsensusConfig,
+    validator_key: PublicKeyBytes,
+    signing_key: PrivateKeyBytes,
+) -> Result<Box<dyn Consensus>, Error> {
+    Ok(match config.algorithm {
+        ConsensusAlgorithm::PoET => Box::new(PoETConsensus::with_keys(config, validator_key, signing_key)?),
+        ConsensusAlgorithm::PoA => Box::new(PoAConsensus::with_keys(config, validator_key, signing_key)?),
+    })
+}
+
+/// Check a validator against the staked validator set of `state`
+///
+/// The validator must belong to the active set and still have at least
+/// `min_stake` bonded, so unstaking takes effect right away. An empty set
+/// leaves block production open to anyone.
+pub fn check_staked_validator(
+    config: &ConsensusConfig,
+    state: &BlockchainState,
+    validator: &PublicKeyBytes,
+) -> Result<(), Error> {
+    let validators = state.validator_set();
+    if validators.is_empty() {
+        return Ok(());
+    }
+
+    if !validators.contains(validator) {
+        return Err(Error::BlockValidation(format!(
+            "Validator {} is not in the active set of epoch {}",
+            hex::encode(validator),
+            validators.epoch
+        )));
+    }
+
+    let stake = state.accounts.get(validator).map_or(0, |account| account.stake);
+    if stake < config.min_stake {
+        return Err(Error::BlockValidation(format!(
+            "Validator {} has {} staked, minimum is {}",
+            hex::encode(validator),
+            stake,
+            config.min_stake
+        )));
+    }
+
+    Ok(())
 }
@@ -0,0 +1,290 @@
+//! Proof of Authority (PoA) consensus implementation
+//!
+//! A fixed list of authorities takes turns producing blocks. Time is cut
+//! into slots of the target block time, counted from the UNIX epoch, and
+//! the leader of a slot is the authority at the slot number modulo the
+//! number of authorities. A block is only valid if its timestamp falls into
+//! a slot led by its validator and after its parent's slot, so every slot
+//! holds at most one block per branch. An authority that is offline simply
+//! leaves its slots empty.
+
+use super::{Consensus, ConsensusConfig, Error};
+use crate::block::{Block, BlockHeader};
+use crate::state::BlockchainState;
+use crate::storage::BlockchainStorage;
+use crate::transaction::Transaction;
+use crate::types::{PrivateKeyBytes, PublicKeyBytes};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// PoA consensus implementation
+pub struct PoAConsensus {
+    /// Configuration for the consensus mechanism
+    config: ConsensusConfig,
+    /// Validator's identity key
+    validator_key: PublicKeyBytes,
+    /// Validator's signing key
+    signing_key: PrivateKeyBytes,
+    /// Is consensus running
+    running: bool,
+}
+
+impl PoAConsensus {
+    /// Create a PoA consensus instance that signs blocks with the given validator keys
+    ///
+    /// The node does not have to be one of the authorities; it then only
+    /// validates blocks.
+    ///
+    /// # Parameters
+    /// * `config` - Consensus configuration, with the authorities in their turn order
+    /// * `validator_key` - Public key identifying this validator in block headers
+    /// * `signing_key` - Private key used to sign produced blocks
+    pub fn with_keys(
+        config: &ConsensusConfig,
+        validator_key: PublicKeyBytes,
+        signing_key: PrivateKeyBytes,
+    ) -> Result<Self, Error> {
+        if config.authorities.is_empty() {
+            return Err(Error::Initialization("PoA needs at least one authority".into()));
+        }
+        if config.target_block_time_ms == 0 {
+            return Err(Error::Initialization("PoA needs a target block time to cut slots".into()));
+        }
+
+        Ok(Self {
+            config: config.clone(),
+            validator_key,
+            signing_key,
+            running: false,
+        })
+    }
+
+    /// Get the slot a timestamp falls into
+    pub fn slot_at(&self, timestamp: u64) -> u64 {
+        timestamp / self.config.target_block_time_ms
+    }
+
+    /// Get the authority leading a slot
+    pub fn slot_leader(&self, slot: u64) -> PublicKeyBytes {
+        let index = slot % self.config.authorities.len() as u64;
+        self.config.authorities[index as usize]
+    }
+
+    /// Get the first slot after `parent` that this node leads
+    ///
+    /// Returns `None` if the node is not an authority.
+    pub fn next_own_slot(&self, parent: &BlockHeader) -> Option<u64> {
+        let first = self.slot_at(parent.timestamp) + 1;
+        let rounds = self.config.authorities.len() as u64;
+        (first..first + rounds).find(|slot| self.slot_leader(*slot) == self.validator_key)
+    }
+}
+
+/// Get the current time in milliseconds since the UNIX epoch
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl Consensus for PoAConsensus {
+    fn initialize(&mut self, _storage: &BlockchainStorage) -> Result<(), Error> {
+        // The schedule only depends on the configuration and the clock
+        Ok(())
+    }
+
+    fn start(&mut self) -> Result<(), Error> {
+        if self.running {
+            return Err(Error::AlreadyRunning);
+        }
+
+        self.running = true;
+
+        Ok(())
+    }
+
+    fn stop(&mut self) -> Result<(), Error> {
+        if !self.running {
+            return Err(Error::NotRunning);
+        }
+
+        self.running = false;
+
+        Ok(())
+    }
+
+    fn generate_block(&self, txs: Vec<Transaction>, parent: &BlockHeader, state_root: [u8; 32]) -> Result<Block, Error> {
+        let mut block = Block::new(
+            parent.hash(),
+            parent.height + 1,
+            txs,
+            self.validator_key,
+        ).map_err(|e| Error::BlockCreation(format!("{:?}", e)))?;
+        block.header.state_root = state_root;
+
+        // Date the block into a slot this node leads; a block built ahead of
+        // that slot carries a future timestamp, which peers eventually refuse
+        let now_slot = self.slot_at(block.header.timestamp);
+        let mut slot = self.next_own_slot(parent).ok_or_else(|| {
+            Error::BlockCreation(format!(
+                "{} is not an authority",
+                hex::encode(self.validator_key)
+            ))
+        })?;
+        if slot < now_slot {
+            let rounds = self.config.authorities.len() as u64;
+            slot += (now_slot - slot).div_ceil(rounds) * rounds;
+        }
+        if slot != now_slot {
+            block.header.timestamp = slot * self.config.target_block_time_ms;
+        }
+
+        block.header.sign(&self.signing_key)
+            .map_err(|e| Error::BlockSigning(format!("{:?}", e)))?;
+
+        Ok(block)
+    }
+
+    fn validate_block(&self, block: &Block, parent: &BlockHeader) -> Result<(), Error> {
+        block.validate()
+            .map_err(|e| Error::BlockValidation(format!("{:?}", e)))?;
+
+        let slot = self.slot_at(block.header.timestamp);
+        let parent_slot = self.slot_at(parent.timestamp);
+        if slot <= parent_slot {
+            return Err(Error::BlockValidation(format!(
+                "Block is in slot {}, not after its parent's slot {}",
+                slot, parent_slot
+            )));
+        }
+
+        let leader = self.slot_leader(slot);
+        if block.header.validator != leader {
+            return Err(Error::BlockValidation(format!(
+                "Slot {} is led by {}, not by {}",
+                slot,
+                hex::encode(leader),
+                hex::encode(block.header.validator)
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn check_validator(&self, validator: &PublicKeyBytes, _state: &BlockchainState) -> Result<(), Error> {
+        // Authorities are fixed by configuration, stake plays no part
+        if !self.config.authorities.contains(validator) {
+            return Err(Error::BlockValidation(format!(
+                "{} is not an authority",
+                hex::encode(validator)
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn is_running(&self) -> bool {
+        self.running
+    }
+
+    fn should_produce_block(&self, parent: &BlockHeader) -> bool {
+        let slot = self.slot_at(now_ms());
+        slot > self.slot_at(parent.timestamp) && self.slot_leader(slot) == self.validator_key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BLOCK_VERSION;
+    use crate::crypto::KeyPair;
+
+    fn authorities(count: usize) -> Vec<KeyPair> {
+        (0..count).map(|_| KeyPair::generate().unwrap()).collect()
+    }
+
+    fn config(authorities: &[KeyPair]) -> ConsensusConfig {
+        ConsensusConfig {
+            algorithm: super::super::ConsensusAlgorithm::PoA,
+            authorities: authorities.iter().map(|keys| keys.public_key).collect(),
+            ..ConsensusConfig::default()
+        }
+    }
+
+    fn consensus(config: &ConsensusConfig, keys: &KeyPair) -> PoAConsensus {
+        PoAConsensus::with_keys(config, keys.public_key, keys.private_key).unwrap()
+    }
+
+    fn parent(timestamp: u64) -> BlockHeader {
+        let mut parent = BlockHeader::new(BLOCK_VERSION, [1u8; 32], [0u8; 32], 7, [2u8; 32]);
+        parent.timestamp = timestamp;
+        parent
+    }
+
+    #[test]
+    fn test_leaders_rotate_by_slot() {
+        let keys = authorities(3);
+        let config = config(&keys);
+        let poa = consensus(&config, &keys[0]);
+        let slot_ms = config.target_block_time_ms;
+
+        assert_eq!(poa.slot_at(slot_ms * 4 + 1), 4);
+        for slot in 0..6 {
+            assert_eq!(poa.slot_leader(slot), keys[slot as usize % 3].public_key);
+        }
+        // After a parent in slot 4 the first own slot is 6
+        assert_eq!(poa.next_own_slot(&parent(slot_ms * 4)), Some(6));
+
+        let outsider = KeyPair::generate().unwrap();
+        assert_eq!(consensus(&config, &outsider).next_own_slot(&parent(0)), None);
+        assert!(PoAConsensus::with_keys(&ConsensusConfig::default(), outsider.public_key, outsider.private_key).is_err());
+    }
+
+    #[test]
+    fn test_generated_blocks_land_in_own_slots() {
+        let keys = authorities(3);
+        let config = config(&keys);
+        let parent = parent(now_ms());
+
+        for authority in &keys {
+            let poa = consensus(&config, authority);
+            let block = poa.generate_block(vec![], &parent, [0u8; 32]).unwrap();
+            assert_eq!(poa.slot_leader(poa.slot_at(block.header.timestamp)), authority.public_key);
+            assert!(block.header.timestamp >= parent.timestamp);
+
+            // Every node agrees on the schedule
+            consensus(&config, &keys[0]).validate_block(&block, &parent).unwrap();
+        }
+
+        let outsider = KeyPair::generate().unwrap();
+        assert!(consensus(&config, &outsider).generate_block(vec![], &parent, [0u8; 32]).is_err());
+    }
+
+    #[test]
+    fn test_schedule_violations_are_rejected() {
+        let keys = authorities(2);
+        let config = config(&keys);
+        let slot_ms = config.target_block_time_ms;
+        let parent = parent(slot_ms * 10);
+        let verifier = consensus(&config, &keys[0]);
+
+        // Slot 11 belongs to the second authority
+        let mut block = Block::new(parent.hash(), parent.height + 1, vec![], keys[0].public_key).unwrap();
+        block.header.timestamp = slot_ms * 11;
+        block.header.sign(&keys[0].private_key).unwrap();
+        assert!(verifier.validate_block(&block, &parent).is_err());
+
+        // The parent's own slot is taken
+        block.header.timestamp = slot_ms * 10 + 1;
+        block.header.sign(&keys[0].private_key).unwrap();
+        assert!(verifier.validate_block(&block, &parent).is_err());
+
+        block.header.timestamp = slot_ms * 12;
+        block.header.sign(&keys[0].private_key).unwrap();
+        verifier.validate_block(&block, &parent).unwrap();
+
+        let state = BlockchainState::new();
+        assert!(verifier.check_validator(&keys[1].public_key, &state).is_ok());
+        assert!(verifier.check_validator(&[9u8; 32], &state).is_err());
+    }
+}
@@ -1,120 +1,314 @@
 //! Proof of Elapsed Time (PoET) consensus implementation
 //!
 //! A lightweight consensus mechanism optimized for performance and fairness
+//!
+//! Every validator waits a while before building on a block, and whoever's
+//! wait runs out first produces the next one. The wait is not drawn from a
+//! local random source but derived from the validator's signature over the
+//! parent block hash. Ed25519 signatures are deterministic, so the wait is
+//! fixed by the parent and the validator key, stays unknown to others until
+//! the validator publishes its signature, and can be checked by anyone once
+//! it does. The signature and the wait travel in the block header as a
+//! [`WaitCertificate`], and a block is only valid if its timestamp lies at
+//! least the claimed wait after its parent's.
+//!
+//! Signature verification cannot tell whether a signer used the
+//! deterministic nonce, so a validator grinding through alternative
+//! signatures could pick a shorter wait. A proper VRF would close that gap.
 
-use super::{Consensus, ConsensusConfig, Error};
-use crate::block::Block;
+use super::{check_staked_validator, Consensus, ConsensusConfig, Error};
+use crate::block::{Block, BlockHeader, WaitCertificate};
+use crate::crypto::{self, KeyPair};
+use crate::state::BlockchainState;
 use crate::storage::BlockchainStorage;
 use crate::transaction::Transaction;
-use std::time::{Duration, Instant};
-use rand::{Rng, thread_rng};
+use crate::types::{Hash, PrivateKeyBytes, PublicKeyBytes, SignatureBytes};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Domain separator of the message signed for a wait certificate
+const WAIT_DOMAIN: &[u8] = b"blocana-poet-wait";
 
 /// PoET consensus implementation
 pub struct PoETConsensus {
     /// Configuration for the consensus mechanism
     config: ConsensusConfig,
     /// Validator's identity key
-    validator_key: [u8; 32],
+    validator_key: PublicKeyBytes,
     /// Validator's signing key
-    signing_key: [u8; 32],
-    /// Current wait time
-    current_wait: Duration,
-    /// Last block timestamp
-    last_block_time: Instant,
+    signing_key: PrivateKeyBytes,
     /// Is consensus running
     running: bool,
 }
 
 impl PoETConsensus {
-    /// Create a new PoET consensus instance
+    /// Create a new PoET consensus instance with a freshly generated validator key
     pub fn new(config: &ConsensusConfig) -> Result<Self, Error> {
-        // In a real implementation, we would load keys from secure storage
-        // For this example, we'll just use placeholder values
-        let validator_key = [0u8; 32];
-        let signing_key = [0u8; 32];
-        
+        let keys = KeyPair::generate()
+            .map_err(|e| Error::Initialization(format!("{:?}", e)))?;
+        Self::with_keys(config, keys.public_key, keys.private_key)
+    }
+
+    /// Create a PoET consensus instance that signs blocks with the given validator keys
+    ///
+    /// # Parameters
+    /// * `config` - Consensus configuration
+    /// * `validator_key` - Public key identifying this validator in block headers
+    /// * `signing_key` - Private key used to sign produced blocks
+    pub fn with_keys(
+        config: &ConsensusConfig,
+        validator_key: PublicKeyBytes,
+        signing_key: PrivateKeyBytes,
+    ) -> Result<Self, Error> {
         Ok(Self {
             config: config.clone(),
             validator_key,
             signing_key,
-            current_wait: Duration::from_millis(0),
-            last_block_time: Instant::now(),
             running: false,
         })
     }
-    
-    /// Generate a fair random wait time
-    fn generate_wait_time(&self) -> Duration {
-        let mut rng = thread_rng();
-        // Generate random wait time between 0 and 2x target block time
-        let wait_ms = rng.gen_range(0..self.config.target_block_time_ms * 2);
-        Duration::from_millis(wait_ms)
+
+    /// Issue this validator's wait certificate for building on the given parent
+    pub fn wait_certificate(&self, parent_hash: &Hash) -> Result<WaitCertificate, Error> {
+        let proof = crypto::sign_message(&self.signing_key, &wait_message(parent_hash))
+            .map_err(|e| Error::BlockSigning(format!("{:?}", e)))?;
+
+        Ok(WaitCertificate {
+            proof,
+            wait_ms: self.wait_from_proof(&proof),
+        })
+    }
+
+    /// Get the time at which this validator's wait on `parent` ends
+    ///
+    /// # Returns
+    /// Milliseconds since the UNIX epoch
+    pub fn eligible_at(&self, parent: &BlockHeader) -> Result<u64, Error> {
+        let certificate = self.wait_certificate(&parent.hash())?;
+        Ok(parent.timestamp.saturating_add(certificate.wait_ms))
+    }
+
+    /// Check a header's wait certificate against its parent
+    ///
+    /// The certificate must be signed by the header's validator for the
+    /// parent, claim exactly the wait its proof yields, and the header must
+    /// not be timestamped before that wait ran out.
+    pub fn verify_wait(&self, header: &BlockHeader, parent: &BlockHeader) -> Result<(), Error> {
+        if !header.has_wait_certificate() {
+            return Err(Error::BlockValidation(format!(
+                "Block version {} carries no wait certificate",
+                header.version
+            )));
+        }
+        let certificate = header
+            .wait_certificate
+            .as_ref()
+            .ok_or_else(|| Error::BlockValidation("Missing wait certificate".into()))?;
+
+        crypto::verify_signature(&header.validator, &certificate.proof, &wait_message(&parent.hash()))
+            .map_err(|_| Error::BlockValidation(
+                "Wait certificate is not the validator's proof for the parent block".into()
+            ))?;
+
+        let wait_ms = self.wait_from_proof(&certificate.proof);
+        if certificate.wait_ms != wait_ms {
+            return Err(Error::BlockValidation(format!(
+                "Claimed wait of {}ms does not match the proven wait of {}ms",
+                certificate.wait_ms, wait_ms
+            )));
+        }
+
+        let wait_end = parent.timestamp.saturating_add(wait_ms);
+        if header.timestamp < wait_end {
+            return Err(Error::BlockValidation(format!(
+                "Block timestamp {} is before the end of its wait at {}",
+                header.timestamp, wait_end
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Derive the wait from a certificate proof
+    ///
+    /// Waits are spread evenly between zero and twice the target block time.
+    fn wait_from_proof(&self, proof: &SignatureBytes) -> u64 {
+        let range = self.config.target_block_time_ms.saturating_mul(2);
+        if range == 0 {
+            return 0;
+        }
+
+        let digest = crypto::hash_data(proof);
+        let mut value = [0u8; 8];
+        value.copy_from_slice(&digest[..8]);
+        u64::from_le_bytes(value) % range
     }
 }
 
+/// Build the message a validator signs to prove its wait on a parent block
+fn wait_message(parent_hash: &Hash) -> Vec<u8> {
+    [WAIT_DOMAIN, parent_hash.as_slice()].concat()
+}
+
+/// Get the current time in milliseconds since the UNIX epoch
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 impl Consensus for PoETConsensus {
     fn initialize(&mut self, _storage: &BlockchainStorage) -> Result<(), Error> {
-        // Set up initial wait time
-        self.current_wait = self.generate_wait_time();
-        self.last_block_time = Instant::now();
-        
+        // Waits are derived from the chain itself, so there is nothing to restore
         Ok(())
     }
-    
+
     fn start(&mut self) -> Result<(), Error> {
         if self.running {
             return Err(Error::AlreadyRunning);
         }
-        
+
         self.running = true;
         // In a real implementation, we would start a consensus thread here
-        
+
         Ok(())
     }
-    
+
     fn stop(&mut self) -> Result<(), Error> {
         if !self.running {
             return Err(Error::NotRunning);
         }
-        
+
         self.running = false;
-        
+
         Ok(())
     }
-    
-    fn generate_block(&self, txs: Vec<Transaction>, previous_hash: [u8; 32], height: u64) -> Result<Block, Error> {
+
+    fn generate_block(&self, txs: Vec<Transaction>, parent: &BlockHeader, state_root: [u8; 32]) -> Result<Block, Error> {
         // Create a new block with the transactions
         let mut block = Block::new(
-            previous_hash,
-            height,
+            parent.hash(),
+            parent.height + 1,
             txs,
             self.validator_key,
         ).map_err(|e| Error::BlockCreation(format!("{:?}", e)))?;
-        
+        block.header.state_root = state_root;
+
+        // A block built before the wait ran out is dated at its end; peers
+        // refuse it if that lies too far in the future
+        let certificate = self.wait_certificate(&block.header.prev_hash)?;
+        let wait_end = parent.timestamp.saturating_add(certificate.wait_ms);
+        block.header.timestamp = block.header.timestamp.max(wait_end);
+        block.header.wait_certificate = Some(certificate);
+
         // Sign the block header
         block.header.sign(&self.signing_key)
             .map_err(|e| Error::BlockSigning(format!("{:?}", e)))?;
-        
+
         Ok(block)
     }
-    
-    fn validate_block(&self, block: &Block) -> Result<(), Error> {
+
+    fn validate_block(&self, block: &Block, parent: &BlockHeader) -> Result<(), Error> {
         // Validate block structure and signatures
         block.validate()
             .map_err(|e| Error::BlockValidation(format!("{:?}", e)))?;
-        
-        // In PoET, we would verify the validator's wait time certificate here
-        // This is a simplified version
-        
-        Ok(())
+
+        self.verify_wait(&block.header, parent)
     }
-    
+
+    fn check_validator(&self, validator: &PublicKeyBytes, state: &BlockchainState) -> Result<(), Error> {
+        check_staked_validator(&self.config, state, validator)
+    }
+
     fn is_running(&self) -> bool {
         self.running
     }
-    
-    fn should_produce_block(&self) -> bool {
+
+    fn should_produce_block(&self, parent: &BlockHeader) -> bool {
         // Check if we've waited long enough
-        self.last_block_time.elapsed() >= self.current_wait
+        self.eligible_at(parent)
+            .map(|wait_end| now_ms() >= wait_end)
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BLOCK_VERSION;
+
+    fn consensus(keys: &KeyPair) -> PoETConsensus {
+        PoETConsensus::with_keys(&ConsensusConfig::default(), keys.public_key, keys.private_key).unwrap()
+    }
+
+    fn parent() -> BlockHeader {
+        let mut parent = BlockHeader::new(BLOCK_VERSION, [1u8; 32], [0u8; 32], 7, [2u8; 32]);
+        parent.timestamp = 1_000_000;
+        parent
+    }
+
+    #[test]
+    fn test_wait_is_deterministic_per_parent_and_key() {
+        let keys = KeyPair::generate().unwrap();
+        let poet = consensus(&keys);
+        let parent = parent();
+
+        let certificate = poet.wait_certificate(&parent.hash()).unwrap();
+        assert_eq!(poet.wait_certificate(&parent.hash()).unwrap(), certificate);
+        assert!(certificate.wait_ms < ConsensusConfig::default().target_block_time_ms * 2);
+        assert_eq!(poet.eligible_at(&parent).unwrap(), parent.timestamp + certificate.wait_ms);
+
+        // Another validator gets its own wait
+        let other = consensus(&KeyPair::generate().unwrap());
+        assert_ne!(other.wait_certificate(&parent.hash()).unwrap().proof, certificate.proof);
+    }
+
+    #[test]
+    fn test_generated_block_passes_wait_checks() {
+        let keys = KeyPair::generate().unwrap();
+        let poet = consensus(&keys);
+        let parent = parent();
+
+        let block = poet.generate_block(vec![], &parent, [0u8; 32]).unwrap();
+        assert_eq!(block.header.height, parent.height + 1);
+        poet.validate_block(&block, &parent).unwrap();
+
+        // Any validator checks the same certificate
+        let verifier = consensus(&KeyPair::generate().unwrap());
+        verifier.validate_block(&block, &parent).unwrap();
+    }
+
+    #[test]
+    fn test_wait_violations_are_rejected() {
+        let keys = KeyPair::generate().unwrap();
+        let poet = consensus(&keys);
+        let parent = parent();
+        let block = poet.generate_block(vec![], &parent, [0u8; 32]).unwrap();
+        let certificate = block.header.wait_certificate.clone().unwrap();
+
+        let resign = |header: &mut BlockHeader| header.sign(&keys.private_key).unwrap();
+
+        // A shorter wait than the proof yields
+        let mut impatient = block.clone();
+        impatient.header.wait_certificate.as_mut().unwrap().wait_ms = certificate.wait_ms.wrapping_sub(1);
+        resign(&mut impatient.header);
+        assert!(poet.validate_block(&impatient, &parent).is_err());
+
+        // Dated before the wait ran out
+        let mut early = block.clone();
+        early.header.timestamp = parent.timestamp + certificate.wait_ms - 1;
+        resign(&mut early.header);
+        assert!(poet.validate_block(&early, &parent).is_err());
+
+        // A certificate proven for another parent
+        let mut other_parent = parent.clone();
+        other_parent.height += 1;
+        assert!(poet.validate_block(&block, &other_parent).is_err());
+
+        // No certificate at all
+        let mut missing = block.clone();
+        missing.header.wait_certificate = None;
+        resign(&mut missing.header);
+        assert!(poet.validate_block(&missing, &parent).is_err());
     }
 }
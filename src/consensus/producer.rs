@@ -0,0 +1,140 @@
+//! Block production on the consensus schedule
+//!
+//! The producer runs on its own thread and keeps asking the chain whether
+//! the consensus engine wants a block on top of the current tip. When it
+//! does, the block is generated and handed to a callback, typically one
+//! that publishes it to the network. A block arriving from a peer moves the
+//! tip and with it the schedule, so the producer simply waits its turn on
+//! the new tip.
+
+use super::Error;
+use crate::block::Block;
+use crate::Blockchain;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Shortest interval between two looks at the schedule
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Longest interval between two looks at the schedule
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Drives block production for a chain
+pub struct BlockProducer {
+    /// Chain the blocks are produced for
+    chain: Option<Arc<Mutex<Blockchain>>>,
+    /// Dropping this ends the production thread
+    stop: Option<mpsc::Sender<()>>,
+    /// Production thread
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl BlockProducer {
+    /// Create a producer that is not running yet
+    pub fn new() -> Self {
+        Self {
+            chain: None,
+            stop: None,
+            worker: None,
+        }
+    }
+
+    /// Start the consensus engine of `chain` and produce blocks whenever it says so
+    ///
+    /// `on_block` is called with every block produced, after it was stored.
+    /// The schedule is checked at a twentieth of the target block time, so
+    /// blocks come out close to the end of the wait.
+    pub fn start<F>(&mut self, chain: Arc<Mutex<Blockchain>>, mut on_block: F) -> Result<(), Error>
+    where
+        F: FnMut(&Block) + Send + 'static,
+    {
+        if self.is_running() {
+            return Err(Error::AlreadyRunning);
+        }
+
+        let poll_interval = {
+            let mut chain = chain
+                .lock()
+                .map_err(|_| Error::Other("Blockchain lock poisoned".into()))?;
+            chain
+                .start()
+                .map_err(|e| Error::Initialization(format!("{:?}", e)))?;
+            (Duration::from_millis(chain.config.consensus_config.target_block_time_ms) / 20)
+                .clamp(MIN_POLL_INTERVAL, MAX_POLL_INTERVAL)
+        };
+
+        let (stop_tx, stop_rx) = mpsc::channel::<()>();
+        let worker_chain = chain.clone();
+        let worker = thread::Builder::new()
+            .name("blocana-producer".into())
+            .spawn(move || {
+                // Sleeps between looks until the sender is dropped
+                while let Err(RecvTimeoutError::Timeout) = stop_rx.recv_timeout(poll_interval) {
+                    let produced = {
+                        let mut chain = worker_chain.lock().unwrap();
+                        match chain.should_produce_block() {
+                            Ok(true) => Some(chain.generate_block()),
+                            Ok(false) => None,
+                            Err(e) => Some(Err(e)),
+                        }
+                    };
+                    match produced {
+                        Some(Ok(block)) => on_block(&block),
+                        Some(Err(e)) => log::warn!("Failed to produce block: {:?}", e),
+                        None => {}
+                    }
+                }
+            });
+        let worker = match worker {
+            Ok(worker) => worker,
+            Err(e) => {
+                let _ = chain.lock().map(|mut chain| chain.stop());
+                return Err(Error::Other(format!("Failed to spawn producer thread: {}", e)));
+            }
+        };
+
+        self.chain = Some(chain);
+        self.stop = Some(stop_tx);
+        self.worker = Some(worker);
+        Ok(())
+    }
+
+    /// Stop producing blocks and stop the chain's consensus engine
+    pub fn stop(&mut self) -> Result<(), Error> {
+        let Some(chain) = self.chain.take() else {
+            return Err(Error::NotRunning);
+        };
+
+        // Dropping the sender ends the production loop
+        self.stop = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+
+        let mut chain = chain
+            .lock()
+            .map_err(|_| Error::Other("Blockchain lock poisoned".into()))?;
+        chain.stop().map_err(|e| Error::Other(format!("{:?}", e)))
+    }
+
+    /// Check whether blocks are being produced
+    pub fn is_running(&self) -> bool {
+        self.chain.is_some()
+    }
+}
+
+impl Default for BlockProducer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for BlockProducer {
+    fn drop(&mut self) {
+        if self.is_running() {
+            let _ = self.stop();
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! Equivocation detection
+//!
+//! A validator equivocates when it signs two different blocks at the same
+//! height, which lets it back two branches at once. Both signed headers
+//! together prove the offence to anyone, so a node that sees them submits
+//! them as an evidence transaction. Applying the evidence slashes the
+//! offender's stake and removes it from the validator set.
+
+use super::Error;
+use crate::block::BlockHeader;
+use crate::types::{Hash, PublicKeyBytes};
+use std::collections::HashMap;
+
+/// Two signed headers from the same validator at the same height
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct EquivocationEvidence {
+    /// One of the headers
+    pub first: BlockHeader,
+    /// The other header
+    pub second: BlockHeader,
+}
+
+impl EquivocationEvidence {
+    /// Get the validator the evidence proves guilty
+    ///
+    /// Fails unless both headers are signed by the same validator, share a
+    /// height and differ.
+    pub fn offender(&self) -> Result<PublicKeyBytes, Error> {
+        if self.first.validator != self.second.validator {
+            return Err(Error::BlockValidation("Evidence headers are from different validators".into()));
+        }
+        if self.first.height != self.second.height {
+            return Err(Error::BlockValidation(format!(
+                "Evidence headers are at heights {} and {}",
+                self.first.height, self.second.height
+            )));
+        }
+        if self.first.hash() == self.second.hash() {
+            return Err(Error::BlockValidation("Evidence headers are the same block".into()));
+        }
+        for header in [&self.first, &self.second] {
+            header
+                .verify_signature()
+                .map_err(|_| Error::BlockValidation("Evidence header is not signed by its validator".into()))?;
+        }
+
+        Ok(self.first.validator)
+    }
+
+    /// Get the height the validator equivocated at
+    pub fn height(&self) -> u64 {
+        self.first.height
+    }
+}
+
+/// Watches block headers for validators signing two blocks at one height
+#[derive(Debug)]
+pub struct EquivocationDetector {
+    /// Number of heights below the highest one seen that are still watched
+    window: u64,
+    /// Highest height seen so far
+    highest: u64,
+    /// First header seen from each validator at each watched height
+    seen: HashMap<(PublicKeyBytes, u64), (Hash, BlockHeader)>,
+}
+
+impl EquivocationDetector {
+    /// Create a detector watching `window` heights below the highest one seen
+    pub fn new(window: u64) -> Self {
+        Self {
+            window,
+            highest: 0,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Record a header whose signature was checked
+    ///
+    /// # Returns
+    /// Evidence if the validator already signed another header at this height
+    pub fn observe(&mut self, header: &BlockHeader) -> Option<EquivocationEvidence> {
+        if header.height.saturating_add(self.window) < self.highest {
+            return None;
+        }
+        if header.height > self.highest {
+            self.highest = header.height;
+            let oldest = self.highest.saturating_sub(self.window);
+            self.seen.retain(|(_, height), _| *height >= oldest);
+        }
+
+        let hash = header.hash();
+        match self.seen.get(&(header.validator, header.height)) {
+            Some((first_hash, first)) if *first_hash != hash => Some(EquivocationEvidence {
+                first: first.clone(),
+                second: header.clone(),
+            }),
+            Some(_) => None,
+            None => {
+                self.seen.insert((header.validator, header.height), (hash, header.clone()));
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BLOCK_VERSION;
+    use crate::crypto::KeyPair;
+
+    fn header(keys: &KeyPair, height: u64, timestamp: u64) -> BlockHeader {
+        let mut header = BlockHeader::new(BLOCK_VERSION, [1u8; 32], [0u8; 32], height, keys.public_key);
+        header.timestamp = timestamp;
+        header.sign(&keys.private_key).unwrap();
+        header
+    }
+
+    #[test]
+    fn test_detects_two_headers_at_one_height() {
+        let keys = KeyPair::generate().unwrap();
+        let mut detector = EquivocationDetector::new(10);
+
+        let first = header(&keys, 5, 1000);
+        assert!(detector.observe(&first).is_none());
+        // Seeing the same block again is fine
+        assert!(detector.observe(&first).is_none());
+        // So is another validator at the same height
+        assert!(detector.observe(&header(&KeyPair::generate().unwrap(), 5, 1000)).is_none());
+
+        let evidence = detector.observe(&header(&keys, 5, 1001)).unwrap();
+        assert_eq!(evidence.offender().unwrap(), keys.public_key);
+        assert_eq!(evidence.height(), 5);
+
+        // Heights that fell out of the window are no longer watched
+        assert!(detector.observe(&header(&keys, 20, 1000)).is_none());
+        assert!(detector.observe(&header(&keys, 5, 1002)).is_none());
+    }
+
+    #[test]
+    fn test_invalid_evidence() {
+        let keys = KeyPair::generate().unwrap();
+        let first = header(&keys, 5, 1000);
+
+        let same = EquivocationEvidence { first: first.clone(), second: first.clone() };
+        assert!(same.offender().is_err());
+
+        let other_height = EquivocationEvidence { first: first.clone(), second: header(&keys, 6, 1000) };
+        assert!(other_height.offender().is_err());
+
+        let other_validator = EquivocationEvidence {
+            first: first.clone(),
+            second: header(&KeyPair::generate().unwrap(), 5, 1000),
+        };
+        assert!(other_validator.offender().is_err());
+
+        // A header the validator never signed
+        let mut forged = first.clone();
+        forged.timestamp += 1;
+        let unsigned = EquivocationEvidence { first, second: forged };
+        assert!(unsigned.offender().is_err());
+    }
+}
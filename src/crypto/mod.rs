@@ -237,6 +237,67 @@ pub fn compute_merkle_root(leaf_hashes: &[Hash]) -> Hash {
     hashes[0]
 }
 
+/// Proof that a leaf is part of a Merkle tree built by [`compute_merkle_root`]
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct MerkleProof {
+    /// Position of the leaf in the tree
+    pub index: u32,
+    /// Sibling hashes from the leaf level up to just below the root
+    pub siblings: Vec<Hash>,
+}
+
+/// Build the inclusion proof for the leaf at `index`
+///
+/// # Returns
+/// The proof, or `None` if `index` is out of range
+pub fn compute_merkle_proof(leaf_hashes: &[Hash], index: usize) -> Option<MerkleProof> {
+    if index >= leaf_hashes.len() || index > u32::MAX as usize {
+        return None;
+    }
+    
+    let mut siblings = Vec::new();
+    let mut hashes = leaf_hashes.to_vec();
+    let mut position = index;
+    
+    // Walk up the same levels as compute_merkle_root, remembering our sibling at each one
+    while hashes.len() > 1 {
+        if hashes.len() & 1 == 1 {
+            hashes.push(hashes[hashes.len() - 1]);
+        }
+        
+        siblings.push(hashes[position ^ 1]);
+        
+        hashes = hashes
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+        position /= 2;
+    }
+    
+    Some(MerkleProof {
+        index: index as u32,
+        siblings,
+    })
+}
+
+/// Verify that `leaf` is part of the tree with the given root
+pub fn verify_merkle_proof(leaf: &Hash, proof: &MerkleProof, root: &Hash) -> bool {
+    let mut node = *leaf;
+    let mut position = proof.index;
+    
+    for sibling in &proof.siblings {
+        node = if position & 1 == 0 {
+            hash_pair(&node, sibling)
+        } else {
+            hash_pair(sibling, &node)
+        };
+        position /= 2;
+    }
+    
+    // Leftover index bits mean the proof is too short for the claimed position
+    position == 0 && node == *root
+}
+
 /// Compute a keyed hash using HMAC-SHA256
 ///
 /// This is useful for creating authentication codes or deriving keys
@@ -432,6 +493,36 @@ mod tests {
         assert_eq!(root, expected_root);
     }
 
+    #[test]
+    fn test_merkle_proof() {
+        let leaves: Vec<Hash> = (0..5u8).map(|i| hash_data(&[i])).collect();
+        let root = compute_merkle_root(&leaves);
+        
+        // Every leaf, including the duplicated odd one, has a valid proof
+        for (index, leaf) in leaves.iter().enumerate() {
+            let proof = compute_merkle_proof(&leaves, index).unwrap();
+            assert_eq!(proof.siblings.len(), 3);
+            assert!(verify_merkle_proof(leaf, &proof, &root));
+        }
+        
+        // A proof does not verify another leaf or position
+        let proof = compute_merkle_proof(&leaves, 1).unwrap();
+        assert!(!verify_merkle_proof(&leaves[2], &proof, &root));
+        let mut moved = proof.clone();
+        moved.index = 3;
+        assert!(!verify_merkle_proof(&leaves[1], &moved, &root));
+        moved.index = 1 + 8;
+        assert!(!verify_merkle_proof(&leaves[1], &moved, &root));
+        
+        // A single leaf is its own root
+        let single = compute_merkle_proof(&leaves[..1], 0).unwrap();
+        assert!(single.siblings.is_empty());
+        assert!(verify_merkle_proof(&leaves[0], &single, &leaves[0]));
+        
+        assert!(compute_merkle_proof(&leaves, 5).is_none());
+        assert!(compute_merkle_proof(&[], 0).is_none());
+    }
+
     #[test]
     fn test_derive_child_key() {
         let master = KeyPair::generate().unwrap();
@@ -0,0 +1,350 @@
+//! Genesis configuration for Blocana networks
+//!
+//! A network's starting point is described by a JSON genesis file listing
+//! the network id, the genesis timestamp, the initial account balances, the
+//! initial validators and the consensus parameters. The genesis block is
+//! derived from that file alone, so every node loading the same file ends up
+//! with the same genesis hash.
+//!
+//! # Example
+//!
+//! ```json
+//! {
+//!   "network_id": 7,
+//!   "timestamp": 1700000000000,
+//!   "accounts": [
+//!     { "address": "<64 hex chars>", "balance": 1000000 }
+//!   ],
+//!   "validators": [
+//!     { "public_key": "<64 hex chars>", "stake": 5000 }
+//!   ],
+//!   "consensus": { "target_block_time_ms": 500 }
+//! }
+//! ```
+
+use crate::block::{Block, BlockHeader, STATE_ROOT_BLOCK_VERSION};
+use crate::state::{BlockchainState, RewardConfig, StateDiff, ValidatorSet};
+use crate::types::{Hash, PublicKeyBytes};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Description of a network's starting point
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+pub struct GenesisConfig {
+    /// Network identifier
+    pub network_id: u64,
+    /// Genesis block timestamp (ms since UNIX epoch)
+    pub timestamp: u64,
+    /// Accounts funded at genesis
+    #[serde(default)]
+    pub accounts: Vec<GenesisAccount>,
+    /// Validators allowed to produce blocks from genesis on
+    #[serde(default)]
+    pub validators: Vec<GenesisValidator>,
+    /// Consensus parameters
+    #[serde(default)]
+    pub consensus: GenesisConsensusParams,
+}
+
+/// An account funded at genesis
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+pub struct GenesisAccount {
+    /// Account address
+    #[serde(with = "hex_key")]
+    pub address: PublicKeyBytes,
+    /// Initial balance
+    pub balance: u64,
+}
+
+/// A validator active at genesis
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+pub struct GenesisValidator {
+    /// Validator public key
+    #[serde(with = "hex_key")]
+    pub public_key: PublicKeyBytes,
+    /// Stake bonded at genesis
+    #[serde(default)]
+    pub stake: u64,
+}
+
+/// Consensus parameters fixed at genesis
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+#[serde(default)]
+pub struct GenesisConsensusParams {
+    /// Target block time in milliseconds
+    pub target_block_time_ms: u64,
+    /// Maximum number of validators
+    pub max_validators: u32,
+    /// Minimum stake amount
+    pub min_stake: u64,
+    /// Number of blocks per validator epoch, 0 to never change the validator set
+    pub epoch_length: u64,
+    /// Coins issued by each block before the first halving
+    pub initial_reward: u64,
+    /// Number of blocks between reward halvings, 0 to never halve
+    pub halving_interval: u64,
+}
+
+impl Default for GenesisConsensusParams {
+    fn default() -> Self {
+        let consensus = crate::consensus::ConsensusConfig::default();
+        let rewards = RewardConfig::default();
+        Self {
+            target_block_time_ms: consensus.target_block_time_ms,
+            max_validators: consensus.max_validators,
+            min_stake: consensus.min_stake,
+            epoch_length: consensus.epoch_length,
+            initial_reward: rewards.initial_reward,
+            halving_interval: rewards.halving_interval,
+        }
+    }
+}
+
+impl Default for GenesisConfig {
+    fn default() -> Self {
+        Self {
+            network_id: crate::DEFAULT_NETWORK_ID,
+            timestamp: 0,
+            accounts: Vec::new(),
+            validators: Vec::new(),
+            consensus: GenesisConsensusParams::default(),
+        }
+    }
+}
+
+impl GenesisConfig {
+    /// Parse a genesis configuration from JSON
+    pub fn from_json(json: &str) -> Result<Self, crate::Error> {
+        let genesis: Self = serde_json::from_str(json)
+            .map_err(|e| crate::Error::Config(format!("Invalid genesis file: {}", e)))?;
+        genesis.validate()?;
+        Ok(genesis)
+    }
+
+    /// Load a genesis configuration from a JSON file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, crate::Error> {
+        let json = std::fs::read_to_string(path)?;
+        Self::from_json(&json)
+    }
+
+    /// Check the configuration for contradictions
+    pub fn validate(&self) -> Result<(), crate::Error> {
+        let mut addresses = HashSet::new();
+        for account in &self.accounts {
+            if !addresses.insert(account.address) {
+                return Err(crate::Error::Config(format!(
+                    "Genesis account {} is listed twice",
+                    hex::encode(account.address)
+                )));
+            }
+        }
+
+        let mut validators = HashSet::new();
+        for validator in &self.validators {
+            if !validators.insert(validator.public_key) {
+                return Err(crate::Error::Config(format!(
+                    "Genesis validator {} is listed twice",
+                    hex::encode(validator.public_key)
+                )));
+            }
+        }
+        for validator in &self.validators {
+            if validator.stake < self.consensus.min_stake {
+                return Err(crate::Error::Config(format!(
+                    "Genesis validator {} stakes {}, minimum is {}",
+                    hex::encode(validator.public_key),
+                    validator.stake,
+                    self.consensus.min_stake
+                )));
+            }
+        }
+        if self.validators.len() > self.consensus.max_validators as usize {
+            return Err(crate::Error::Config(format!(
+                "Genesis lists {} validators, maximum is {}",
+                self.validators.len(),
+                self.consensus.max_validators
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Build the account state at genesis
+    ///
+    /// Validator stakes are bonded on top of the listed balances, and the
+    /// validators form the set of the first epoch.
+    pub fn state(&self) -> BlockchainState {
+        let mut state = BlockchainState::genesis_state(
+            self.accounts
+                .iter()
+                .map(|account| (account.address, account.balance))
+                .collect(),
+        );
+
+        for validator in &self.validators {
+            state.get_account_state(&validator.public_key).stake = validator.stake;
+        }
+        let validators = state.elect_validators(self.consensus.max_validators, self.consensus.min_stake);
+        state.set_validator_set(&ValidatorSet { epoch: 0, validators });
+
+        state
+    }
+
+    /// Build the account changes to persist along with the genesis block
+    pub fn state_diff(&self) -> StateDiff {
+        StateDiff {
+            updated: self.state().accounts,
+            ..StateDiff::default()
+        }
+    }
+
+    /// Build the genesis block
+    ///
+    /// The block has no transactions, so its merkle root commits to the
+    /// whole genesis configuration instead. Together with the state root this
+    /// makes the genesis hash change with any field of the file. The block
+    /// is not signed; it is trusted because every node derives it itself.
+    pub fn block(&self) -> Block {
+        let validator = self
+            .validators
+            .first()
+            .map(|validator| validator.public_key)
+            .unwrap_or([0u8; 32]);
+
+        // The genesis block has no parent to wait on, so it keeps the last
+        // version without a wait certificate and its hash stays the same
+        let mut header = BlockHeader::new(STATE_ROOT_BLOCK_VERSION, [0u8; 32], self.digest(), 0, validator);
+        header.timestamp = self.timestamp;
+        header.state_root = self.state().state_root();
+
+        Block {
+            header,
+            transactions: Vec::new(),
+        }
+    }
+
+    /// Get the hash of the genesis block
+    pub fn hash(&self) -> Hash {
+        self.block().header.hash()
+    }
+
+    /// Get the reward schedule fixed by this genesis
+    pub fn reward_config(&self) -> RewardConfig {
+        RewardConfig {
+            initial_reward: self.consensus.initial_reward,
+            halving_interval: self.consensus.halving_interval,
+        }
+    }
+
+    /// Hash a canonical encoding of the configuration
+    ///
+    /// Account and validator order in the file does not matter.
+    fn digest(&self) -> Hash {
+        let mut canonical = self.clone();
+        canonical.accounts.sort_by_key(|account| account.address);
+        canonical.validators.sort_by_key(|validator| validator.public_key);
+
+        // Encoding plain integers and byte arrays into a Vec cannot fail
+        let bytes = bincode::encode_to_vec(&canonical, bincode::config::standard())
+            .expect("genesis configuration is encodable");
+        crate::crypto::hash_data(&bytes)
+    }
+}
+
+/// Serde helpers writing 32-byte keys as hex strings
+mod hex_key {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(key: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(key))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let bytes = hex::decode(&s).map_err(serde::de::Error::custom)?;
+        bytes
+            .try_into()
+            .map_err(|_| serde::de::Error::custom(format!("expected 32 bytes, got key {}", s)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_json() -> String {
+        format!(
+            r#"{{
+                "network_id": 7,
+                "timestamp": 1700000000000,
+                "accounts": [
+                    {{ "address": "{}", "balance": 1000 }},
+                    {{ "address": "{}", "balance": 2000 }}
+                ],
+                "validators": [{{ "public_key": "{}", "stake": 5000 }}],
+                "consensus": {{ "target_block_time_ms": 1000 }}
+            }}"#,
+            hex::encode([1u8; 32]),
+            hex::encode([2u8; 32]),
+            hex::encode([3u8; 32])
+        )
+    }
+
+    #[test]
+    fn test_parse_genesis() {
+        let genesis = GenesisConfig::from_json(&sample_json()).unwrap();
+        assert_eq!(genesis.network_id, 7);
+        assert_eq!(genesis.accounts[1].address, [2u8; 32]);
+        assert_eq!(genesis.validators[0].stake, 5000);
+        assert_eq!(genesis.consensus.target_block_time_ms, 1000);
+        // Unspecified parameters keep their defaults
+        assert_eq!(genesis.consensus.min_stake, GenesisConsensusParams::default().min_stake);
+
+        let state = genesis.state();
+        assert_eq!(state.accounts[&[1u8; 32]].balance, 1000);
+        assert_eq!(state.accounts[&[2u8; 32]].balance, 2000);
+        assert_eq!(state.accounts[&[3u8; 32]].stake, 5000);
+        assert!(state.validator_set().contains(&[3u8; 32]));
+    }
+
+    #[test]
+    fn test_genesis_hash_is_deterministic() {
+        let genesis = GenesisConfig::from_json(&sample_json()).unwrap();
+        assert_eq!(genesis.hash(), GenesisConfig::from_json(&sample_json()).unwrap().hash());
+
+        let block = genesis.block();
+        assert_eq!(block.header.height, 0);
+        assert_eq!(block.header.prev_hash, [0u8; 32]);
+        assert_eq!(block.header.timestamp, 1_700_000_000_000);
+        assert_eq!(block.header.validator, [3u8; 32]);
+        assert_eq!(block.header.state_root, genesis.state().state_root());
+
+        // Listing order does not matter, any value does
+        let mut reordered = genesis.clone();
+        reordered.accounts.reverse();
+        assert_eq!(reordered.hash(), genesis.hash());
+
+        let mut other_network = genesis.clone();
+        other_network.network_id = 8;
+        assert_ne!(other_network.hash(), genesis.hash());
+
+        let mut other_params = genesis.clone();
+        other_params.consensus.initial_reward += 1;
+        assert_ne!(other_params.hash(), genesis.hash());
+    }
+
+    #[test]
+    fn test_invalid_genesis() {
+        assert!(GenesisConfig::from_json("{").is_err());
+        assert!(GenesisConfig::from_json(r#"{ "network_id": 1, "timestamp": 0, "accounts": [{ "address": "abcd", "balance": 1 }] }"#).is_err());
+
+        let mut genesis = GenesisConfig::from_json(&sample_json()).unwrap();
+        genesis.accounts.push(genesis.accounts[0].clone());
+        assert!(genesis.validate().is_err());
+
+        let mut understaked = GenesisConfig::from_json(&sample_json()).unwrap();
+        understaked.validators[0].stake = understaked.consensus.min_stake - 1;
+        assert!(understaked.validate().is_err());
+    }
+}
@@ -12,18 +12,17 @@ pub mod crypto;
 pub mod block;
 pub mod transaction;  // This will now export the transaction pool through transaction::pool
 pub mod state;
-// Keep only ONE consensus module declaration - either the import or the inline definition
-// pub mod consensus; <- Comment out or remove this line since we're using an inline definition below
+pub mod consensus;
+pub mod genesis;
 pub mod network;
 pub mod storage;
 pub mod vm;
 
 // Re-exports of the most commonly used types
 pub use types::{Hash, PublicKeyBytes, PrivateKeyBytes, SignatureBytes};
-pub use block::{Block, BlockHeader};
+pub use block::{Block, BlockHeader, BlockImportError, BlockImportOutcome};
 pub use transaction::Transaction;
-// Update these re-exports to use the inline consensus module
-// pub use consensus::{Consensus, PoETConsensus};
+pub use consensus::{Consensus, PoAConsensus, PoETConsensus};
 pub use network::{Node, NodeConfig};
 pub use storage::block_store::BlockStore;
 pub use storage::state_store::StateStore;
@@ -31,6 +30,9 @@ pub use storage::state_store::StateStore;
 /// Library version information
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+/// Network id used when no genesis file says otherwise
+pub const DEFAULT_NETWORK_ID: u64 = 1;
+
 /// Configuration for the Blocana blockchain
 #[derive(Debug, Clone)]
 pub struct BlockchainConfig {
@@ -48,49 +50,822 @@ pub struct BlockchainConfig {
     pub network_config: network::NetworkConfig,
     /// Consensus configuration
     pub consensus_config: consensus::ConsensusConfig,
+    /// Transaction pool configuration
+    pub pool_config: transaction::pool::TransactionPoolConfig,
+    /// Block reward schedule
+    pub reward_config: state::RewardConfig,
+    /// Description of the network's genesis block and state
+    pub genesis: genesis::GenesisConfig,
 }
 
 impl Default for BlockchainConfig {
     fn default() -> Self {
         Self {
-            network_id: 1,
+            network_id: DEFAULT_NETWORK_ID,
             max_block_size: 1_000_000,
             target_block_time_ms: 500,
             max_txs_per_block: 1000,
             storage_config: storage::StorageConfig::default(),
             network_config: network::NetworkConfig::default(),
             consensus_config: consensus::ConsensusConfig::default(),
+            pool_config: transaction::pool::TransactionPoolConfig::default(),
+            reward_config: state::RewardConfig::default(),
+            genesis: genesis::GenesisConfig::default(),
         }
     }
 }
 
+impl BlockchainConfig {
+    /// Use the given genesis, taking the network id and consensus parameters from it
+    pub fn with_genesis(mut self, genesis: genesis::GenesisConfig) -> Self {
+        self.network_id = genesis.network_id;
+        self.target_block_time_ms = genesis.consensus.target_block_time_ms;
+        self.consensus_config.target_block_time_ms = genesis.consensus.target_block_time_ms;
+        self.consensus_config.max_validators = genesis.consensus.max_validators;
+        self.consensus_config.min_stake = genesis.consensus.min_stake;
+        self.consensus_config.epoch_length = genesis.consensus.epoch_length;
+        self.reward_config = genesis.reward_config();
+        self.genesis = genesis;
+        self
+    }
+}
+
+/// Approximate encoded size of a block without its transactions, used when
+/// filling a block up to `max_block_size`
+const BLOCK_HEADER_OVERHEAD: usize = 300;
+
+/// How far ahead of local time an imported block's timestamp may be
+const MAX_FUTURE_BLOCK_TIME_MS: u64 = 15_000;
+
+/// Number of heights below the highest imported block watched for equivocations
+const EQUIVOCATION_WINDOW: u64 = 1_000;
+
 /// Main blockchain instance
+///
+/// Ties together storage, state, the transaction pool and the consensus engine
+/// so that blocks can be produced and applied as a single operation.
 pub struct Blockchain {
     /// Blockchain configuration
     pub config: BlockchainConfig,
+    /// Persistent block and state storage
+    storage: storage::BlockchainStorage,
+    /// Account state at the current chain tip
+    state: state::BlockchainState,
+    /// Pending transactions waiting to be included in a block
+    pool: transaction::pool::TransactionPool,
+    /// Consensus engine used to build and sign blocks
+    consensus: Box<dyn Consensus>,
+    /// Headers of recently imported blocks, to catch validators signing two at one height
+    equivocations: consensus::EquivocationDetector,
+    /// Key pair of this node, used to sign blocks and locally created transactions
+    wallet: crypto::KeyPair,
 }
 
 impl Blockchain {
-    /// Create a new blockchain instance
+    /// Create a new blockchain instance with a freshly generated node key
+    ///
+    /// Opens the configured storage, creates the configured genesis block if
+    /// the database is empty and loads the persisted account state. Fails if
+    /// the database was initialised from a different genesis.
     pub fn new(config: BlockchainConfig) -> Result<Self, Error> {
-        Ok(Self { config })
+        Self::with_wallet(config, crypto::KeyPair::generate()?)
+    }
+
+    /// Create a new blockchain instance that uses the given node key
+    ///
+    /// # Parameters
+    /// * `config` - Blockchain configuration
+    /// * `wallet` - Key pair used to sign blocks and transactions created by this node
+    pub fn with_wallet(config: BlockchainConfig, wallet: crypto::KeyPair) -> Result<Self, Error> {
+        let storage = storage::BlockchainStorage::open(&config.storage_config)?;
+        storage::ensure_compatible_schema(&storage)?;
+
+        let mut consensus = consensus::new_consensus(
+            &config.consensus_config,
+            wallet.public_key,
+            wallet.private_key,
+        )?;
+        consensus.initialize(&storage)?;
+
+        // The pool only accepts transactions for the network this node is on
+        let pool_config = transaction::pool::TransactionPoolConfig {
+            network_id: config.network_id,
+            ..config.pool_config.clone()
+        };
+        let pool = transaction::pool::TransactionPool::with_config(pool_config);
+
+        let mut blockchain = Self {
+            config,
+            storage,
+            state: state::BlockchainState::new(),
+            pool,
+            consensus,
+            equivocations: consensus::EquivocationDetector::new(EQUIVOCATION_WINDOW),
+            wallet,
+        };
+
+        blockchain.ensure_genesis()?;
+        blockchain.state.accounts = blockchain.storage.get_all_account_states()?;
+        blockchain.restore_pool()?;
+
+        Ok(blockchain)
+    }
+
+    /// Restore the transactions that were waiting in the pool when the node stopped
+    ///
+    /// Journaled transactions are revalidated against the loaded state, and
+    /// the ones that expired or became invalid while the node was down are
+    /// dropped from the journal.
+    fn restore_pool(&mut self) -> Result<(), Error> {
+        let journal = self.storage.get_pool_journal()?;
+        if journal.is_empty() {
+            return Ok(());
+        }
+
+        let journaled = journal.len();
+        let mut validation_state = self.state.clone();
+        let restored = self.pool.restore_journal(journal, &mut validation_state);
+        self.save_pool_journal();
+
+        log::info!("Restored {} of {} journaled transactions", restored, journaled);
+        Ok(())
+    }
+
+    /// Save the transactions that entered or left the pool to its journal
+    ///
+    /// A failed write is only logged, the changes are then saved with the next ones.
+    fn save_pool_journal(&mut self) {
+        let (saved, removed) = self.pool.journal_changes();
+        if saved.is_empty() && removed.is_empty() {
+            return;
+        }
+
+        match self.storage.update_pool_journal(&saved, &removed) {
+            Ok(()) => self.pool.clear_journal_changes(),
+            Err(e) => log::warn!("Failed to save the transaction pool journal: {}", e),
+        }
     }
 
+    /// Start the consensus engine
+    ///
+    /// Until it is started the node never asks to produce blocks, although
+    /// [`Blockchain::generate_block`] still works on demand.
     pub fn start(&mut self) -> Result<(), Error> {
-        // Implementation goes here
+        self.consensus.start()?;
         Ok(())
     }
 
+    /// Stop the consensus engine
+    pub fn stop(&mut self) -> Result<(), Error> {
+        self.consensus.stop()?;
+        Ok(())
+    }
+
+    /// Check whether the consensus engine is running
+    pub fn is_running(&self) -> bool {
+        self.consensus.is_running()
+    }
+
+    /// Check whether the consensus engine wants this node to build on the tip now
+    ///
+    /// Never true while the node's key is not an active validator.
+    pub fn should_produce_block(&self) -> Result<bool, Error> {
+        if !self.consensus.is_running() {
+            return Ok(false);
+        }
+        if self.consensus.check_validator(&self.wallet.public_key, &self.state).is_err() {
+            return Ok(false);
+        }
+
+        Ok(self.consensus.should_produce_block(&self.tip_header()?))
+    }
+
+    /// Create and store the genesis block if the chain is empty
+    ///
+    /// A database that already holds a different genesis block belongs to
+    /// another network and is refused.
+    fn ensure_genesis(&mut self) -> Result<(), Error> {
+        let genesis = self.config.genesis.block();
+        let genesis_hash = genesis.header.hash();
+
+        if let Some(stored) = self.storage.get_block_by_height(0)? {
+            let stored_hash = stored.header.hash();
+            if stored_hash != genesis_hash {
+                return Err(Error::Config(format!(
+                    "Database was initialised from genesis {}, but the configured genesis is {}",
+                    hex::encode(stored_hash),
+                    hex::encode(genesis_hash)
+                )));
+            }
+            return Ok(());
+        }
+
+        self.storage
+            .store_block_with_state(&genesis, &self.config.genesis.state_diff())?;
+
+        log::info!("Created genesis block {}", hex::encode(genesis_hash));
+        Ok(())
+    }
+
+    /// Get the height and hash of the current chain tip
+    pub fn chain_tip(&self) -> Result<(u64, Hash), Error> {
+        let hash = self
+            .storage
+            .get_chain_tip()?
+            .ok_or_else(|| Error::DB("Chain has no blocks".into()))?;
+        let height = self.storage.get_latest_height()?;
+        Ok((height, hash))
+    }
+
+    /// Get the header of the current chain tip
+    fn tip_header(&self) -> Result<BlockHeader, Error> {
+        let (_, tip_hash) = self.chain_tip()?;
+        self.storage
+            .get_block(&tip_hash)?
+            .map(|block| block.header)
+            .ok_or_else(|| Error::DB("Chain tip block is missing".into()))
+    }
+
     /// Generate a new block
+    ///
+    /// Selects the highest priority transactions from the pool within the
+    /// configured block limits, has the consensus engine build and sign the
+    /// block on top of the current tip, applies it to the state, persists it
+    /// and evicts the included transactions from the pool.
+    ///
+    /// The block is dated no earlier than the end of this node's consensus
+    /// wait on the tip, so blocks generated faster than the consensus allows
+    /// carry future timestamps that peers eventually refuse.
     pub fn generate_block(&mut self) -> Result<Block, Error> {
-        // Placeholder implementation
-        Err(Error::Other("Block generation not implemented".into()))
+        let parent = self.tip_header()?;
+
+        let candidates = self
+            .pool
+            .select_transactions(self.config.max_txs_per_block, &self.state);
+
+        // Respect the block size limit, and skip transactions an earlier one
+        // in the block already made stale, such as a second report of the
+        // same equivocation
+        let mut selection_state = self.state.clone();
+        let mut block_size = BLOCK_HEADER_OVERHEAD;
+        let mut transactions = Vec::with_capacity(candidates.len());
+        for tx in candidates {
+            let tx_size = tx.estimate_size();
+            if block_size + tx_size > self.config.max_block_size {
+                break;
+            }
+            if selection_state.apply_transaction(&tx).is_err() {
+                continue;
+            }
+            block_size += tx_size;
+            transactions.push(tx);
+        }
+
+        let mut block = self.build_block(transactions.clone(), &parent)?;
+
+        // The size estimate is approximate, so trim if the encoded block is still too big
+        while block.serialized_size() > self.config.max_block_size && !transactions.is_empty() {
+            transactions.pop();
+            block = self.build_block(transactions.clone(), &parent)?;
+        }
+
+        self.commit_block(&block)?;
+
+        log::info!(
+            "Generated block {} at height {} with {} transactions",
+            hex::encode(&block.header.hash()[0..4]),
+            block.header.height,
+            block.transactions.len()
+        );
+
+        Ok(block)
     }
 
-    /// Create a new transaction
-    pub fn create_transaction(&mut self, _recipient: PublicKeyBytes, _amount: u64) -> Result<Transaction, Error> {
-        // Placeholder implementation
-        Err(Error::Other("Transaction creation not implemented".into()))
+    /// Have the consensus engine build a block committing to the state it produces
+    fn build_block(&mut self, transactions: Vec<Transaction>, parent: &BlockHeader) -> Result<Block, Error> {
+        let height = parent.height + 1;
+        // Apply the transactions and our reward just long enough to read the resulting state root
+        let diff = self.state.apply_transactions_with_diff(
+            &transactions,
+            &self.wallet.public_key,
+            height,
+            &self.config.reward_config,
+            &self.config.consensus_config,
+        )?;
+        let state_root = self.state.state_root();
+        self.state.apply_undo(&diff.undo);
+
+        Ok(self
+            .consensus
+            .generate_block(transactions, parent, state_root)?)
+    }
+
+    /// Import a block received from elsewhere
+    ///
+    /// Performs every contextual check against the stored parent and the
+    /// configured limits. A block on top of the tip is applied to the state
+    /// and stored atomically with it. A block on another branch is kept as a
+    /// side chain block, and if that branch becomes heavier than the main
+    /// chain the node reorganizes onto it.
+    ///
+    /// # Returns
+    /// What happened to the block, or the reason it was refused
+    pub fn import_block(&mut self, block: Block) -> Result<block::BlockImportOutcome, block::BlockImportError> {
+        use block::{BlockImportError, BlockImportOutcome};
+
+        let block_hash = block.header.hash();
+        if self.storage.get_block(&block_hash)?.is_some() {
+            return Err(BlockImportError::AlreadyKnown { hash: block_hash });
+        }
+
+        let parent = self
+            .storage
+            .get_block(&block.header.prev_hash)?
+            .ok_or(BlockImportError::UnknownParent { prev_hash: block.header.prev_hash })?;
+        if self.storage.get_block_meta(&block.header.prev_hash)?.is_some_and(|meta| meta.invalid) {
+            return Err(BlockImportError::InvalidParent { prev_hash: block.header.prev_hash });
+        }
+
+        let expected_height = parent.header.height + 1;
+        if block.header.height != expected_height {
+            return Err(BlockImportError::InvalidHeight {
+                expected: expected_height,
+                actual: block.header.height,
+            });
+        }
+
+        // Older headers do not commit to the state, and with it to the fees and issuance paid
+        if block.header.version < block::STATE_ROOT_BLOCK_VERSION {
+            return Err(BlockImportError::Invalid(format!(
+                "Block version {} does not commit to the state root",
+                block.header.version
+            )));
+        }
+
+        if block.header.timestamp < parent.header.timestamp {
+            return Err(BlockImportError::TimestampBeforeParent {
+                timestamp: block.header.timestamp,
+                parent_timestamp: parent.header.timestamp,
+            });
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        if block.header.timestamp > now + MAX_FUTURE_BLOCK_TIME_MS {
+            return Err(BlockImportError::TimestampInFuture {
+                timestamp: block.header.timestamp,
+                now,
+            });
+        }
+
+        if block.transactions.len() > self.config.max_txs_per_block {
+            return Err(BlockImportError::TooManyTransactions {
+                count: block.transactions.len(),
+                max_count: self.config.max_txs_per_block,
+            });
+        }
+        let size = block.serialized_size();
+        if size > self.config.max_block_size {
+            return Err(BlockImportError::TooLarge {
+                size,
+                max_size: self.config.max_block_size,
+            });
+        }
+
+        // Block version, merkle root, signatures and the consensus proof
+        self.consensus
+            .validate_block(&block, &parent.header)
+            .map_err(|e| BlockImportError::Invalid(format!("{:?}", e)))?;
+
+        // A second signed block from the same validator at this height proves it equivocated
+        if let Some(evidence) = self.equivocations.observe(&block.header) {
+            log::warn!(
+                "Validator {} signed two blocks at height {}",
+                hex::encode(&block.header.validator[0..4]),
+                block.header.height
+            );
+            if let Err(e) = self.report_equivocation(evidence) {
+                log::warn!("Failed to report equivocation: {}", e);
+            }
+        }
+
+        // Transactions signed for another network must not be replayed here
+        for (index, tx) in block.transactions.iter().enumerate() {
+            tx.verify_for_network(self.config.network_id)
+                .map_err(|e| BlockImportError::InvalidTransaction { index, reason: e.to_string() })?;
+        }
+
+        let (_, tip_hash) = self
+            .chain_tip()
+            .map_err(|e| BlockImportError::Storage(e.to_string()))?;
+        if block.header.prev_hash != tip_hash {
+            return self.import_side_block(block);
+        }
+
+        self.commit_block(&block)?;
+
+        log::info!(
+            "Imported block {} at height {} with {} transactions",
+            hex::encode(&block_hash[0..4]),
+            block.header.height,
+            block.transactions.len()
+        );
+
+        Ok(BlockImportOutcome::Extended)
+    }
+
+    /// Store a block that does not build on the tip and reorganize if its branch is now heavier
+    fn import_side_block(&mut self, block: Block) -> Result<block::BlockImportOutcome, block::BlockImportError> {
+        use block::{BlockImportError, BlockImportOutcome};
+
+        let block_hash = block.header.hash();
+        self.storage.store_block(&block)?;
+
+        let (_, tip_hash) = self
+            .chain_tip()
+            .map_err(|e| BlockImportError::Storage(e.to_string()))?;
+        let branch_weight = self.block_weight(&block_hash)?;
+        let tip_weight = self.block_weight(&tip_hash)?;
+
+        // Ties keep the branch we saw first
+        if branch_weight <= tip_weight {
+            log::debug!(
+                "Stored side chain block {} at height {}",
+                hex::encode(&block_hash[0..4]),
+                block.header.height
+            );
+            return Ok(BlockImportOutcome::SideChain);
+        }
+
+        self.reorganize(&block_hash)
+    }
+
+    /// Get the cumulative weight of the chain ending at a stored block
+    fn block_weight(&self, hash: &Hash) -> Result<u64, block::BlockImportError> {
+        self.storage
+            .get_block_meta(hash)?
+            .map(|meta| meta.cumulative_weight)
+            .ok_or_else(|| block::BlockImportError::Storage(format!(
+                "Missing metadata for block {}",
+                hex::encode(hash)
+            )))
+    }
+
+    /// Switch the main chain to the branch ending at `new_tip`
+    fn reorganize(&mut self, new_tip: &Hash) -> Result<block::BlockImportOutcome, block::BlockImportError> {
+        let reorg = self.storage.plan_reorg(new_tip)?;
+        self.switch_chain(&reorg)?;
+
+        log::info!(
+            "Reorganized chain to {}: {} blocks disconnected, {} connected",
+            hex::encode(&new_tip[0..4]),
+            reorg.disconnected.len(),
+            reorg.connected.len()
+        );
+
+        Ok(block::BlockImportOutcome::Reorganized {
+            disconnected: reorg.disconnected.len(),
+            connected: reorg.connected.len(),
+        })
+    }
+
+    /// Roll back the top `count` blocks of the main chain
+    ///
+    /// The state is restored from the stored undo records, so nothing is
+    /// re-executed. Transactions from the removed blocks go back to the pool.
+    /// The genesis block is never rolled back.
+    ///
+    /// # Returns
+    /// The blocks that were removed from the main chain, old tip first
+    pub fn rollback(&mut self, count: u64) -> Result<Vec<Block>, Error> {
+        let reorg = self.storage.plan_rollback(count)?;
+        self.switch_chain(&reorg)?;
+
+        log::info!(
+            "Rolled back {} blocks to {}",
+            reorg.disconnected.len(),
+            hex::encode(&reorg.common_ancestor[0..4])
+        );
+
+        Ok(reorg.disconnected)
+    }
+
+    /// Move the main chain from its current tip onto the blocks described by `reorg`
+    ///
+    /// Rewinds the state to the common ancestor with the undo records of the
+    /// disconnected blocks, applies the connected blocks, rewrites storage in
+    /// one batch and hands transactions that only the old branch contained
+    /// back to the pool. If any step fails the in-memory state is restored,
+    /// and a connected block that does not apply is marked invalid together
+    /// with the connected blocks after it.
+    fn switch_chain(&mut self, reorg: &storage::ChainReorg) -> Result<(), block::BlockImportError> {
+        use block::BlockImportError;
+
+        // A branch through a block that already failed to apply fails again
+        for (index, new_block) in reorg.connected.iter().enumerate() {
+            let block_hash = new_block.header.hash();
+            if self.storage.get_block_meta(&block_hash)?.is_some_and(|meta| meta.invalid) {
+                self.mark_invalid(&reorg.connected[index..])?;
+                return Err(BlockImportError::Invalid(format!(
+                    "Branch block {} is invalid",
+                    hex::encode(block_hash)
+                )));
+            }
+        }
+
+        let mut undos = Vec::with_capacity(reorg.disconnected.len());
+        for old_block in &reorg.disconnected {
+            let block_hash = old_block.header.hash();
+            let undo = self.storage.get_block_undo(&block_hash)?.ok_or_else(|| {
+                BlockImportError::Storage(format!(
+                    "Undo record for block {} not found",
+                    hex::encode(block_hash)
+                ))
+            })?;
+            undos.push(undo);
+        }
+        for undo in &undos {
+            self.state.apply_undo(undo);
+        }
+
+        let mut diffs = Vec::with_capacity(reorg.connected.len());
+        let mut failure = None;
+        // Position of the first connected block that does not apply
+        let mut invalid_from = None;
+        for (index, new_block) in reorg.connected.iter().enumerate() {
+            let block_hash = new_block.header.hash();
+            if let Err(e) = self.consensus.check_validator(&new_block.header.validator, &self.state) {
+                failure = Some(BlockImportError::Invalid(format!("{:?}", e)));
+                invalid_from = Some(index);
+                break;
+            }
+            match self.state.apply_block_with_diff(
+                new_block,
+                &self.config.reward_config,
+                &self.config.consensus_config,
+            ) {
+                Ok(diff) => {
+                    diffs.push(diff);
+                    if let Err(e) = self.check_state_root(new_block) {
+                        failure = Some(e);
+                        invalid_from = Some(index);
+                        break;
+                    }
+                }
+                Err(e) => {
+                    failure = Some(BlockImportError::Invalid(format!(
+                        "Branch block {} does not apply: {}",
+                        hex::encode(block_hash),
+                        e.error
+                    )));
+                    invalid_from = Some(index);
+                    break;
+                }
+            }
+        }
+        if failure.is_none() {
+            if let Err(e) = self.storage.apply_reorg(reorg, &diffs) {
+                failure = Some(e.into());
+            }
+        }
+        if let Some(err) = failure {
+            // Put the old main chain state back
+            for diff in diffs.iter().rev() {
+                self.state.apply_undo(&diff.undo);
+            }
+            for old_block in reorg.disconnected.iter().rev() {
+                self.state
+                    .apply_block(old_block, &self.config.reward_config, &self.config.consensus_config)
+                    .map_err(|e| BlockImportError::Storage(format!("Failed to restore state: {}", e)))?;
+            }
+
+            // Never try to switch onto the failed block or its descendants again
+            if let Some(index) = invalid_from {
+                self.mark_invalid(&reorg.connected[index..])?;
+            }
+            return Err(err);
+        }
+
+        // Move transactions between the pool and the chain
+        let connected_txs: std::collections::HashSet<Hash> = reorg
+            .connected
+            .iter()
+            .flat_map(|b| b.transactions.iter().map(|tx| tx.hash()))
+            .collect();
+        for tx_hash in &connected_txs {
+            self.pool.remove_transaction(tx_hash);
+        }
+
+        let mut resubmit_state = self.state.clone();
+        for old_block in reorg.disconnected.iter().rev() {
+            for tx in &old_block.transactions {
+                if connected_txs.contains(&tx.hash()) {
+                    continue;
+                }
+                // Readmitted rather than resubmitted, so the reorg does not use up the senders' rate;
+                // advance the scratch state so follow-up nonces from the same sender are accepted
+                match self.pool.readmit_transaction(tx.clone(), &mut resubmit_state) {
+                    Ok(_) => {
+                        let _ = resubmit_state.apply_transaction(tx);
+                    }
+                    Err(e) => log::debug!(
+                        "Dropped orphaned transaction {}: {}",
+                        hex::encode(&tx.hash()[0..4]),
+                        e
+                    ),
+                }
+            }
+        }
+        let mut revalidation_state = self.state.clone();
+        self.pool.revalidate_transactions(&mut revalidation_state);
+        self.save_pool_journal();
+
+        Ok(())
+    }
+
+    /// Mark side chain blocks as invalid so fork choice never switches onto them
+    fn mark_invalid(&self, blocks: &[Block]) -> Result<(), block::BlockImportError> {
+        let hashes: Vec<Hash> = blocks.iter().map(|block| block.header.hash()).collect();
+        self.storage.mark_blocks_invalid(&hashes)?;
+
+        log::warn!(
+            "Marked {} side chain blocks from {} as invalid",
+            hashes.len(),
+            hex::encode(&hashes[0][0..4])
+        );
+        Ok(())
+    }
+
+    /// Apply a block on top of the tip and persist it together with its state changes
+    ///
+    /// The block's validator must be allowed to produce blocks on the tip's
+    /// state.
+    /// Applying the block credits its validator with the transaction fees and
+    /// the issuance from `reward_config`; a header whose state root assumes
+    /// any other payout is refused. The block, the accounts it touched and
+    /// its undo record are written in
+    /// one batch, then the included transactions are evicted from the pool
+    /// and the remaining ones are revalidated against the new state.
+    fn commit_block(&mut self, block: &Block) -> Result<(), block::BlockImportError> {
+        use block::BlockImportError;
+
+        self.consensus
+            .check_validator(&block.header.validator, &self.state)
+            .map_err(|e| BlockImportError::Invalid(format!("{:?}", e)))?;
+
+        let diff = self
+            .state
+            .apply_block_with_diff(block, &self.config.reward_config, &self.config.consensus_config)
+            .map_err(|e| BlockImportError::InvalidTransaction {
+                index: e.index,
+                reason: e.error.to_string(),
+            })?;
+
+        if let Err(e) = self.check_state_root(block) {
+            self.state.apply_undo(&diff.undo);
+            return Err(e);
+        }
+
+        if let Err(e) = self.storage.store_block_with_state(block, &diff) {
+            self.state.apply_undo(&diff.undo);
+            return Err(e.into());
+        }
+
+        // Evict included transactions and refresh the validity of the rest
+        for tx in &block.transactions {
+            self.pool.remove_transaction(&tx.hash());
+        }
+        let mut revalidation_state = self.state.clone();
+        self.pool.revalidate_transactions(&mut revalidation_state);
+        self.save_pool_journal();
+
+        Ok(())
+    }
+
+    /// Check that the current state matches the root committed in a just applied block
+    ///
+    /// Headers older than [`block::STATE_ROOT_BLOCK_VERSION`] carry no state
+    /// root and are refused; only the stored legacy chain, which is never
+    /// imported, may hold them.
+    fn check_state_root(&self, block: &Block) -> Result<(), block::BlockImportError> {
+        if !block.header.has_state_root() {
+            return Err(block::BlockImportError::Invalid(format!(
+                "Block at height {} carries no state root",
+                block.header.height
+            )));
+        }
+
+        let actual = self.state.state_root();
+        if actual != block.header.state_root {
+            return Err(block::BlockImportError::StateRootMismatch {
+                expected: block.header.state_root,
+                actual,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Submit a transaction to the node's pool
+    ///
+    /// # Returns
+    /// The transaction hash if it was accepted
+    pub fn submit_transaction(&mut self, tx: Transaction) -> Result<Hash, Error> {
+        self.submit_transaction_detailed(tx).map_err(Into::into)
+    }
+
+    /// Submit a transaction to the node's pool, reporting why it was refused
+    ///
+    /// # Returns
+    /// The transaction hash if it was accepted, otherwise the pool's reason
+    /// for refusing it
+    pub fn submit_transaction_detailed(
+        &mut self,
+        tx: Transaction,
+    ) -> transaction::pool::TxResult<Hash> {
+        let mut validation_state = self.state.clone();
+        let tx_hash = self.pool.add_transaction_detailed(tx, &mut validation_state)?;
+        self.save_pool_journal();
+        Ok(tx_hash)
+    }
+
+    /// Create a transfer from the node's wallet and submit it to the pool
+    ///
+    /// The nonce continues after any transactions from the wallet that are
+    /// still pending in the pool, and the fee is the minimum the pool accepts.
+    ///
+    /// # Parameters
+    /// * `recipient` - Address receiving the funds
+    /// * `amount` - Amount to transfer
+    ///
+    /// # Returns
+    /// The hash of the submitted transaction
+    pub fn create_transaction(&mut self, recipient: PublicKeyBytes, amount: u64) -> Result<Hash, Error> {
+        let nonce = self.next_wallet_nonce();
+        let tx = Transaction::new(self.wallet.public_key, recipient, amount, 0, nonce, Vec::new());
+        self.submit_from_wallet(tx)
+    }
+
+    /// Report an equivocating validator by submitting the evidence from the node's wallet
+    ///
+    /// The wallet pays the minimum fee, like for [`Blockchain::create_transaction`].
+    ///
+    /// # Returns
+    /// The hash of the evidence transaction
+    pub fn report_equivocation(&mut self, evidence: consensus::EquivocationEvidence) -> Result<Hash, Error> {
+        let nonce = self.next_wallet_nonce();
+        let tx = Transaction::new_evidence(self.wallet.public_key, &evidence, 0, nonce);
+        self.submit_from_wallet(tx)
+    }
+
+    /// Get the nonce following the wallet's transactions still pending in the pool
+    fn next_wallet_nonce(&mut self) -> u64 {
+        let sender = self.wallet.public_key;
+        let state_nonce = self.state.get_account_state(&sender).nonce;
+        self.pool.next_nonce(&sender, state_nonce)
+    }
+
+    /// Sign a transaction from the node's wallet with the minimum fee the pool accepts and submit it
+    fn submit_from_wallet(&mut self, tx: Transaction) -> Result<Hash, Error> {
+        let mut tx = tx.with_network_id(self.config.network_id);
+        // A zero fee never passes verification, even if the pool would accept it
+        tx.fee = (self.config.pool_config.min_fee_per_byte * tx.estimate_size() as u64).max(1);
+        tx.sign(&self.wallet.private_key)?;
+
+        self.submit_transaction(tx)
+    }
+
+    /// Get the address of the node's wallet
+    pub fn wallet_address(&self) -> PublicKeyBytes {
+        self.wallet.public_key
+    }
+
+    /// Get the underlying storage
+    pub fn storage(&self) -> &storage::BlockchainStorage {
+        &self.storage
+    }
+
+    /// Get the account state at the current chain tip
+    pub fn state(&self) -> &state::BlockchainState {
+        &self.state
+    }
+
+    /// Get mutable access to the in-memory account state
+    ///
+    /// Changes made here are not persisted; this is intended for tooling and tests.
+    pub fn state_mut(&mut self) -> &mut state::BlockchainState {
+        &mut self.state
+    }
+
+    /// Get the transaction pool
+    pub fn pool(&self) -> &transaction::pool::TransactionPool {
+        &self.pool
+    }
+
+    /// Get mutable access to the transaction pool
+    pub fn pool_mut(&mut self) -> &mut transaction::pool::TransactionPool {
+        &mut self.pool
     }
 
     /// Print blockchain status
@@ -99,6 +874,12 @@ impl Blockchain {
         println!("  Network ID: {}", self.config.network_id);
         println!("  Block size limit: {} bytes", self.config.max_block_size);  // Add missing argument
         println!("  Target block time: {}ms", self.config.target_block_time_ms);
+        println!("  Node address: {}", hex::encode(self.wallet.public_key));
+        match self.chain_tip() {
+            Ok((height, hash)) => println!("  Chain tip: {} ({})", height, hex::encode(&hash[0..8])),
+            Err(e) => println!("  Chain tip: unavailable ({})", e),
+        }
+        println!("  Pending transactions: {}", self.pool.len());
     }
 
     /// Print connected peers
@@ -170,7 +951,7 @@ impl From<storage::Error> for Error {
             storage::Error::Database(s) => Error::DB(s),
             storage::Error::Serialization(s) => Error::Serialization(s),
             storage::Error::Other(s) => Error::Other(s),
-            storage::Error::NotFound(_) => todo!(),
+            storage::Error::NotFound(s) => Error::DB(format!("Not found: {}", s)),
         }
     }
 }
@@ -211,22 +992,6 @@ impl From<transaction::pool::PoolError> for Error {
     }
 }
 
-/// Define placeholder modules for those not yet implemented
-
-/// Mock consensus module
-pub mod consensus {
-    #[derive(Debug)]
-    pub enum Error {}
-
-    #[derive(Debug, Default, Clone)]
-    pub struct ConsensusConfig {}
-    
-    pub trait Consensus {}
-
-    pub struct PoETConsensus {}
-    impl Consensus for PoETConsensus {}
-}
-
 /// Mock VM module for compilation
 pub mod vm_alternative {
     #[derive(Debug)]
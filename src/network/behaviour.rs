@@ -0,0 +1,74 @@
+//! Combined network behaviour of a Blocana node
+//!
+//! The peer policies come first, so a banned peer or one beyond the peer
+//! limits is refused before any protocol sets up a handler for it.
+
+use super::discovery::Kademlia;
+use super::peers::PeerSlots;
+use super::protocol::{SyncBehaviour, SyncEvent};
+use super::reputation::PeerReputation;
+use libp2p::swarm::NetworkBehaviour;
+use libp2p::{gossipsub, identify, kad};
+use std::convert::Infallible;
+
+/// Everything a node runs on its connections
+#[derive(NetworkBehaviour)]
+#[behaviour(to_swarm = "NodeEvent")]
+pub struct NodeBehaviour {
+    /// Peer scores; banned peers are refused
+    pub reputation: PeerReputation,
+    /// Peer limits, enforced when a connection is established
+    pub slots: PeerSlots,
+    /// Block and transaction propagation
+    pub gossipsub: gossipsub::Behaviour,
+    /// Block sync requests
+    pub sync: SyncBehaviour,
+    /// Peer discovery
+    pub kad: Kademlia,
+    /// Exchange of listen addresses and supported protocols
+    pub identify: identify::Behaviour,
+}
+
+/// Events of the node behaviour
+#[derive(Debug)]
+pub enum NodeEvent {
+    /// Gossipsub event
+    Gossipsub(gossipsub::Event),
+    /// Sync protocol event
+    Sync(SyncEvent),
+    /// Kademlia event
+    Kad(kad::Event),
+    /// Identify event
+    Identify(identify::Event),
+}
+
+impl From<gossipsub::Event> for NodeEvent {
+    fn from(event: gossipsub::Event) -> Self {
+        NodeEvent::Gossipsub(event)
+    }
+}
+
+impl From<SyncEvent> for NodeEvent {
+    fn from(event: SyncEvent) -> Self {
+        NodeEvent::Sync(event)
+    }
+}
+
+impl From<kad::Event> for NodeEvent {
+    fn from(event: kad::Event) -> Self {
+        NodeEvent::Kad(event)
+    }
+}
+
+impl From<identify::Event> for NodeEvent {
+    fn from(event: identify::Event) -> Self {
+        NodeEvent::Identify(event)
+    }
+}
+
+/// The peer policies emit no events
+impl From<Infallible> for NodeEvent {
+    fn from(event: Infallible) -> Self {
+        match event {}
+    }
+}
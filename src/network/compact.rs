@@ -0,0 +1,224 @@
+//! Compact block relay
+//!
+//! Most transactions of a new block are already in the receiver's pool, so
+//! blocks are announced as their header plus a short id per transaction.
+//! The receiver rebuilds the block from its pool and only asks the sender
+//! for the transactions it is missing.
+//!
+//! A short id is the start of the hash of the block hash followed by the
+//! transaction hash. Salting with the block hash means two transactions
+//! colliding in one block almost surely do not collide in the next, and a
+//! collision crafted for one block is useless for any other.
+
+use crate::block::{compute_merkle_root, Block, BlockHeader};
+use crate::transaction::Transaction;
+use crate::types::Hash;
+use std::collections::HashMap;
+
+/// Length of a short transaction id in bytes
+pub const SHORT_ID_LEN: usize = 6;
+
+/// Short id of a transaction within a block
+pub type ShortId = [u8; SHORT_ID_LEN];
+
+/// Get the short id of a transaction in the block with the given hash
+pub fn short_id(block_hash: &Hash, tx_hash: &Hash) -> ShortId {
+    let mut salted = [0u8; 64];
+    salted[..32].copy_from_slice(block_hash);
+    salted[32..].copy_from_slice(tx_hash);
+
+    let mut id = [0u8; SHORT_ID_LEN];
+    id.copy_from_slice(&crate::crypto::hash_data(&salted)[..SHORT_ID_LEN]);
+    id
+}
+
+/// A block announced by its header and the short ids of its transactions
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub struct CompactBlock {
+    /// Header of the block
+    pub header: BlockHeader,
+    /// Short ids of the transactions, in block order
+    pub short_ids: Vec<ShortId>,
+}
+
+impl CompactBlock {
+    /// Build the compact form of a block
+    pub fn from_block(block: &Block) -> Self {
+        let block_hash = block.header.hash();
+        Self {
+            header: block.header.clone(),
+            short_ids: block
+                .transactions
+                .iter()
+                .map(|tx| short_id(&block_hash, &tx.hash()))
+                .collect(),
+        }
+    }
+
+    /// Rebuild as much of the block as the given transactions allow
+    ///
+    /// Short ids matched by more than one of the transactions are left
+    /// missing rather than guessed.
+    pub fn reconstruct<'a>(&self, available: impl IntoIterator<Item = &'a Transaction>) -> PartialBlock {
+        let block_hash = self.header.hash();
+        let mut wanted: HashMap<ShortId, Option<&Transaction>> =
+            self.short_ids.iter().map(|id| (*id, None)).collect();
+        let mut ambiguous = Vec::new();
+        for tx in available {
+            if let Some(slot) = wanted.get_mut(&short_id(&block_hash, &tx.hash())) {
+                if slot.replace(tx).is_some() {
+                    ambiguous.push(short_id(&block_hash, &tx.hash()));
+                }
+            }
+        }
+        for id in ambiguous {
+            wanted.insert(id, None);
+        }
+
+        PartialBlock {
+            header: self.header.clone(),
+            hash: block_hash,
+            transactions: self
+                .short_ids
+                .iter()
+                .map(|id| wanted.get(id).copied().flatten().cloned())
+                .collect(),
+            short_ids: self.short_ids.clone(),
+        }
+    }
+}
+
+/// A block being rebuilt from a compact block
+#[derive(Debug, Clone)]
+pub struct PartialBlock {
+    header: BlockHeader,
+    hash: Hash,
+    short_ids: Vec<ShortId>,
+    transactions: Vec<Option<Transaction>>,
+}
+
+impl PartialBlock {
+    /// Get the hash of the block
+    pub fn hash(&self) -> Hash {
+        self.hash
+    }
+
+    /// Get the header of the block
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    /// Get the indexes of the transactions still missing
+    pub fn missing(&self) -> Vec<u32> {
+        self.transactions
+            .iter()
+            .enumerate()
+            .filter(|(_, tx)| tx.is_none())
+            .map(|(index, _)| index as u32)
+            .collect()
+    }
+
+    /// Check whether every transaction is known
+    pub fn is_complete(&self) -> bool {
+        self.transactions.iter().all(Option::is_some)
+    }
+
+    /// Insert transactions fetched for the given indexes
+    ///
+    /// Fails without changing anything if the transactions do not match
+    /// the indexes' short ids.
+    pub fn fill(&mut self, indexes: &[u32], transactions: Vec<Transaction>) -> Result<(), String> {
+        if indexes.len() != transactions.len() {
+            return Err(format!(
+                "Expected {} transactions, got {}",
+                indexes.len(),
+                transactions.len()
+            ));
+        }
+        for (index, tx) in indexes.iter().zip(&transactions) {
+            let expected = self
+                .short_ids
+                .get(*index as usize)
+                .ok_or_else(|| format!("Transaction index {} out of range", index))?;
+            if short_id(&self.hash, &tx.hash()) != *expected {
+                return Err(format!("Transaction {} does not match its short id", index));
+            }
+        }
+
+        for (index, tx) in indexes.iter().zip(transactions) {
+            self.transactions[*index as usize] = Some(tx);
+        }
+        Ok(())
+    }
+
+    /// Forget every transaction, so the whole block is fetched
+    pub fn clear(&mut self) {
+        self.transactions.iter_mut().for_each(|tx| *tx = None);
+    }
+
+    /// Assemble the block
+    ///
+    /// Returns `None` while transactions are missing or when the transactions
+    /// found do not match the header's merkle root, which happens when a pool
+    /// transaction shares a short id with one of the block's.
+    pub fn to_block(&self) -> Option<Block> {
+        let transactions: Vec<Transaction> = self.transactions.iter().cloned().collect::<Option<_>>()?;
+        let root = compute_merkle_root(&transactions).ok()?;
+        (root == self.header.merkle_root).then(|| Block {
+            header: self.header.clone(),
+            transactions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transactions(count: u64) -> Vec<Transaction> {
+        (0..count)
+            .map(|nonce| Transaction::new([1u8; 32], [2u8; 32], 10, 1, nonce, Vec::new()))
+            .collect()
+    }
+
+    #[test]
+    fn test_reconstruct_from_pool() {
+        let txs = transactions(5);
+        let block = Block::new([0u8; 32], 1, txs.clone(), [3u8; 32]).unwrap();
+        let compact = CompactBlock::from_block(&block);
+        assert!(compact.short_ids.len() * SHORT_ID_LEN < block.serialized_size());
+
+        // Everything is in the pool, along with unrelated transactions
+        let mut pool = transactions(8);
+        pool.reverse();
+        let rebuilt = compact.reconstruct(&pool).to_block().unwrap();
+        assert_eq!(rebuilt.header.hash(), block.header.hash());
+        assert_eq!(rebuilt.transactions.len(), 5);
+
+        // Two are missing and are fetched by index
+        let mut partial = compact.reconstruct(&txs[1..4]);
+        assert_eq!(partial.missing(), vec![0, 4]);
+        assert!(partial.fill(&[0, 4], vec![txs[4].clone(), txs[0].clone()]).is_err());
+        assert!(partial.fill(&[0], vec![txs[0].clone()]).is_ok());
+        assert!(partial.fill(&[9], vec![txs[4].clone()]).is_err());
+        assert!(partial.to_block().is_none());
+        partial.fill(&[4], vec![txs[4].clone()]).unwrap();
+        let rebuilt = partial.to_block().unwrap();
+        assert!(rebuilt.transactions.iter().zip(&txs).all(|(a, b)| a.hash() == b.hash()));
+    }
+
+    #[test]
+    fn test_mismatched_transactions_are_detected() {
+        // A header whose merkle root does not cover the transactions
+        let txs = transactions(3);
+        let mut block = Block::new([0u8; 32], 1, txs.clone(), [3u8; 32]).unwrap();
+        block.header.merkle_root = [9u8; 32];
+
+        let mut partial = CompactBlock::from_block(&block).reconstruct(&txs);
+        assert!(partial.is_complete());
+        assert!(partial.to_block().is_none());
+
+        partial.clear();
+        assert_eq!(partial.missing(), vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,49 @@
+//! Peer discovery
+//!
+//! Nodes find each other through a Kademlia DHT. The DHT is only used for
+//! its routing table: every discovery interval the node looks up a random
+//! peer id, which walks the DHT and turns up peers it did not know yet.
+//! Identify tells each side of a connection the other's listen addresses,
+//! so peers that dialed in can be added to the routing table too.
+
+use super::Error;
+use libp2p::identity::Keypair;
+use libp2p::kad::{self, store::MemoryStore, Mode};
+use libp2p::{identify, StreamProtocol};
+
+/// Kademlia behaviour used for discovery
+pub type Kademlia = kad::Behaviour<MemoryStore>;
+
+/// Get the Kademlia protocol name of the given network
+///
+/// Each network runs its own DHT, so walks never turn up peers of another
+/// network.
+pub fn kad_protocol(network_id: u64) -> Result<StreamProtocol, Error> {
+    StreamProtocol::try_from_owned(format!("/blocana/{}/kad/1", network_id))
+        .map_err(|e| Error::Other(format!("Invalid Kademlia protocol name: {}", e)))
+}
+
+/// Build the Kademlia behaviour used for discovery
+///
+/// The node always acts as a DHT server, since its peers have to be able to
+/// ask it for its routing table even before it learned its external address.
+/// Periodic bootstrapping is turned off, the network thread runs its own
+/// random walks at the configured interval instead. Kademlia still
+/// bootstraps by itself whenever a new peer joins the routing table, so a
+/// freshly started node does not wait a whole interval for its first walk.
+pub fn build_kademlia(keypair: &Keypair, network_id: u64) -> Result<Kademlia, Error> {
+    let mut config = kad::Config::new(kad_protocol(network_id)?);
+    config.set_periodic_bootstrap_interval(None);
+
+    let peer_id = keypair.public().to_peer_id();
+    let mut kademlia = kad::Behaviour::with_config(peer_id, MemoryStore::new(peer_id), config);
+    kademlia.set_mode(Some(Mode::Server));
+    Ok(kademlia)
+}
+
+/// Build the identify behaviour
+pub fn build_identify(keypair: &Keypair, network_id: u64) -> identify::Behaviour {
+    let config = identify::Config::new(format!("/blocana/{}", network_id), keypair.public())
+        .with_agent_version(format!("blocana/{}", env!("CARGO_PKG_VERSION")));
+    identify::Behaviour::new(config)
+}
@@ -0,0 +1,86 @@
+//! Gossipsub topics and message encoding
+//!
+//! Blocks and transactions are published on per-network topics, so nodes of
+//! different networks sharing peers never see each other's messages. Payloads
+//! are the bincode encoding of the transaction, or of the compact form of the
+//! block.
+
+use super::Error;
+use super::compact::CompactBlock;
+use crate::block::Block;
+use crate::transaction::Transaction;
+use libp2p::gossipsub::{self, IdentTopic, MessageAuthenticity, MessageId, ValidationMode};
+use libp2p::identity::Keypair;
+use std::time::Duration;
+
+/// Extra room on top of the block size limit for the gossipsub envelope
+const MESSAGE_OVERHEAD: usize = 64 * 1024;
+
+/// Topics a node publishes to and subscribes on
+#[derive(Debug, Clone)]
+pub struct GossipTopics {
+    /// Topic carrying new blocks in compact form
+    pub blocks: IdentTopic,
+    /// Topic carrying pending transactions
+    pub transactions: IdentTopic,
+}
+
+impl GossipTopics {
+    /// Get the topics of the given network
+    pub fn for_network(network_id: u64) -> Self {
+        Self {
+            blocks: IdentTopic::new(format!("blocana/{}/compact_blocks", network_id)),
+            transactions: IdentTopic::new(format!("blocana/{}/transactions", network_id)),
+        }
+    }
+}
+
+/// Build the gossipsub behaviour used by the node
+///
+/// Messages are signed by the sender's peer key and are only forwarded once
+/// the node has validated them, so invalid blocks and transactions stop at
+/// the first honest peer. Message ids are content hashes, so the same block
+/// published by two nodes is only delivered once.
+pub fn build_behaviour(
+    keypair: &Keypair,
+    heartbeat: Duration,
+    max_block_size: usize,
+) -> Result<gossipsub::Behaviour, Error> {
+    let config = gossipsub::ConfigBuilder::default()
+        .heartbeat_interval(heartbeat)
+        .validation_mode(ValidationMode::Strict)
+        .validate_messages()
+        .message_id_fn(|message| MessageId::from(crate::crypto::hash_data(&message.data).to_vec()))
+        .max_transmit_size(max_block_size + MESSAGE_OVERHEAD)
+        .build()
+        .map_err(|e| Error::Other(format!("Invalid gossipsub configuration: {}", e)))?;
+
+    gossipsub::Behaviour::new(MessageAuthenticity::Signed(keypair.clone()), config)
+        .map_err(|e| Error::Other(e.to_string()))
+}
+
+/// Encode a block in compact form for publishing
+pub fn encode_compact_block(block: &Block) -> Result<Vec<u8>, Error> {
+    bincode::encode_to_vec(CompactBlock::from_block(block), bincode::config::standard())
+        .map_err(|e| Error::Other(format!("Failed to encode block: {}", e)))
+}
+
+/// Decode a compact block received from a peer
+pub fn decode_compact_block(data: &[u8]) -> Option<CompactBlock> {
+    bincode::decode_from_slice(data, bincode::config::standard())
+        .ok()
+        .map(|(block, _)| block)
+}
+
+/// Encode a transaction for publishing
+pub fn encode_transaction(tx: &Transaction) -> Result<Vec<u8>, Error> {
+    bincode::encode_to_vec(tx, bincode::config::standard())
+        .map_err(|e| Error::Other(format!("Failed to encode transaction: {}", e)))
+}
+
+/// Decode a transaction received from a peer
+pub fn decode_transaction(data: &[u8]) -> Option<Transaction> {
+    bincode::decode_from_slice(data, bincode::config::standard())
+        .ok()
+        .map(|(tx, _)| tx)
+}
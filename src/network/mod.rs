@@ -1,18 +1,84 @@
 //! Network functionality for the Blocana blockchain
 //!
-//! This module contains the networking layer implementation.
+//! This module contains the networking layer implementation. Nodes talk to
+//! each other over libp2p (TCP, noise, yamux), spread new blocks and
+//! transactions with gossipsub and fetch missing history with the sync
+//! protocol. Blocks are relayed in compact form and rebuilt from the
+//! transaction pool, so only the transactions a node lacks cross the wire.
+//! New peers are found through Kademlia and remembered in the node's
+//! database, and peers that send invalid data lose reputation until they
+//! are disconnected or banned. The swarm runs on its own thread, so the
+//! manager can be driven from synchronous code such as the CLI.
+
+pub mod behaviour;
+pub mod compact;
+pub mod discovery;
+pub mod gossip;
+pub mod peers;
+pub mod protocol;
+pub mod reputation;
+pub mod sync;
+
+use crate::block::{Block, BlockImportError};
+use crate::transaction::Transaction;
+use crate::Blockchain;
+use behaviour::{NodeBehaviour, NodeEvent};
+use compact::PartialBlock;
+use gossip::GossipTopics;
+use libp2p::futures::StreamExt;
+use libp2p::gossipsub::{self, MessageAcceptance, MessageId};
+use libp2p::request_response::Message;
+use libp2p::swarm::dial_opts::DialOpts;
+use libp2p::swarm::{DialError, SwarmEvent};
+use libp2p::{identify, identity, kad, noise, tcp, yamux, Multiaddr, PeerId, Swarm};
+use peers::{AddressBook, PeerSlots};
+use protocol::{RequestId, ResponseChannel, SyncEvent};
+use reputation::{PeerAction, PeerReputation, PeerScore, Verdict};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use sync::{BlockSync, ChainStatus, SyncRequest, SyncResponse};
+use tokio::sync::mpsc;
+
+/// Interval between gossipsub heartbeats
+const GOSSIP_HEARTBEAT: Duration = Duration::from_secs(1);
+
+/// Interval at which peers are asked for their chain tips
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Most bytes of blocks put into a single sync response
+const MAX_SYNC_RESPONSE_BYTES: usize = protocol::MAX_MESSAGE_SIZE / 2;
+
+/// How long a connection without any traffic is kept open
+const IDLE_CONNECTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Most compact blocks waiting for missing transactions at once
+const MAX_PENDING_BLOCKS: usize = 16;
 
 /// Configuration for the network layer
 #[derive(Debug, Clone)]
 pub struct NetworkConfig {
-    /// Port to listen on
+    /// Port to listen on, 0 to pick a free one
     pub listen_port: u16,
     /// Maximum number of peers
     pub max_peers: usize,
-    /// Bootstrap nodes
+    /// How many of the `max_peers` slots are kept for peers this node dials
+    ///
+    /// The remaining slots take peers dialing in.
+    pub max_outbound_peers: usize,
+    /// Bootstrap nodes as multiaddresses, e.g. `/ip4/10.0.0.1/tcp/8080`
     pub bootstrap_nodes: Vec<String>,
     /// Peer discovery interval in seconds
     pub discovery_interval_sec: u64,
+    /// Time a peer has to answer a sync request, in seconds
+    pub request_timeout_sec: u64,
+    /// Score at which a peer is disconnected
+    pub score_disconnect_threshold: f64,
+    /// Score at which a peer is disconnected and banned
+    pub score_ban_threshold: f64,
+    /// How long a peer stays banned, in seconds
+    pub ban_duration_sec: u64,
 }
 
 impl Default for NetworkConfig {
@@ -20,51 +86,976 @@ impl Default for NetworkConfig {
         Self {
             listen_port: 8080,
             max_peers: 50,
+            max_outbound_peers: 10,
             bootstrap_nodes: vec![],
             discovery_interval_sec: 60,
+            request_timeout_sec: 10,
+            score_disconnect_threshold: -50.0,
+            score_ban_threshold: -100.0,
+            ban_duration_sec: 3600,
         }
     }
 }
 
+/// Snapshot of the swarm, updated by the network thread
+#[derive(Debug, Default)]
+struct NetworkStatus {
+    /// Addresses the node is listening on
+    listen_addresses: Vec<Multiaddr>,
+    /// Peers with an open connection
+    connected_peers: HashSet<PeerId>,
+    /// Peers subscribed to at least one of our topics
+    gossip_peers: HashSet<PeerId>,
+    /// Reputation of the connected, scored and banned peers
+    peer_scores: Vec<PeerScore>,
+}
+
+/// Requests sent from the manager to the network thread
+enum Command {
+    /// Publish an encoded block
+    PublishBlock(Vec<u8>),
+    /// Publish an encoded transaction
+    PublishTransaction(Vec<u8>),
+    /// Connect to a peer
+    Dial(Multiaddr),
+    /// Disconnect a peer and refuse it for a while
+    Ban(PeerId, Duration),
+    /// Accept a banned peer again
+    Unban(PeerId),
+}
+
 /// Network manager
+///
+/// Owns the node's peer identity and the thread running the libp2p swarm.
+/// Blocks and transactions received from peers are handed to the shared
+/// [`Blockchain`], and are only relayed further once it accepted them. While
+/// peers report a longer chain, the missing blocks are downloaded from them.
 pub struct NetworkManager {
     /// Network configuration
     config: NetworkConfig,
     /// Is the network running
     running: bool,
+    /// Peer identity of this node
+    keypair: identity::Keypair,
+    /// Channel to the network thread while it runs
+    commands: Option<mpsc::UnboundedSender<Command>>,
+    /// Handle of the network thread
+    worker: Option<thread::JoinHandle<()>>,
+    /// State of the swarm as last seen by the network thread
+    status: Arc<Mutex<NetworkStatus>>,
 }
 
 impl NetworkManager {
-    /// Create a new network manager
+    /// Create a new network manager with a freshly generated peer identity
     pub fn new(config: &NetworkConfig) -> Result<Self, Error> {
         Ok(Self {
             config: config.clone(),
             running: false,
+            keypair: identity::Keypair::generate_ed25519(),
+            commands: None,
+            worker: None,
+            status: Arc::new(Mutex::new(NetworkStatus::default())),
         })
     }
-    
+
+    /// Get the peer id of this node
+    pub fn local_peer_id(&self) -> PeerId {
+        self.keypair.public().to_peer_id()
+    }
+
     /// Start the network services
-    pub fn start(&mut self) -> Result<(), Error> {
+    ///
+    /// Listens on the configured port, dials the bootstrap nodes and the
+    /// peers remembered from earlier runs and starts relaying blocks and
+    /// transactions for the chain's network. Syncing starts from the chain's
+    /// current tip.
+    pub fn start(&mut self, chain: Arc<Mutex<Blockchain>>) -> Result<(), Error> {
         if self.running {
             return Err(Error::AlreadyRunning);
         }
-        
-        // In a real implementation, we would start libp2p here
-        println!("Starting network on port {}", self.config.listen_port);
-        
+
+        let bootstrap = self
+            .config
+            .bootstrap_nodes
+            .iter()
+            .map(|address| parse_address(address))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (network_id, max_block_size, local, book) = {
+            let chain = chain.lock().map_err(|_| Error::Other("Blockchain lock poisoned".into()))?;
+            let (height, hash) = chain
+                .chain_tip()
+                .map_err(|e| Error::Other(format!("Failed to read chain tip: {}", e)))?;
+            (
+                chain.config.network_id,
+                chain.config.max_block_size,
+                ChainStatus { height, hash },
+                AddressBook::load(chain.storage()),
+            )
+        };
+
+        let listen_address: Multiaddr = format!("/ip4/0.0.0.0/tcp/{}", self.config.listen_port)
+            .parse()
+            .map_err(|e| Error::InvalidAddress(format!("{}", e)))?;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| Error::Other(format!("Failed to start network runtime: {}", e)))?;
+
+        // The swarm has to be created inside the runtime, so it is built on
+        // the network thread and setup errors are sent back from there
+        let (ready_tx, ready_rx) = std::sync::mpsc::channel();
+        let (command_tx, command_rx) = mpsc::unbounded_channel();
+        let keypair = self.keypair.clone();
+        let status = self.status.clone();
+        let config = self.config.clone();
+
+        let worker = thread::Builder::new()
+            .name("blocana-network".into())
+            .spawn(move || {
+                runtime.block_on(async move {
+                    let mut swarm = match build_swarm(keypair, &config, network_id, max_block_size)
+                        .and_then(|mut swarm| {
+                            swarm
+                                .listen_on(listen_address)
+                                .map_err(|e| Error::ConnectionFailed(e.to_string()))?;
+                            Ok(swarm)
+                        }) {
+                        Ok(swarm) => swarm,
+                        Err(e) => {
+                            let _ = ready_tx.send(Err(e));
+                            return;
+                        }
+                    };
+                    let _ = ready_tx.send(Ok(()));
+
+                    for address in bootstrap {
+                        if let Err(e) = swarm.dial(address.clone()) {
+                            log::warn!("Failed to dial bootstrap node {}: {}", address, e);
+                        }
+                    }
+
+                    let mut worker = NetworkWorker {
+                        swarm,
+                        topics: GossipTopics::for_network(network_id),
+                        chain,
+                        network_id,
+                        status,
+                        sync: BlockSync::new(local),
+                        book,
+                        compact: HashMap::new(),
+                    };
+                    worker.reconnect();
+                    let discovery_interval = Duration::from_secs(config.discovery_interval_sec.max(1));
+                    worker.run(command_rx, discovery_interval).await;
+                });
+            })
+            .map_err(|e| Error::Other(format!("Failed to spawn network thread: {}", e)))?;
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                let _ = worker.join();
+                return Err(e);
+            }
+            Err(_) => {
+                let _ = worker.join();
+                return Err(Error::Other("Network thread exited during startup".into()));
+            }
+        }
+
+        log::info!(
+            "Network started on port {} as {}",
+            self.config.listen_port,
+            self.local_peer_id()
+        );
+
+        self.commands = Some(command_tx);
+        self.worker = Some(worker);
         self.running = true;
         Ok(())
     }
-    
+
     /// Stop the network services
     pub fn stop(&mut self) -> Result<(), Error> {
         if !self.running {
             return Err(Error::NotRunning);
         }
-        
+
+        // Dropping the sender ends the network thread's loop
+        self.commands = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        *self.status.lock().unwrap() = NetworkStatus::default();
+
         self.running = false;
         Ok(())
     }
+
+    /// Check whether the network services are running
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Announce a block to the network
+    pub fn publish_block(&self, block: &Block) -> Result<(), Error> {
+        self.send(Command::PublishBlock(gossip::encode_compact_block(block)?))
+    }
+
+    /// Announce a transaction to the network
+    pub fn publish_transaction(&self, tx: &Transaction) -> Result<(), Error> {
+        self.send(Command::PublishTransaction(gossip::encode_transaction(tx)?))
+    }
+
+    /// Connect to a peer by multiaddress
+    pub fn dial(&self, address: &str) -> Result<(), Error> {
+        self.send(Command::Dial(parse_address(address)?))
+    }
+
+    /// Disconnect a peer and refuse its connections for `duration`
+    pub fn ban_peer(&self, peer: &str, duration: Duration) -> Result<(), Error> {
+        self.send(Command::Ban(parse_peer_id(peer)?, duration))
+    }
+
+    /// Lift a peer's ban and reset its score
+    pub fn unban_peer(&self, peer: &str) -> Result<(), Error> {
+        self.send(Command::Unban(parse_peer_id(peer)?))
+    }
+
+    /// Get the reputation of the connected peers and of every scored or banned peer
+    ///
+    /// The worst scores come first.
+    pub fn peer_scores(&self) -> Vec<PeerScore> {
+        self.status.lock().unwrap().peer_scores.clone()
+    }
+
+    /// Get the addresses the node is listening on
+    pub fn listen_addresses(&self) -> Vec<Multiaddr> {
+        self.status.lock().unwrap().listen_addresses.clone()
+    }
+
+    /// Get the peers the node is connected to
+    pub fn connected_peers(&self) -> Vec<PeerId> {
+        self.status.lock().unwrap().connected_peers.iter().copied().collect()
+    }
+
+    /// Get the connected peers subscribed to the node's block or transaction topic
+    pub fn gossip_peers(&self) -> Vec<PeerId> {
+        self.status.lock().unwrap().gossip_peers.iter().copied().collect()
+    }
+
+    /// Hand a command to the network thread
+    fn send(&self, command: Command) -> Result<(), Error> {
+        let commands = self.commands.as_ref().ok_or(Error::NotRunning)?;
+        commands.send(command).map_err(|_| Error::NotRunning)
+    }
+}
+
+impl Drop for NetworkManager {
+    fn drop(&mut self) {
+        if self.running {
+            let _ = self.stop();
+        }
+    }
+}
+
+/// Parse a peer multiaddress from the configuration or the command line
+fn parse_address(address: &str) -> Result<Multiaddr, Error> {
+    address
+        .parse()
+        .map_err(|e| Error::InvalidAddress(format!("{}: {}", address, e)))
+}
+
+/// Parse a peer id given by the user
+fn parse_peer_id(peer: &str) -> Result<PeerId, Error> {
+    peer.parse()
+        .map_err(|e| Error::InvalidPeerId(format!("{}: {}", peer, e)))
+}
+
+/// Build the swarm and subscribe to the block and transaction topics
+fn build_swarm(
+    keypair: identity::Keypair,
+    config: &NetworkConfig,
+    network_id: u64,
+    max_block_size: usize,
+) -> Result<Swarm<NodeBehaviour>, Error> {
+    let behaviour = NodeBehaviour {
+        reputation: PeerReputation::new(
+            config.score_disconnect_threshold,
+            config.score_ban_threshold,
+            Duration::from_secs(config.ban_duration_sec),
+        ),
+        slots: PeerSlots::new(config.max_peers, config.max_outbound_peers),
+        gossipsub: gossip::build_behaviour(&keypair, GOSSIP_HEARTBEAT, max_block_size)?,
+        sync: protocol::build_behaviour(Duration::from_secs(config.request_timeout_sec)),
+        kad: discovery::build_kademlia(&keypair, network_id)?,
+        identify: discovery::build_identify(&keypair, network_id),
+    };
+    let topics = GossipTopics::for_network(network_id);
+
+    let mut swarm = libp2p::SwarmBuilder::with_existing_identity(keypair)
+        .with_tokio()
+        .with_tcp(tcp::Config::default(), noise::Config::new, yamux::Config::default)
+        .map_err(|e| Error::Other(format!("Failed to set up transport: {}", e)))?
+        .with_behaviour(|_| behaviour)
+        .map_err(|e| Error::Other(e.to_string()))?
+        .with_swarm_config(|config| config.with_idle_connection_timeout(IDLE_CONNECTION_TIMEOUT))
+        .build();
+
+    for topic in [&topics.blocks, &topics.transactions] {
+        swarm
+            .behaviour_mut()
+            .gossipsub
+            .subscribe(topic)
+            .map_err(|e| Error::Other(format!("Failed to subscribe to {}: {}", topic, e)))?;
+    }
+
+    Ok(swarm)
+}
+
+/// State owned by the network thread
+struct NetworkWorker {
+    swarm: Swarm<NodeBehaviour>,
+    topics: GossipTopics,
+    chain: Arc<Mutex<Blockchain>>,
+    network_id: u64,
+    status: Arc<Mutex<NetworkStatus>>,
+    sync: BlockSync,
+    book: AddressBook,
+    /// Compact blocks waiting for transactions, by the id of their request
+    compact: HashMap<RequestId, PendingBlock>,
+}
+
+/// A compact block waiting for the transactions missing from the pool
+struct PendingBlock {
+    partial: PartialBlock,
+    /// Peer that relayed the block, which is asked for the transactions
+    source: PeerId,
+    /// Gossip message of the block, validated once the block is complete
+    message_id: MessageId,
+    /// Positions of the transactions asked for
+    requested: Vec<u32>,
+    /// Whether the whole block was asked for because the pool's
+    /// transactions did not match it
+    refetched: bool,
+}
+
+impl NetworkWorker {
+    /// Drive the swarm until the manager goes away
+    async fn run(mut self, mut commands: mpsc::UnboundedReceiver<Command>, discovery_interval: Duration) {
+        let mut status_timer = tokio::time::interval(STATUS_INTERVAL);
+        let mut discovery_timer = tokio::time::interval(discovery_interval);
+        loop {
+            tokio::select! {
+                command = commands.recv() => match command {
+                    Some(command) => self.handle_command(command),
+                    None => break,
+                },
+                event = self.swarm.select_next_some() => {
+                    self.handle_event(event);
+                    self.update_status();
+                }
+                _ = status_timer.tick() => {
+                    self.sync.refresh_status();
+                    self.swarm.behaviour_mut().reputation.prune(Instant::now());
+                }
+                _ = discovery_timer.tick() => self.discover(),
+            }
+            self.drive_sync();
+        }
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        let (topic, data) = match command {
+            Command::PublishBlock(data) => (self.topics.blocks.clone(), data),
+            Command::PublishTransaction(data) => (self.topics.transactions.clone(), data),
+            Command::Dial(address) => {
+                if let Err(e) = self.swarm.dial(address.clone()) {
+                    log::warn!("Failed to dial {}: {}", address, e);
+                }
+                return;
+            }
+            Command::Ban(peer, duration) => {
+                log::info!("Banning {} for {:?}", peer, duration);
+                self.swarm.behaviour_mut().reputation.ban(&peer, duration, Instant::now());
+                let _ = self.swarm.disconnect_peer_id(peer);
+                self.update_status();
+                return;
+            }
+            Command::Unban(peer) => {
+                if self.swarm.behaviour_mut().reputation.unban(&peer) {
+                    log::info!("Lifted the ban of {}", peer);
+                }
+                self.update_status();
+                return;
+            }
+        };
+
+        match self.swarm.behaviour_mut().gossipsub.publish(topic.clone(), data) {
+            Ok(_) => {}
+            // Nobody to tell yet; peers catch up once they connect
+            Err(gossipsub::PublishError::InsufficientPeers) => {
+                log::debug!("No peers subscribed to {}", topic);
+            }
+            Err(gossipsub::PublishError::Duplicate) => {}
+            Err(e) => log::warn!("Failed to publish on {}: {}", topic, e),
+        }
+    }
+
+    fn handle_event(&mut self, event: SwarmEvent<NodeEvent>) {
+        match event {
+            SwarmEvent::NewListenAddr { address, .. } => {
+                log::info!("Listening on {}", address);
+                self.status.lock().unwrap().listen_addresses.push(address);
+            }
+            SwarmEvent::ExpiredListenAddr { address, .. } => {
+                self.status
+                    .lock()
+                    .unwrap()
+                    .listen_addresses
+                    .retain(|known| known != &address);
+            }
+            SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
+                log::debug!("Connected to {} at {}", peer_id, endpoint.get_remote_address());
+                self.sync.add_peer(peer_id);
+            }
+            SwarmEvent::ConnectionClosed { peer_id, num_established: 0, .. } => {
+                self.sync.remove_peer(&peer_id);
+            }
+            SwarmEvent::IncomingConnectionError { send_back_addr, error, .. } => {
+                log::debug!("Refused connection from {}: {}", send_back_addr, error);
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => {
+                log::debug!("Failed to connect to {:?}: {}", peer_id, error);
+                // The address book entry belongs to an identity that is gone
+                if let (Some(peer), DialError::WrongPeerId { .. }) = (peer_id, &error) {
+                    self.book.remove(self.chain.lock().unwrap().storage(), &peer);
+                }
+            }
+            SwarmEvent::Behaviour(NodeEvent::Kad(event)) => self.handle_kad_event(event),
+            SwarmEvent::Behaviour(NodeEvent::Identify(identify::Event::Received { peer_id, info, .. })) => {
+                self.handle_identify(peer_id, info);
+            }
+            SwarmEvent::Behaviour(NodeEvent::Gossipsub(gossipsub::Event::Message {
+                propagation_source,
+                message_id,
+                message,
+            })) => {
+                let acceptance = if message.topic == self.topics.blocks.hash() {
+                    self.handle_compact_block(&propagation_source, &message_id, &message.data)
+                } else if message.topic == self.topics.transactions.hash() {
+                    Some(self.handle_transaction(&propagation_source, &message.data))
+                } else {
+                    Some(MessageAcceptance::Ignore)
+                };
+
+                if let Some(acceptance) = acceptance {
+                    self.swarm.behaviour_mut().gossipsub.report_message_validation_result(
+                        &message_id,
+                        &propagation_source,
+                        acceptance,
+                    );
+                }
+            }
+            SwarmEvent::Behaviour(NodeEvent::Sync(event)) => self.handle_sync_event(event),
+            _ => {}
+        }
+    }
+
+    /// Dial the peers remembered from earlier runs
+    ///
+    /// They are also added to the routing table, so the first random walk
+    /// has somewhere to start from even when no peer is reachable yet.
+    fn reconnect(&mut self) {
+        let known = self.book.most_recent();
+        if known.is_empty() {
+            return;
+        }
+        log::info!("Reconnecting to {} known peers", known.len());
+
+        let mut free = self.swarm.behaviour().slots.free_outbound();
+        for (peer, addresses) in known {
+            for address in &addresses {
+                self.swarm.behaviour_mut().kad.add_address(&peer, address.clone());
+            }
+            if free > 0 {
+                free -= 1;
+                self.dial_peer(peer, addresses);
+            }
+        }
+    }
+
+    /// Look up a random peer id, turning up peers not known yet
+    fn discover(&mut self) {
+        log::debug!(
+            "Starting peer discovery with {} connected peers",
+            self.swarm.connected_peers().count()
+        );
+        self.swarm.behaviour_mut().kad.get_closest_peers(PeerId::random());
+    }
+
+    fn handle_kad_event(&mut self, event: kad::Event) {
+        match event {
+            kad::Event::OutboundQueryProgressed {
+                result: kad::QueryResult::GetClosestPeers(result),
+                ..
+            } => {
+                let peers = match result {
+                    Ok(kad::GetClosestPeersOk { peers, .. }) => peers,
+                    Err(kad::GetClosestPeersError::Timeout { peers, .. }) => peers,
+                };
+                self.dial_discovered(peers);
+            }
+            kad::Event::RoutingUpdated { peer, is_new_peer: true, .. } => {
+                log::debug!("Added {} to the routing table", peer);
+            }
+            _ => {}
+        }
+    }
+
+    /// Dial peers found by a random walk while outbound slots are free
+    fn dial_discovered(&mut self, peers: Vec<kad::PeerInfo>) {
+        let now = Instant::now();
+        let mut free = self.swarm.behaviour().slots.free_outbound();
+        for peer in peers {
+            if free == 0 {
+                break;
+            }
+            if peer.addrs.is_empty()
+                || peer.peer_id == *self.swarm.local_peer_id()
+                || self.swarm.is_connected(&peer.peer_id)
+                || self.swarm.behaviour().reputation.is_banned(&peer.peer_id, now)
+            {
+                continue;
+            }
+            free -= 1;
+            self.dial_peer(peer.peer_id, peer.addrs);
+        }
+    }
+
+    fn dial_peer(&mut self, peer: PeerId, addresses: Vec<Multiaddr>) {
+        if let Err(e) = self.swarm.dial(DialOpts::peer_id(peer).addresses(addresses).build()) {
+            log::debug!("Failed to dial {}: {}", peer, e);
+        }
+    }
+
+    /// Learn the listen addresses of a peer
+    ///
+    /// Peers speaking our network's Kademlia protocol join the routing table
+    /// and the address book, whichever side opened the connection.
+    fn handle_identify(&mut self, peer: PeerId, info: identify::Info) {
+        let speaks_kad = discovery::kad_protocol(self.network_id)
+            .map(|protocol| info.protocols.contains(&protocol))
+            .unwrap_or(false);
+        if !speaks_kad {
+            return;
+        }
+
+        for address in &info.listen_addrs {
+            self.swarm.behaviour_mut().kad.add_address(&peer, address.clone());
+        }
+        let chain = self.chain.lock().unwrap();
+        self.book.insert(chain.storage(), peer, &info.listen_addrs, unix_time_ms());
+    }
+
+    /// Rebuild a compact block received from a peer
+    ///
+    /// Returns `None` while the transactions missing from the pool are
+    /// fetched from the peer; the message is validated once they arrive.
+    fn handle_compact_block(
+        &mut self,
+        source: &PeerId,
+        message_id: &MessageId,
+        data: &[u8],
+    ) -> Option<MessageAcceptance> {
+        let Some(compact) = gossip::decode_compact_block(data) else {
+            self.report(source, PeerAction::InvalidBlock);
+            return Some(MessageAcceptance::Reject);
+        };
+
+        let partial = {
+            let chain = self.chain.lock().unwrap();
+            if let Ok(Some(_)) = chain.storage().get_block_meta(&compact.header.hash()) {
+                return Some(MessageAcceptance::Ignore);
+            }
+            compact.reconstruct(chain.pool().get_all_transactions())
+        };
+        self.advance_compact_block(*source, message_id.clone(), partial, false)
+    }
+
+    /// Import a rebuilt block, or ask its sender for what is still missing
+    ///
+    /// If the transactions taken from the pool do not match the header, one
+    /// of them shares a short id with a transaction of the block, so the
+    /// whole block is fetched once before the sender is blamed.
+    fn advance_compact_block(
+        &mut self,
+        source: PeerId,
+        message_id: MessageId,
+        mut partial: PartialBlock,
+        refetched: bool,
+    ) -> Option<MessageAcceptance> {
+        let missing = partial.missing();
+        if !missing.is_empty() {
+            if self.compact.len() >= MAX_PENDING_BLOCKS {
+                log::debug!("Too many incomplete blocks, ignored block from {}", source);
+                return Some(MessageAcceptance::Ignore);
+            }
+            let request = SyncRequest::BlockTransactions {
+                block_hash: partial.hash(),
+                indexes: missing.clone(),
+            };
+            let id = self.swarm.behaviour_mut().sync.send_request(&source, request);
+            self.compact.insert(
+                id,
+                PendingBlock {
+                    partial,
+                    source,
+                    message_id,
+                    requested: missing,
+                    refetched,
+                },
+            );
+            return None;
+        }
+
+        match partial.to_block() {
+            Some(block) => Some(self.handle_block(&source, block)),
+            None if !refetched => {
+                log::debug!(
+                    "Transactions from the pool do not match block {}, fetching all",
+                    hex::encode(&partial.hash()[0..4])
+                );
+                partial.clear();
+                self.advance_compact_block(source, message_id, partial, true)
+            }
+            None => {
+                log::warn!("Rejected block from {}: transactions do not match the merkle root", source);
+                self.report(&source, PeerAction::InvalidBlock);
+                Some(MessageAcceptance::Reject)
+            }
+        }
+    }
+
+    /// Complete a compact block with the transactions its sender returned
+    fn complete_compact_block(&mut self, pending: PendingBlock, response: SyncResponse) {
+        let PendingBlock {
+            mut partial,
+            source,
+            message_id,
+            requested,
+            refetched,
+        } = pending;
+
+        let filled = match response {
+            SyncResponse::Transactions(transactions) => partial.fill(&requested, transactions),
+            _ => Err("Response does not match the request".to_string()),
+        };
+        let acceptance = match filled {
+            Ok(()) => self.advance_compact_block(source, message_id.clone(), partial, refetched),
+            Err(e) => {
+                log::warn!("Bad block transactions from {}: {}", source, e);
+                self.report(&source, PeerAction::BadSyncResponse);
+                Some(MessageAcceptance::Ignore)
+            }
+        };
+
+        if let Some(acceptance) = acceptance {
+            self.swarm
+                .behaviour_mut()
+                .gossipsub
+                .report_message_validation_result(&message_id, &source, acceptance);
+        }
+    }
+
+    /// Import a block received from a peer
+    ///
+    /// Blocks that are invalid in themselves are rejected, which penalises
+    /// the sender. Blocks refused for local reasons, such as an unknown
+    /// parent, are not relayed but do not count against the peer.
+    fn handle_block(&mut self, source: &PeerId, block: Block) -> MessageAcceptance {
+        let announced = ChainStatus {
+            height: block.header.height,
+            hash: block.header.hash(),
+        };
+
+        let result = self.chain.lock().unwrap().import_block(block);
+        self.update_local_tip();
+        match result {
+            Ok(_) => {
+                self.report(source, PeerAction::ValidBlock);
+                MessageAcceptance::Accept
+            }
+            Err(e @ BlockImportError::UnknownParent { .. }) => {
+                // We are behind; the sender has at least this block
+                log::debug!("Ignored block from the network: {}", e);
+                self.sync.on_status(source, announced);
+                MessageAcceptance::Ignore
+            }
+            Err(e) if e.is_invalid_block() => {
+                log::warn!("Rejected block from {}: {}", source, e);
+                self.report(source, PeerAction::InvalidBlock);
+                MessageAcceptance::Reject
+            }
+            Err(e) => {
+                log::debug!("Ignored block from the network: {}", e);
+                MessageAcceptance::Ignore
+            }
+        }
+    }
+
+    /// Add a transaction received from a peer to the pool
+    ///
+    /// A transaction the pool refuses may still be valid, for example when
+    /// it is already pending, so only a bad signature or a foreign network
+    /// gets it rejected. Refusals the sender is to blame for still cost it
+    /// reputation.
+    fn handle_transaction(&mut self, source: &PeerId, data: &[u8]) -> MessageAcceptance {
+        let tx = match gossip::decode_transaction(data) {
+            Some(tx) if tx.verify_for_network(self.network_id).is_ok() => tx,
+            _ => {
+                self.report(source, PeerAction::InvalidTransaction);
+                return MessageAcceptance::Reject;
+            }
+        };
+
+        let result = self.chain.lock().unwrap().submit_transaction_detailed(tx);
+        match result {
+            Ok(_) => {
+                self.report(source, PeerAction::ValidTransaction);
+                MessageAcceptance::Accept
+            }
+            Err(e) => {
+                log::debug!("Ignored transaction from {}: {}", source, e.log_context());
+                if let Some(action) = PeerAction::for_transaction_error(&e) {
+                    self.report(source, action);
+                }
+                MessageAcceptance::Ignore
+            }
+        }
+    }
+
+    /// Update a peer's reputation, disconnecting or banning it if it fell too low
+    fn report(&mut self, peer: &PeerId, action: PeerAction) {
+        let verdict = self
+            .swarm
+            .behaviour_mut()
+            .reputation
+            .report(peer, action, Instant::now());
+        match verdict {
+            Verdict::Keep => {}
+            Verdict::Disconnect => {
+                log::warn!("Disconnecting {} for its low score", peer);
+                let _ = self.swarm.disconnect_peer_id(*peer);
+            }
+            Verdict::Ban => {
+                log::warn!("Banning {} for its low score", peer);
+                let _ = self.swarm.disconnect_peer_id(*peer);
+            }
+        }
+    }
+
+    /// Serve a sync request or feed a sync response to the downloader
+    fn handle_sync_event(&mut self, event: SyncEvent) {
+        match event {
+            SyncEvent::Message { peer, message, .. } => match message {
+                Message::Request { request, channel, .. } => self.serve(&peer, request, channel),
+                Message::Response { request_id, response } => {
+                    if let Some(pending) = self.compact.remove(&request_id) {
+                        self.complete_compact_block(pending, response);
+                        return;
+                    }
+                    let result = {
+                        let chain = self.chain.lock().unwrap();
+                        self.sync.on_response(&peer, request_id, response, |height| {
+                            chain.storage().get_block_hash_by_height(height).ok()
+                        })
+                    };
+                    if let Err(e) = result {
+                        log::warn!("Bad sync response from {}: {}", peer, e);
+                        self.report(&peer, PeerAction::BadSyncResponse);
+                    }
+                    self.import_synced_blocks();
+                }
+            },
+            SyncEvent::OutboundFailure { peer, request_id, error, .. } if self.compact.contains_key(&request_id) => {
+                log::debug!("Fetching block transactions from {} failed: {}", peer, error);
+                if let Some(pending) = self.compact.remove(&request_id) {
+                    self.swarm.behaviour_mut().gossipsub.report_message_validation_result(
+                        &pending.message_id,
+                        &pending.source,
+                        MessageAcceptance::Ignore,
+                    );
+                }
+            }
+            SyncEvent::OutboundFailure { peer, request_id, error, .. } => {
+                log::debug!("Sync request {} to {} failed: {}", request_id, peer, error);
+                if self.sync.on_failure(&peer, request_id) {
+                    log::warn!("Disconnecting {} after repeated sync failures", peer);
+                    let _ = self.swarm.disconnect_peer_id(peer);
+                }
+            }
+            SyncEvent::InboundFailure { peer, error, .. } => {
+                log::debug!("Failed to answer sync request from {}: {}", peer, error);
+            }
+            SyncEvent::ResponseSent { .. } => {}
+        }
+    }
+
+    /// Answer a sync request from the local chain
+    fn serve(&mut self, peer: &PeerId, request: SyncRequest, channel: ResponseChannel<SyncResponse>) {
+        let chain = self.chain.lock().unwrap();
+        let storage = chain.storage();
+        let tip = match chain.chain_tip() {
+            Ok((height, hash)) => ChainStatus { height, hash },
+            Err(e) => {
+                log::warn!("Cannot serve sync request: {}", e);
+                return;
+            }
+        };
+
+        let response = match request {
+            SyncRequest::Status(status) => {
+                self.sync.on_status(peer, status);
+                Ok(SyncResponse::Status(tip))
+            }
+            SyncRequest::Headers { start, count } => match requested_range(start, count, sync::HEADERS_PER_REQUEST) {
+                Some(end) => storage.get_headers_in_range(start, end).map(SyncResponse::Headers),
+                None => Ok(SyncResponse::Headers(Vec::new())),
+            },
+            SyncRequest::Blocks { start, count } => match requested_range(start, count, sync::BLOCKS_PER_REQUEST) {
+                Some(end) => storage
+                    .get_blocks_in_range(start, end)
+                    .map(|blocks| SyncResponse::Blocks(limit_size(blocks))),
+                None => Ok(SyncResponse::Blocks(Vec::new())),
+            },
+            SyncRequest::BlockTransactions { block_hash, indexes } => storage
+                .get_block(&block_hash)
+                .map(|block| SyncResponse::Transactions(pick_transactions(block, &indexes))),
+        };
+
+        match response {
+            Ok(response) => {
+                let _ = self.swarm.behaviour_mut().sync.send_response(channel, response);
+            }
+            Err(e) => log::warn!("Cannot serve sync request: {}", e),
+        }
+    }
+
+    /// Send the requests the downloader wants sent
+    fn drive_sync(&mut self) {
+        let behaviour = self.swarm.behaviour_mut();
+        self.sync
+            .next_requests(|peer, request| behaviour.sync.send_request(peer, request));
+    }
+
+    /// Import the downloaded blocks that are next in line
+    fn import_synced_blocks(&mut self) {
+        let ready = self.sync.take_ready();
+        if ready.is_empty() {
+            return;
+        }
+
+        let mut chain = self.chain.lock().unwrap();
+        let mut imported = 0;
+        let mut offender = None;
+        for (peer, block) in ready {
+            match chain.import_block(block) {
+                Ok(_) | Err(BlockImportError::AlreadyKnown { .. }) => imported += 1,
+                Err(e) => {
+                    log::warn!("Failed to import synced block from {}: {}", peer, e);
+                    offender = e.is_invalid_block().then_some(peer);
+                    self.sync.import_failed(offender.as_ref());
+                    break;
+                }
+            }
+        }
+        drop(chain);
+
+        if let Some(peer) = offender {
+            self.report(&peer, PeerAction::InvalidBlock);
+        }
+        self.update_local_tip();
+        log::info!(
+            "Synced {} blocks, now at height {} of {}",
+            imported,
+            self.sync.local().height,
+            self.sync.best_peer_height()
+        );
+    }
+
+    /// Tell the downloader where the local chain is now
+    fn update_local_tip(&mut self) {
+        if let Ok((height, hash)) = self.chain.lock().unwrap().chain_tip() {
+            self.sync.set_local(ChainStatus { height, hash });
+        }
+    }
+
+    /// Publish the current peer sets to the manager
+    fn update_status(&self) {
+        let mut status = self.status.lock().unwrap();
+        status.connected_peers = self.swarm.connected_peers().copied().collect();
+        status.gossip_peers = self
+            .swarm
+            .behaviour()
+            .gossipsub
+            .all_peers()
+            .filter(|(_, topics)| !topics.is_empty())
+            .map(|(peer, _)| *peer)
+            .collect();
+        status.peer_scores = self
+            .swarm
+            .behaviour()
+            .reputation
+            .snapshot(self.swarm.connected_peers(), Instant::now());
+    }
+}
+
+/// Get the current time in milliseconds since the UNIX epoch
+fn unix_time_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Get the last height of a sync request, capped at `max` items
+fn requested_range(start: u64, count: u32, max: u32) -> Option<u64> {
+    let count = count.min(max);
+    (count > 0).then(|| start.saturating_add(count as u64 - 1))
+}
+
+/// Get the transactions of a block at the given positions
+///
+/// Nothing is returned for an unknown block, for a position out of range or
+/// for more positions than the block has transactions.
+fn pick_transactions(block: Option<Block>, indexes: &[u32]) -> Vec<Transaction> {
+    match block {
+        Some(block) if indexes.len() <= block.transactions.len() => indexes
+            .iter()
+            .map(|index| block.transactions.get(*index as usize).cloned())
+            .collect::<Option<Vec<_>>>()
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// Keep the first blocks of a sync response that fit in a message
+///
+/// At least one block is always kept, so even a full block can be synced.
+fn limit_size(blocks: Vec<Block>) -> Vec<Block> {
+    let mut total = 0;
+    let mut kept = Vec::with_capacity(blocks.len());
+    for block in blocks {
+        total += block.serialized_size();
+        if !kept.is_empty() && total > MAX_SYNC_RESPONSE_BYTES {
+            break;
+        }
+        kept.push(block);
+    }
+    kept
 }
 
 /// Error types for network operations
@@ -76,6 +1067,8 @@ pub enum Error {
     NotRunning,
     /// Invalid address
     InvalidAddress(String),
+    /// Invalid peer id
+    InvalidPeerId(String),
     /// Connection failed
     ConnectionFailed(String),
     /// Other errors
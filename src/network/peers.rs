@@ -0,0 +1,309 @@
+//! Peer slots and the peer address book
+//!
+//! A node keeps at most `max_peers` peers. Part of them are outbound slots,
+//! filled by peers the node dialed itself, the rest take peers that dialed
+//! in. Keeping slots for its own choice of peers means a node flooded with
+//! inbound connections still talks to peers it picked.
+//!
+//! Peers the node has been connected to are remembered in an address book
+//! kept in the node's database, so after a restart it can reconnect without
+//! any bootstrap node.
+
+use crate::storage::{BlockchainStorage, PeerRecord};
+use libp2p::core::transport::PortUse;
+use libp2p::core::Endpoint;
+use libp2p::swarm::{
+    dummy, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler, THandlerInEvent,
+    THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::task::{Context, Poll};
+
+/// Most peers kept in the address book
+pub const MAX_KNOWN_PEERS: usize = 256;
+
+/// Most addresses remembered for a single peer
+pub const MAX_ADDRESSES_PER_PEER: usize = 8;
+
+/// Which side opened the connection to a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// The peer dialed us
+    Inbound,
+    /// We dialed the peer
+    Outbound,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Direction::Inbound => write!(f, "inbound"),
+            Direction::Outbound => write!(f, "outbound"),
+        }
+    }
+}
+
+/// A connection was refused because its direction's slots are taken
+#[derive(Debug, thiserror::Error)]
+#[error("all {max} {direction} peer slots are taken")]
+pub struct PeerLimitReached {
+    /// Direction of the refused connection
+    pub direction: Direction,
+    /// Number of slots in that direction
+    pub max: usize,
+}
+
+/// Accounting of the connected peers against the peer limits
+///
+/// A peer takes a slot in the direction of its first connection and keeps
+/// it until its last connection closes; further connections to an already
+/// counted peer are always allowed.
+#[derive(Debug)]
+pub struct PeerSlots {
+    max_inbound: usize,
+    max_outbound: usize,
+    peers: HashMap<PeerId, Direction>,
+}
+
+impl PeerSlots {
+    /// Split `max_peers` slots, up to `max_outbound` of them for outbound peers
+    pub fn new(max_peers: usize, max_outbound: usize) -> Self {
+        let max_outbound = max_outbound.min(max_peers);
+        Self {
+            max_inbound: max_peers - max_outbound,
+            max_outbound,
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Get the number of peers counted in the given direction
+    pub fn count(&self, direction: Direction) -> usize {
+        self.peers.values().filter(|counted| **counted == direction).count()
+    }
+
+    /// Get the number of outbound slots still free
+    pub fn free_outbound(&self) -> usize {
+        self.max_outbound.saturating_sub(self.count(Direction::Outbound))
+    }
+
+    /// Check whether a connection to `peer` in the given direction may be kept
+    pub fn check(&self, peer: &PeerId, direction: Direction) -> Result<(), ConnectionDenied> {
+        let max = match direction {
+            Direction::Inbound => self.max_inbound,
+            Direction::Outbound => self.max_outbound,
+        };
+        if self.peers.contains_key(peer) || self.count(direction) < max {
+            Ok(())
+        } else {
+            Err(ConnectionDenied::new(PeerLimitReached { direction, max }))
+        }
+    }
+
+    /// Count a newly established connection
+    pub fn connected(&mut self, peer: PeerId, direction: Direction) {
+        self.peers.entry(peer).or_insert(direction);
+    }
+
+    /// Release the slot of a peer whose last connection closed
+    pub fn disconnected(&mut self, peer: &PeerId) {
+        self.peers.remove(peer);
+    }
+}
+
+/// Refuses connections beyond the peer limits
+impl NetworkBehaviour for PeerSlots {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Infallible;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check(&peer, Direction::Inbound)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+        _port_use: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check(&peer, Direction::Outbound)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, event: FromSwarm) {
+        match event {
+            FromSwarm::ConnectionEstablished(established) => {
+                let direction = if established.endpoint.is_dialer() {
+                    Direction::Outbound
+                } else {
+                    Direction::Inbound
+                };
+                self.connected(established.peer_id, direction);
+            }
+            FromSwarm::ConnectionClosed(closed) if closed.remaining_established == 0 => {
+                self.disconnected(&closed.peer_id);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(&mut self, _cx: &mut Context<'_>) -> Poll<ToSwarm<Infallible, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
+
+/// Peers the node can reconnect to, backed by the database
+#[derive(Debug, Default)]
+pub struct AddressBook {
+    peers: HashMap<PeerId, (Vec<Multiaddr>, u64)>,
+}
+
+impl AddressBook {
+    /// Load the address book from the database
+    ///
+    /// Entries that no longer parse are dropped.
+    pub fn load(storage: &BlockchainStorage) -> Self {
+        let mut book = Self::default();
+        let records = match storage.get_all_peers() {
+            Ok(records) => records,
+            Err(e) => {
+                log::warn!("Failed to load the peer address book: {}", e);
+                return book;
+            }
+        };
+
+        for (key, record) in records {
+            let peer = PeerId::from_bytes(&key).ok();
+            let addresses: Vec<Multiaddr> = record
+                .addresses
+                .iter()
+                .filter_map(|address| address.parse().ok())
+                .collect();
+            match peer {
+                Some(peer) if !addresses.is_empty() => {
+                    book.peers.insert(peer, (addresses, record.last_seen));
+                }
+                _ => {
+                    let _ = storage.remove_peer(&key);
+                }
+            }
+        }
+        book
+    }
+
+    /// Get the number of known peers
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Check whether no peer is known
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+
+    /// Get the known peers, most recently seen first
+    pub fn most_recent(&self) -> Vec<(PeerId, Vec<Multiaddr>)> {
+        let mut peers: Vec<_> = self.peers.iter().collect();
+        peers.sort_by_key(|(_, (_, last_seen))| std::cmp::Reverse(*last_seen));
+        peers
+            .into_iter()
+            .map(|(peer, (addresses, _))| (*peer, addresses.clone()))
+            .collect()
+    }
+
+    /// Remember the addresses of a peer seen at `now`
+    ///
+    /// When the book is full, the peer seen longest ago is forgotten.
+    pub fn insert(&mut self, storage: &BlockchainStorage, peer: PeerId, addresses: &[Multiaddr], now: u64) {
+        let addresses: Vec<Multiaddr> = addresses.iter().take(MAX_ADDRESSES_PER_PEER).cloned().collect();
+        if addresses.is_empty() {
+            return;
+        }
+
+        let record = PeerRecord {
+            addresses: addresses.iter().map(|address| address.to_string()).collect(),
+            last_seen: now,
+        };
+        if let Err(e) = storage.store_peer(&peer.to_bytes(), &record) {
+            log::warn!("Failed to store peer {}: {}", peer, e);
+        }
+        self.peers.insert(peer, (addresses, now));
+
+        while self.peers.len() > MAX_KNOWN_PEERS {
+            let oldest = self
+                .peers
+                .iter()
+                .min_by_key(|(_, (_, last_seen))| *last_seen)
+                .map(|(peer, _)| *peer);
+            match oldest {
+                Some(oldest) => self.remove(storage, &oldest),
+                None => break,
+            }
+        }
+    }
+
+    /// Forget a peer
+    pub fn remove(&mut self, storage: &BlockchainStorage, peer: &PeerId) {
+        if self.peers.remove(peer).is_some() {
+            if let Err(e) = storage.remove_peer(&peer.to_bytes()) {
+                log::warn!("Failed to remove peer {}: {}", peer, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slots_split_by_direction() {
+        let mut slots = PeerSlots::new(3, 1);
+        let (a, b, c, d) = (PeerId::random(), PeerId::random(), PeerId::random(), PeerId::random());
+
+        assert!(slots.check(&a, Direction::Outbound).is_ok());
+        slots.connected(a, Direction::Outbound);
+        assert_eq!(slots.free_outbound(), 0);
+        assert!(slots.check(&b, Direction::Outbound).is_err());
+
+        // Outbound peers do not take inbound slots
+        slots.connected(b, Direction::Inbound);
+        slots.connected(c, Direction::Inbound);
+        assert!(slots.check(&d, Direction::Inbound).is_err());
+
+        // A counted peer may open further connections either way
+        assert!(slots.check(&a, Direction::Inbound).is_ok());
+        assert!(slots.check(&b, Direction::Outbound).is_ok());
+
+        slots.disconnected(&a);
+        assert!(slots.check(&d, Direction::Outbound).is_ok());
+        assert_eq!(slots.count(Direction::Inbound), 2);
+    }
+
+    #[test]
+    fn test_outbound_slots_capped_by_max_peers() {
+        let slots = PeerSlots::new(2, 10);
+        assert_eq!(slots.free_outbound(), 2);
+        assert!(slots.check(&PeerId::random(), Direction::Inbound).is_err());
+    }
+}
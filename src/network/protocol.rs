@@ -0,0 +1,126 @@
+//! Request/response protocol used for block sync
+//!
+//! Sync runs on libp2p's request/response behaviour. Every request opens a
+//! fresh substream on `/blocana/sync/1`, the requester writes one
+//! length-prefixed bincode [`SyncRequest`], the responder answers with one
+//! [`SyncResponse`] and the substream is closed. A request that is not
+//! answered within the configured timeout fails, so a stalled peer cannot
+//! hold the sync up.
+
+use super::sync::{SyncRequest, SyncResponse};
+use async_trait::async_trait;
+use libp2p::futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::request_response::{self, ProtocolSupport};
+use libp2p::StreamProtocol;
+use std::io;
+use std::time::Duration;
+
+pub use request_response::{OutboundRequestId as RequestId, ResponseChannel};
+
+/// Protocol name of the sync protocol
+pub const SYNC_PROTOCOL: StreamProtocol = StreamProtocol::new("/blocana/sync/1");
+
+/// Largest message accepted on the wire
+pub const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Most requests in progress at once on a single connection, in both directions
+const MAX_CONCURRENT_STREAMS: usize = 32;
+
+/// Network behaviour carrying sync requests and responses
+pub type SyncBehaviour = request_response::Behaviour<SyncCodec>;
+
+/// Events of the sync protocol
+pub type SyncEvent = request_response::Event<SyncRequest, SyncResponse>;
+
+/// Build the sync behaviour with the given request timeout
+pub fn build_behaviour(timeout: Duration) -> SyncBehaviour {
+    let config = request_response::Config::default()
+        .with_request_timeout(timeout)
+        .with_max_concurrent_streams(MAX_CONCURRENT_STREAMS);
+    SyncBehaviour::new([(SYNC_PROTOCOL, ProtocolSupport::Full)], config)
+}
+
+/// Length-prefixed bincode encoding of sync messages
+#[derive(Debug, Clone, Default)]
+pub struct SyncCodec;
+
+#[async_trait]
+impl request_response::Codec for SyncCodec {
+    type Protocol = StreamProtocol;
+    type Request = SyncRequest;
+    type Response = SyncResponse;
+
+    async fn read_request<T>(&mut self, _protocol: &StreamProtocol, io: &mut T) -> io::Result<SyncRequest>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io).await
+    }
+
+    async fn read_response<T>(&mut self, _protocol: &StreamProtocol, io: &mut T) -> io::Result<SyncResponse>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _protocol: &StreamProtocol,
+        io: &mut T,
+        request: SyncRequest,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, &request).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _protocol: &StreamProtocol,
+        io: &mut T,
+        response: SyncResponse,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, &response).await
+    }
+}
+
+/// Write a length-prefixed bincode message
+async fn write_message<T, M>(io: &mut T, message: &M) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + Send,
+    M: bincode::Encode,
+{
+    let bytes = bincode::encode_to_vec(message, bincode::config::standard())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if bytes.len() > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Message too large"));
+    }
+    io.write_all(&(bytes.len() as u32).to_le_bytes()).await?;
+    io.write_all(&bytes).await?;
+    io.flush().await
+}
+
+/// Read a length-prefixed bincode message
+async fn read_message<T, M>(io: &mut T) -> io::Result<M>
+where
+    T: AsyncRead + Unpin + Send,
+    M: bincode::Decode<()>,
+{
+    let mut length = [0u8; 4];
+    io.read_exact(&mut length).await?;
+    let length = u32::from_le_bytes(length) as usize;
+    if length > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Message too large"));
+    }
+
+    let mut bytes = vec![0u8; length];
+    io.read_exact(&mut bytes).await?;
+    bincode::decode_from_slice(&bytes, bincode::config::standard())
+        .map(|(message, _)| message)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
@@ -0,0 +1,371 @@
+//! Peer reputation
+//!
+//! Every peer starts with a score of zero. Blocks and transactions that turn
+//! out useful raise it a little, invalid ones lower it, and the score decays
+//! back towards zero over time so that old offences are eventually
+//! forgiven. A peer whose score drops to the disconnect threshold is
+//! disconnected; at the ban threshold it is also refused for a while.
+
+use crate::transaction::error::TransactionError;
+use libp2p::core::transport::PortUse;
+use libp2p::core::Endpoint;
+use libp2p::swarm::{
+    dummy, ConnectionDenied, ConnectionId, FromSwarm, NetworkBehaviour, THandler, THandlerInEvent,
+    THandlerOutEvent, ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+/// Highest score a peer can build up
+pub const MAX_SCORE: f64 = 100.0;
+
+/// Time after which half of a peer's score is forgotten
+const SCORE_HALF_LIFE: Duration = Duration::from_secs(10 * 60);
+
+/// Scores closer to zero than this are dropped when pruning
+const NEGLIGIBLE_SCORE: f64 = 0.5;
+
+/// Something a peer did that affects its reputation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerAction {
+    /// Relayed a block that was imported
+    ValidBlock,
+    /// Relayed a transaction that entered the pool
+    ValidTransaction,
+    /// Sent a block that is invalid in itself
+    InvalidBlock,
+    /// Sent a transaction that is garbage, badly signed or for another network
+    InvalidTransaction,
+    /// Relayed a transaction the sender's account cannot pay for, or with a
+    /// wrong nonce or a fee below the minimum
+    ///
+    /// Honest peers do this now and then when their view of the chain lags
+    /// behind, so it costs little.
+    UnacceptableTransaction,
+    /// Answered a sync request with data that does not fit the request
+    BadSyncResponse,
+}
+
+impl PeerAction {
+    /// Get the change of score the action causes
+    pub fn score_change(self) -> f64 {
+        match self {
+            PeerAction::ValidBlock => 5.0,
+            PeerAction::ValidTransaction => 0.5,
+            PeerAction::InvalidBlock => -50.0,
+            PeerAction::InvalidTransaction => -20.0,
+            PeerAction::UnacceptableTransaction => -2.0,
+            PeerAction::BadSyncResponse => -20.0,
+        }
+    }
+
+    /// Get the action revealed by the pool refusing a relayed transaction
+    ///
+    /// Returns `None` when the refusal is not the relaying peer's fault, as
+    /// with a duplicate, a full pool or a rate limited sender.
+    pub fn for_transaction_error(error: &TransactionError) -> Option<Self> {
+        match error {
+            TransactionError::InvalidSignature
+            | TransactionError::WrongNetwork { .. }
+            | TransactionError::DataTooLarge { .. } => Some(PeerAction::InvalidTransaction),
+            TransactionError::InvalidNonce { .. }
+            | TransactionError::InsufficientBalance { .. }
+            | TransactionError::InsufficientStake { .. }
+            | TransactionError::FeeTooLow { .. } => Some(PeerAction::UnacceptableTransaction),
+            TransactionError::AlreadyExists { .. }
+            | TransactionError::ReplacementFeeTooLow { .. }
+            | TransactionError::PoolFull { .. }
+            | TransactionError::MemoryLimitReached { .. }
+            | TransactionError::RateLimited { .. }
+            | TransactionError::Validation(_)
+            | TransactionError::Expired { .. }
+            | TransactionError::Temporary(_)
+            | TransactionError::Internal(_)
+            | TransactionError::Crypto(_)
+            | TransactionError::Database(_) => None,
+        }
+    }
+}
+
+/// What to do with a peer after its score changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Nothing
+    Keep,
+    /// Close the connections to the peer
+    Disconnect,
+    /// Close the connections and refuse new ones for the ban duration
+    Ban,
+}
+
+/// Reputation of a peer as shown to the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerScore {
+    /// The peer
+    pub peer: PeerId,
+    /// Current score
+    pub score: f64,
+    /// Time left on the peer's ban, if it is banned
+    pub banned_for: Option<Duration>,
+}
+
+/// A peer was refused because it is banned
+#[derive(Debug, thiserror::Error)]
+#[error("peer {0} is banned")]
+pub struct PeerBanned(pub PeerId);
+
+/// Scores and bans of the peers a node has dealt with
+#[derive(Debug)]
+pub struct PeerReputation {
+    disconnect_threshold: f64,
+    ban_threshold: f64,
+    ban_duration: Duration,
+    /// Score of each peer when it was last updated
+    scores: HashMap<PeerId, (f64, Instant)>,
+    /// End of each peer's ban
+    bans: HashMap<PeerId, Instant>,
+}
+
+impl PeerReputation {
+    /// Create an empty reputation table
+    ///
+    /// Peers are disconnected once their score drops to `disconnect_threshold`
+    /// and banned for `ban_duration` once it drops to `ban_threshold`.
+    pub fn new(disconnect_threshold: f64, ban_threshold: f64, ban_duration: Duration) -> Self {
+        Self {
+            disconnect_threshold,
+            ban_threshold,
+            ban_duration,
+            scores: HashMap::new(),
+            bans: HashMap::new(),
+        }
+    }
+
+    /// Get the score of a peer at `now`
+    pub fn score(&self, peer: &PeerId, now: Instant) -> f64 {
+        self.scores
+            .get(peer)
+            .map(|(score, updated)| decay(*score, now.saturating_duration_since(*updated)))
+            .unwrap_or(0.0)
+    }
+
+    /// Record something a peer did and decide what to do with it
+    pub fn report(&mut self, peer: &PeerId, action: PeerAction, now: Instant) -> Verdict {
+        let score = (self.score(peer, now) + action.score_change()).min(MAX_SCORE);
+        self.scores.insert(*peer, (score, now));
+
+        if score <= self.ban_threshold {
+            self.ban(peer, self.ban_duration, now);
+            Verdict::Ban
+        } else if score <= self.disconnect_threshold {
+            Verdict::Disconnect
+        } else {
+            Verdict::Keep
+        }
+    }
+
+    /// Refuse a peer for `duration`
+    pub fn ban(&mut self, peer: &PeerId, duration: Duration, now: Instant) {
+        self.bans.insert(*peer, now + duration);
+    }
+
+    /// Lift a peer's ban and forget its score; returns whether it was banned
+    pub fn unban(&mut self, peer: &PeerId) -> bool {
+        self.scores.remove(peer);
+        self.bans.remove(peer).is_some()
+    }
+
+    /// Check whether a peer is banned at `now`
+    pub fn is_banned(&self, peer: &PeerId, now: Instant) -> bool {
+        self.bans.get(peer).is_some_and(|until| *until > now)
+    }
+
+    /// Refuse connections to banned peers
+    pub fn check(&self, peer: &PeerId) -> Result<(), ConnectionDenied> {
+        if self.is_banned(peer, Instant::now()) {
+            Err(ConnectionDenied::new(PeerBanned(*peer)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get the reputation of the given peers and of every scored or banned peer
+    pub fn snapshot<'a>(&self, peers: impl IntoIterator<Item = &'a PeerId>, now: Instant) -> Vec<PeerScore> {
+        let mut all: HashSet<PeerId> = peers.into_iter().copied().collect();
+        all.extend(self.scores.keys().copied());
+        all.extend(self.bans.keys().filter(|peer| self.is_banned(peer, now)).copied());
+
+        let mut scores: Vec<PeerScore> = all
+            .into_iter()
+            .map(|peer| PeerScore {
+                peer,
+                score: self.score(&peer, now),
+                banned_for: self
+                    .bans
+                    .get(&peer)
+                    .map(|until| until.saturating_duration_since(now))
+                    .filter(|left| !left.is_zero()),
+            })
+            .collect();
+        scores.sort_by(|a, b| a.score.total_cmp(&b.score));
+        scores
+    }
+
+    /// Forget expired bans and scores that decayed to nothing
+    pub fn prune(&mut self, now: Instant) {
+        self.bans.retain(|_, until| *until > now);
+        self.scores.retain(|_, (score, updated)| {
+            decay(*score, now.saturating_duration_since(*updated)).abs() >= NEGLIGIBLE_SCORE
+        });
+    }
+}
+
+/// Refuses connections to and from banned peers
+impl NetworkBehaviour for PeerReputation {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Infallible;
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        _addresses: &[Multiaddr],
+        _effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        if let Some(peer) = maybe_peer {
+            self.check(&peer)?;
+        }
+        Ok(Vec::new())
+    }
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check(&peer)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+        _port_use: PortUse,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check(&peer)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, _event: FromSwarm) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer: PeerId,
+        _connection_id: ConnectionId,
+        event: THandlerOutEvent<Self>,
+    ) {
+        match event {}
+    }
+
+    fn poll(&mut self, _cx: &mut Context<'_>) -> Poll<ToSwarm<Infallible, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
+
+/// Get what is left of a score after `elapsed`
+fn decay(score: f64, elapsed: Duration) -> f64 {
+    score * 0.5f64.powf(elapsed.as_secs_f64() / SCORE_HALF_LIFE.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reputation() -> PeerReputation {
+        PeerReputation::new(-50.0, -100.0, Duration::from_secs(3600))
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let mut reputation = reputation();
+        let peer = PeerId::random();
+        let now = Instant::now();
+
+        assert_eq!(reputation.report(&peer, PeerAction::InvalidTransaction, now), Verdict::Keep);
+        assert_eq!(reputation.report(&peer, PeerAction::InvalidTransaction, now), Verdict::Keep);
+        assert_eq!(reputation.report(&peer, PeerAction::InvalidBlock, now), Verdict::Disconnect);
+        assert!(!reputation.is_banned(&peer, now));
+        assert_eq!(reputation.report(&peer, PeerAction::InvalidBlock, now), Verdict::Ban);
+        assert!(reputation.is_banned(&peer, now));
+
+        // The ban runs out
+        assert!(!reputation.is_banned(&peer, now + Duration::from_secs(3601)));
+        reputation.prune(now + Duration::from_secs(3601));
+        assert!(reputation.bans.is_empty());
+    }
+
+    #[test]
+    fn test_score_decays() {
+        let mut reputation = reputation();
+        let peer = PeerId::random();
+        let now = Instant::now();
+
+        reputation.report(&peer, PeerAction::InvalidBlock, now);
+        assert_eq!(reputation.score(&peer, now), -50.0);
+        assert_eq!(reputation.score(&peer, now + SCORE_HALF_LIFE), -25.0);
+
+        // Good behaviour is capped, so it cannot buy a free pass
+        for _ in 0..100 {
+            reputation.report(&peer, PeerAction::ValidBlock, now);
+        }
+        assert_eq!(reputation.score(&peer, now), MAX_SCORE);
+
+        reputation.prune(now + SCORE_HALF_LIFE * 10);
+        assert!(reputation.scores.is_empty());
+    }
+
+    #[test]
+    fn test_manual_ban_and_snapshot() {
+        let mut reputation = reputation();
+        let (banned, connected) = (PeerId::random(), PeerId::random());
+        let now = Instant::now();
+
+        reputation.ban(&banned, Duration::from_secs(60), now);
+        let snapshot = reputation.snapshot([&connected], now);
+        assert_eq!(snapshot.len(), 2);
+        let entry = snapshot.iter().find(|entry| entry.peer == banned).unwrap();
+        assert_eq!(entry.banned_for, Some(Duration::from_secs(60)));
+
+        assert!(reputation.unban(&banned));
+        assert!(!reputation.is_banned(&banned, now));
+        assert!(!reputation.unban(&banned));
+    }
+
+    #[test]
+    fn test_transaction_errors() {
+        assert_eq!(
+            PeerAction::for_transaction_error(&TransactionError::InvalidSignature),
+            Some(PeerAction::InvalidTransaction)
+        );
+        assert_eq!(
+            PeerAction::for_transaction_error(&TransactionError::InvalidNonce {
+                sender: [0u8; 32],
+                expected: 1,
+                actual: 0,
+            }),
+            Some(PeerAction::UnacceptableTransaction)
+        );
+        assert_eq!(
+            PeerAction::for_transaction_error(&TransactionError::AlreadyExists { tx_hash: [0u8; 32] }),
+            None
+        );
+    }
+}
@@ -0,0 +1,714 @@
+//! Block synchronization
+//!
+//! A node that is behind its peers catches up in two steps. It first asks
+//! the peer with the highest chain for the headers above its own tip and
+//! checks that they link up, then downloads the block bodies for those
+//! headers in batches, spread over every peer that has them. Bodies are
+//! imported strictly in height order as soon as the next one is available,
+//! so a restarted node simply continues from its last stored block.
+//!
+//! [`BlockSync`] only keeps track of what to ask whom; sending the requests
+//! and importing the blocks is left to the network thread.
+
+use super::protocol::RequestId;
+use crate::block::{Block, BlockHeader};
+use crate::transaction::Transaction;
+use crate::types::Hash;
+use libp2p::PeerId;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+/// Most headers returned for a single request
+pub const HEADERS_PER_REQUEST: u32 = 256;
+
+/// Most blocks returned for a single request
+pub const BLOCKS_PER_REQUEST: u32 = 32;
+
+/// Most headers kept ahead of the local tip while their bodies download
+const MAX_HEADERS_AHEAD: usize = 2048;
+
+/// Failed requests after which a peer is no longer asked for anything
+const MAX_PEER_FAILURES: u32 = 3;
+
+/// Height and hash of a chain tip
+#[derive(Debug, Clone, Copy, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct ChainStatus {
+    /// Height of the tip
+    pub height: u64,
+    /// Hash of the tip
+    pub hash: Hash,
+}
+
+/// Requests of the sync protocol
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub enum SyncRequest {
+    /// Exchange chain tips; carries the sender's tip
+    Status(ChainStatus),
+    /// Ask for main chain headers starting at a height
+    Headers {
+        /// Height of the first header
+        start: u64,
+        /// Number of headers wanted
+        count: u32,
+    },
+    /// Ask for main chain blocks starting at a height
+    Blocks {
+        /// Height of the first block
+        start: u64,
+        /// Number of blocks wanted
+        count: u32,
+    },
+    /// Ask for transactions of a block by their position in it
+    BlockTransactions {
+        /// Hash of the block
+        block_hash: Hash,
+        /// Positions of the transactions wanted
+        indexes: Vec<u32>,
+    },
+}
+
+/// Responses of the sync protocol
+#[derive(Debug, Clone, bincode::Encode, bincode::Decode)]
+pub enum SyncResponse {
+    /// The responder's tip
+    Status(ChainStatus),
+    /// Consecutive headers from the requested height, possibly fewer than asked for
+    Headers(Vec<BlockHeader>),
+    /// Consecutive blocks from the requested height, possibly fewer than asked for
+    Blocks(Vec<Block>),
+    /// The requested transactions in the requested order, or none if the
+    /// block is unknown or an index is out of range
+    Transactions(Vec<Transaction>),
+}
+
+/// What the sync knows about a peer
+#[derive(Debug, Default)]
+struct SyncPeer {
+    /// Last tip the peer reported
+    status: Option<ChainStatus>,
+    /// Whether the tip has to be asked for again
+    status_stale: bool,
+    /// Whether a request to the peer is outstanding
+    busy: bool,
+    /// Failed or bogus requests so far
+    failures: u32,
+}
+
+impl SyncPeer {
+    fn usable(&self) -> bool {
+        !self.busy && self.failures < MAX_PEER_FAILURES
+    }
+
+    fn height(&self) -> u64 {
+        self.status.map(|status| status.height).unwrap_or(0)
+    }
+}
+
+/// A request waiting for its response
+#[derive(Debug)]
+struct InFlight {
+    peer: PeerId,
+    request: SyncRequest,
+}
+
+/// Download state of the block sync
+#[derive(Debug)]
+pub struct BlockSync {
+    /// Tip of the local chain
+    local: ChainStatus,
+    /// Connected peers
+    peers: HashMap<PeerId, SyncPeer>,
+    /// Linked headers not imported yet, by height
+    headers: BTreeMap<u64, BlockHeader>,
+    /// Height to ask for headers from when searching for a fork point
+    fork_search: Option<u64>,
+    /// Outstanding header request
+    header_request: Option<RequestId>,
+    /// First heights of block batches still to request
+    queued: BTreeSet<u64>,
+    /// Outstanding requests
+    requests: HashMap<RequestId, InFlight>,
+    /// Downloaded blocks waiting for their turn, with the peer that sent them
+    downloaded: BTreeMap<u64, (PeerId, Block)>,
+}
+
+impl BlockSync {
+    /// Create a sync starting at the given local tip
+    pub fn new(local: ChainStatus) -> Self {
+        Self {
+            local,
+            peers: HashMap::new(),
+            headers: BTreeMap::new(),
+            fork_search: None,
+            header_request: None,
+            queued: BTreeSet::new(),
+            requests: HashMap::new(),
+            downloaded: BTreeMap::new(),
+        }
+    }
+
+    /// Get the local tip the sync works from
+    pub fn local(&self) -> ChainStatus {
+        self.local
+    }
+
+    /// Update the local tip after blocks were imported
+    pub fn set_local(&mut self, local: ChainStatus) {
+        self.local = local;
+    }
+
+    /// Start tracking a newly connected peer
+    pub fn add_peer(&mut self, peer: PeerId) {
+        self.peers.entry(peer).or_insert_with(|| SyncPeer {
+            status_stale: true,
+            ..SyncPeer::default()
+        });
+    }
+
+    /// Forget a disconnected peer, handing its outstanding work to others
+    pub fn remove_peer(&mut self, peer: &PeerId) {
+        self.peers.remove(peer);
+
+        let abandoned: Vec<RequestId> = self
+            .requests
+            .iter()
+            .filter(|(_, in_flight)| in_flight.peer == *peer)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in abandoned {
+            if let Some(in_flight) = self.requests.remove(&id) {
+                self.requeue(id, &in_flight.request);
+            }
+        }
+    }
+
+    /// Record the tip a peer reported
+    pub fn on_status(&mut self, peer: &PeerId, status: ChainStatus) {
+        if let Some(state) = self.peers.get_mut(peer) {
+            state.status = Some(status);
+            state.status_stale = false;
+        }
+    }
+
+    /// Ask every peer for its tip again on the next round of requests
+    pub fn refresh_status(&mut self) {
+        for state in self.peers.values_mut() {
+            state.status_stale = true;
+        }
+    }
+
+    /// Get the highest tip reported by a usable peer
+    pub fn best_peer_height(&self) -> u64 {
+        self.peers
+            .values()
+            .filter(|state| state.failures < MAX_PEER_FAILURES)
+            .map(SyncPeer::height)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Check whether the sync is waiting for anything
+    pub fn is_idle(&self) -> bool {
+        self.requests.is_empty() && self.headers.is_empty()
+    }
+
+    /// Plan the next requests
+    ///
+    /// Idle peers with an unknown tip are asked for it, the best peer is
+    /// asked for the next headers, and the remaining idle peers download
+    /// queued block batches, lowest heights first. `send` hands a request
+    /// to the network and returns its id.
+    pub fn next_requests(&mut self, mut send: impl FnMut(&PeerId, SyncRequest) -> RequestId) {
+        let mut peers: Vec<PeerId> = self
+            .peers
+            .iter()
+            .filter(|(_, state)| state.usable())
+            .map(|(peer, _)| *peer)
+            .collect();
+        // Reliable peers first, then the best ones, so header requests go to
+        // whoever knows the most
+        peers.sort_by_key(|peer| {
+            let state = &self.peers[peer];
+            (state.failures, std::cmp::Reverse(state.height()))
+        });
+
+        for peer in peers {
+            let state = &self.peers[&peer];
+            let height = state.height();
+            let request = if state.status_stale || state.status.is_none() {
+                Some(SyncRequest::Status(self.local))
+            } else if self.header_request.is_none() {
+                self.next_header_request(height)
+                    .or_else(|| self.next_block_request(height))
+            } else {
+                self.next_block_request(height)
+            };
+
+            if let Some(request) = request {
+                let id = send(&peer, request.clone());
+                if let SyncRequest::Headers { .. } = request {
+                    self.header_request = Some(id);
+                }
+                if let Some(state) = self.peers.get_mut(&peer) {
+                    state.busy = true;
+                }
+                self.requests.insert(id, InFlight { peer, request });
+            }
+        }
+    }
+
+    /// Handle a response, returning why it was refused if the peer misbehaved
+    ///
+    /// `local_hash` gives the hash of the local main chain block at a height.
+    pub fn on_response(
+        &mut self,
+        peer: &PeerId,
+        id: RequestId,
+        response: SyncResponse,
+        local_hash: impl Fn(u64) -> Option<Hash>,
+    ) -> Result<(), String> {
+        let Some(in_flight) = self.requests.remove(&id) else {
+            return Ok(());
+        };
+        if let Some(state) = self.peers.get_mut(peer) {
+            state.busy = false;
+        }
+        if self.header_request == Some(id) {
+            self.header_request = None;
+        }
+
+        let result = match (&in_flight.request, response) {
+            (SyncRequest::Status(_), SyncResponse::Status(status)) => {
+                self.on_status(peer, status);
+                Ok(())
+            }
+            (SyncRequest::Headers { start, count }, SyncResponse::Headers(headers)) => {
+                self.on_headers(peer, *start, *count, headers, local_hash)
+            }
+            (SyncRequest::Blocks { start, count }, SyncResponse::Blocks(blocks)) => {
+                self.on_blocks(peer, *start, *count, blocks)
+            }
+            _ => Err("Response does not match the request".to_string()),
+        };
+
+        if result.is_err() {
+            self.requeue(id, &in_flight.request);
+            self.strike(peer);
+        }
+        result
+    }
+
+    /// Handle a request that failed or timed out
+    ///
+    /// # Returns
+    /// Whether the peer failed often enough to be dropped
+    pub fn on_failure(&mut self, peer: &PeerId, id: RequestId) -> bool {
+        if let Some(in_flight) = self.requests.remove(&id) {
+            self.requeue(id, &in_flight.request);
+        }
+        if let Some(state) = self.peers.get_mut(peer) {
+            state.busy = false;
+        }
+        self.strike(peer)
+    }
+
+    /// Take the downloaded blocks that can be imported now, in height order
+    pub fn take_ready(&mut self) -> Vec<(PeerId, Block)> {
+        let mut ready = Vec::new();
+        while let Some((&height, _)) = self.headers.first_key_value() {
+            let Some(entry) = self.downloaded.remove(&height) else {
+                break;
+            };
+            self.headers.remove(&height);
+            ready.push(entry);
+        }
+        ready
+    }
+
+    /// Give up on the downloaded headers and blocks after a failed import
+    ///
+    /// The peer that sent the block is penalised if the block itself was bad.
+    pub fn import_failed(&mut self, peer: Option<&PeerId>) {
+        if let Some(peer) = peer {
+            self.strike(peer);
+        }
+        self.reset();
+    }
+
+    /// Drop all download progress; outstanding requests are answered into the void
+    fn reset(&mut self) {
+        self.headers.clear();
+        self.queued.clear();
+        self.downloaded.clear();
+        self.fork_search = None;
+    }
+
+    /// Count a failure against a peer, returning whether it is now unusable
+    fn strike(&mut self, peer: &PeerId) -> bool {
+        match self.peers.get_mut(peer) {
+            Some(state) => {
+                state.failures += 1;
+                state.failures >= MAX_PEER_FAILURES
+            }
+            None => false,
+        }
+    }
+
+    /// Put the work of a lost request back in the queue
+    fn requeue(&mut self, id: RequestId, request: &SyncRequest) {
+        match request {
+            SyncRequest::Blocks { start, .. } if self.headers.contains_key(start) => {
+                self.queued.insert(*start);
+            }
+            SyncRequest::Headers { .. } if self.header_request == Some(id) => {
+                self.header_request = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Height of the highest known block, downloaded or not
+    fn header_tip(&self) -> u64 {
+        self.headers
+            .last_key_value()
+            .map(|(height, _)| *height)
+            .unwrap_or(0)
+            .max(self.local.height)
+    }
+
+    fn next_header_request(&self, peer_height: u64) -> Option<SyncRequest> {
+        let start = match self.fork_search {
+            Some(start) => start,
+            None if peer_height > self.header_tip() && self.headers.len() < MAX_HEADERS_AHEAD => {
+                self.header_tip() + 1
+            }
+            None => return None,
+        };
+        let count = (peer_height + 1)
+            .saturating_sub(start)
+            .min(HEADERS_PER_REQUEST as u64) as u32;
+        (count > 0).then_some(SyncRequest::Headers { start, count })
+    }
+
+    fn next_block_request(&mut self, peer_height: u64) -> Option<SyncRequest> {
+        let start = *self.queued.iter().find(|start| **start <= peer_height)?;
+        self.queued.remove(&start);
+
+        // A batch covers the headers from `start` up to the next queued batch
+        let count = (start..start + BLOCKS_PER_REQUEST as u64)
+            .take_while(|height| {
+                *height <= peer_height
+                    && self.headers.contains_key(height)
+                    && !self.downloaded.contains_key(height)
+                    && (*height == start || !self.queued.contains(height))
+            })
+            .count() as u32;
+        (count > 0).then_some(SyncRequest::Blocks { start, count })
+    }
+
+    fn on_headers(
+        &mut self,
+        peer: &PeerId,
+        start: u64,
+        count: u32,
+        headers: Vec<BlockHeader>,
+        local_hash: impl Fn(u64) -> Option<Hash>,
+    ) -> Result<(), String> {
+        if headers.is_empty() {
+            // The peer's chain changed since it reported its tip
+            if let Some(state) = self.peers.get_mut(peer) {
+                state.status_stale = true;
+            }
+            return Ok(());
+        }
+        if headers.len() > count as usize {
+            return Err(format!("Sent {} headers, {} were asked for", headers.len(), count));
+        }
+        for (offset, header) in headers.iter().enumerate() {
+            if header.height != start + offset as u64 {
+                return Err(format!("Header at position {} has height {}", offset, header.height));
+            }
+            if offset > 0 && header.prev_hash != headers[offset - 1].hash() {
+                return Err(format!("Header {} does not link to its parent", header.height));
+            }
+        }
+
+        let parent_height = start - 1;
+        let parent = match self.headers.get(&parent_height) {
+            Some(header) => Some(header.hash()),
+            None if parent_height <= self.local.height => local_hash(parent_height),
+            None => None,
+        };
+
+        if parent != Some(headers[0].prev_hash) {
+            if !self.headers.is_empty() {
+                // The peer is on another branch than the headers we have;
+                // start over from our own tip
+                self.reset();
+            } else if parent_height == 0 {
+                return Err("Peer is on a different genesis".to_string());
+            } else {
+                // Our tip is not on the peer's chain: look further back
+                self.fork_search =
+                    Some(start.saturating_sub(HEADERS_PER_REQUEST as u64).max(1));
+            }
+            return Ok(());
+        }
+        self.fork_search = None;
+
+        // Headers of blocks we already have are only needed to find the fork point
+        let mut first_new = None;
+        for header in headers {
+            let height = header.height;
+            if first_new.is_none() && height <= self.local.height && local_hash(height) == Some(header.hash()) {
+                continue;
+            }
+            first_new.get_or_insert(height);
+            self.headers.insert(height, header);
+        }
+
+        if let Some(first) = first_new {
+            let last = self.header_tip();
+            let mut batch = first;
+            while batch <= last {
+                self.queued.insert(batch);
+                batch += BLOCKS_PER_REQUEST as u64;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_blocks(&mut self, peer: &PeerId, start: u64, count: u32, blocks: Vec<Block>) -> Result<(), String> {
+        if blocks.len() > count as usize {
+            return Err(format!("Sent {} blocks, {} were asked for", blocks.len(), count));
+        }
+
+        let received = blocks.len() as u64;
+        for (offset, block) in blocks.iter().enumerate() {
+            let height = start + offset as u64;
+            let Some(header) = self.headers.get(&height) else {
+                // Download progress was reset meanwhile
+                return Ok(());
+            };
+            if block.header.hash() != header.hash() {
+                return Err(format!("Block at height {} does not match its header", height));
+            }
+        }
+        for (offset, block) in blocks.into_iter().enumerate() {
+            self.downloaded.insert(start + offset as u64, (*peer, block));
+        }
+
+        if received < count as u64 {
+            // Fetch the rest elsewhere; the peer may have switched branches
+            self.queued.insert(start + received);
+            if let Some(state) = self.peers.get_mut(peer) {
+                state.status_stale = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::protocol::{self, SyncBehaviour};
+    use super::*;
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    /// Build a chain of linked headers on top of `parent`
+    fn headers_after(parent: Hash, first_height: u64, count: u64, salt: u8) -> Vec<BlockHeader> {
+        let mut headers = Vec::new();
+        let mut prev = parent;
+        for height in first_height..first_height + count {
+            let header = BlockHeader::new(2, prev, [salt; 32], height, [salt; 32]);
+            prev = header.hash();
+            headers.push(header);
+        }
+        headers
+    }
+
+    fn block_for(header: &BlockHeader) -> Block {
+        Block {
+            header: header.clone(),
+            transactions: Vec::new(),
+        }
+    }
+
+    /// Collects planned requests, numbered by a sync behaviour that is never polled
+    struct Sent {
+        behaviour: SyncBehaviour,
+        requests: Vec<(RequestId, PeerId, SyncRequest)>,
+    }
+
+    impl Default for Sent {
+        fn default() -> Self {
+            Self {
+                behaviour: protocol::build_behaviour(Duration::from_secs(10)),
+                requests: Vec::new(),
+            }
+        }
+    }
+
+    impl Sent {
+        fn plan(&mut self, sync: &mut BlockSync) -> Vec<(RequestId, PeerId, SyncRequest)> {
+            let start = self.requests.len();
+            sync.next_requests(|peer, request| {
+                let id = self.behaviour.send_request(peer, request.clone());
+                self.requests.push((id, *peer, request));
+                id
+            });
+            self.requests[start..].to_vec()
+        }
+
+        /// Get the id of a request the downloader did not plan
+        fn unplanned(&mut self) -> RequestId {
+            self.behaviour
+                .send_request(&PeerId::random(), SyncRequest::Headers { start: 0, count: 1 })
+        }
+    }
+
+    const GENESIS: Hash = [9u8; 32];
+
+    fn genesis_hash(height: u64) -> Option<Hash> {
+        (height == 0).then_some(GENESIS)
+    }
+
+    /// Connect `count` peers that report `tip` as their chain tip
+    fn peers_at(sync: &mut BlockSync, sent: &mut Sent, tip: &BlockHeader, count: usize) -> Vec<PeerId> {
+        let peers: Vec<PeerId> = (0..count).map(|_| PeerId::random()).collect();
+        for peer in &peers {
+            sync.add_peer(*peer);
+        }
+
+        let planned = sent.plan(sync);
+        assert_eq!(planned.len(), count);
+        let status = ChainStatus { height: tip.height, hash: tip.hash() };
+        for (id, peer, request) in planned {
+            assert!(matches!(request, SyncRequest::Status(_)));
+            sync.on_response(&peer, id, SyncResponse::Status(status), genesis_hash).unwrap();
+        }
+        peers
+    }
+
+    #[test]
+    fn test_sync_downloads_from_several_peers() {
+        let chain = headers_after(GENESIS, 1, 100, 1);
+        let mut sync = BlockSync::new(ChainStatus { height: 0, hash: GENESIS });
+        let mut sent = Sent::default();
+        let peers = peers_at(&mut sync, &mut sent, &chain[99], 2);
+        assert_eq!(sync.best_peer_height(), 100);
+
+        // Headers are only asked from one peer
+        let planned = sent.plan(&mut sync);
+        assert_eq!(planned.len(), 1);
+        let (id, header_peer, request) = planned[0].clone();
+        assert_eq!(request, SyncRequest::Headers { start: 1, count: 100 });
+        sync.on_response(&header_peer, id, SyncResponse::Headers(chain.clone()), genesis_hash)
+            .unwrap();
+
+        // Block batches are spread over both
+        let planned = sent.plan(&mut sync);
+        let busy: HashSet<PeerId> = planned.iter().map(|(_, peer, _)| *peer).collect();
+        assert_eq!(busy, HashSet::from_iter(peers));
+        let starts: Vec<u64> = planned
+            .iter()
+            .map(|(_, _, request)| match request {
+                SyncRequest::Blocks { start, count } => {
+                    assert_eq!(*count, BLOCKS_PER_REQUEST);
+                    *start
+                }
+                other => panic!("unexpected request {:?}", other),
+            })
+            .collect();
+        assert_eq!(HashSet::<u64>::from_iter(starts), HashSet::from([1, 33]));
+
+        // The second batch arrives first and waits for the first one
+        let (first, second): (Vec<_>, Vec<_>) = planned
+            .into_iter()
+            .partition(|(_, _, request)| matches!(request, SyncRequest::Blocks { start: 1, .. }));
+        let answer = |range: std::ops::Range<usize>| SyncResponse::Blocks(chain[range].iter().map(block_for).collect());
+        sync.on_response(&second[0].1, second[0].0, answer(32..64), genesis_hash).unwrap();
+        assert!(sync.take_ready().is_empty());
+        sync.on_response(&first[0].1, first[0].0, answer(0..32), genesis_hash).unwrap();
+
+        let ready = sync.take_ready();
+        assert_eq!(ready.len(), 64);
+        assert!(ready.windows(2).all(|pair| pair[1].1.header.height == pair[0].1.header.height + 1));
+    }
+
+    #[test]
+    fn test_failed_requests_are_retried_elsewhere() {
+        let chain = headers_after(GENESIS, 1, 10, 1);
+        let mut sync = BlockSync::new(ChainStatus { height: 0, hash: GENESIS });
+        let mut sent = Sent::default();
+        peers_at(&mut sync, &mut sent, &chain[9], 2);
+
+        let planned = sent.plan(&mut sync);
+        let (id, peer, _) = planned[0];
+        sync.on_response(&peer, id, SyncResponse::Headers(chain.clone()), genesis_hash)
+            .unwrap();
+        let planned = sent.plan(&mut sync);
+        assert_eq!(planned.len(), 1);
+        let (id, first, ref request) = planned[0];
+        assert_eq!(*request, SyncRequest::Blocks { start: 1, count: 10 });
+
+        // The first peer times out; the other one picks the batch up
+        assert!(!sync.on_failure(&first, id));
+        let planned = sent.plan(&mut sync);
+        let (id, second, ref request) = planned[0];
+        assert_ne!(second, first);
+        assert_eq!(*request, SyncRequest::Blocks { start: 1, count: 10 });
+
+        // A block not matching its header counts against the peer
+        let mut bogus: Vec<Block> = chain.iter().map(block_for).collect();
+        bogus[3].header.timestamp += 1;
+        assert!(sync
+            .on_response(&second, id, SyncResponse::Blocks(bogus), genesis_hash)
+            .is_err());
+        assert!(sync.take_ready().is_empty());
+
+        // Peers failing too often are no longer used
+        assert!(!sync.on_failure(&first, sent.unplanned()));
+        assert!(sync.on_failure(&first, sent.unplanned()));
+        let planned = sent.plan(&mut sync);
+        assert!(!planned.is_empty());
+        assert!(planned.iter().all(|(_, peer, _)| *peer == second));
+    }
+
+    #[test]
+    fn test_sync_finds_fork_point() {
+        // Locally 1..=300 on one branch, the peer forked off at 100 and is longer
+        let local = headers_after(GENESIS, 1, 300, 1);
+        let mut remote = local[..100].to_vec();
+        remote.extend(headers_after(local[99].hash(), 101, 250, 2));
+        let local_hash = |height: u64| match height {
+            0 => Some(GENESIS),
+            h => local.get(h as usize - 1).map(BlockHeader::hash),
+        };
+
+        let tip = &local[299];
+        let mut sync = BlockSync::new(ChainStatus { height: 300, hash: tip.hash() });
+        let mut sent = Sent::default();
+        let peer = peers_at(&mut sync, &mut sent, &remote[349], 1)[0];
+
+        // Asking above our tip does not link, so the sync walks back
+        let planned = sent.plan(&mut sync);
+        assert_eq!(planned[0].2, SyncRequest::Headers { start: 301, count: 50 });
+        sync.on_response(&peer, planned[0].0, SyncResponse::Headers(remote[300..350].to_vec()), local_hash)
+            .unwrap();
+        let planned = sent.plan(&mut sync);
+        assert_eq!(planned[0].2, SyncRequest::Headers { start: 45, count: HEADERS_PER_REQUEST });
+        sync.on_response(&peer, planned[0].0, SyncResponse::Headers(remote[44..300].to_vec()), local_hash)
+            .unwrap();
+
+        // Only the blocks after the fork point are downloaded, and the
+        // headers continue from the peer's branch
+        assert_eq!(sync.headers.first_key_value().map(|(height, _)| *height), Some(101));
+        assert_eq!(sync.queued.first(), Some(&101));
+        let planned = sent.plan(&mut sync);
+        assert_eq!(planned[0].2, SyncRequest::Headers { start: 301, count: 50 });
+        sync.on_response(&peer, planned[0].0, SyncResponse::Headers(remote[300..350].to_vec()), local_hash)
+            .unwrap();
+        assert_eq!(sync.headers.len(), 250);
+    }
+}
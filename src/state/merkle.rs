@@ -0,0 +1,191 @@
+//! State commitment for the Blocana blockchain
+//!
+//! Account states are committed to with a sparse Merkle tree keyed by the
+//! account address. The tree is compacted: an empty subtree hashes to all
+//! zeros and a subtree holding a single account hashes to that account's
+//! leaf, so the root only depends on the set of accounts and costs
+//! O(n log n) hashes to compute instead of 256 levels per account.
+
+use super::AccountState;
+use crate::types::{Hash, PublicKeyBytes};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+
+/// Domain separator for leaf hashes
+const LEAF_PREFIX: u8 = 0x00;
+/// Domain separator for internal node hashes
+const NODE_PREFIX: u8 = 0x01;
+/// Hash of a subtree without any accounts
+pub const EMPTY_ROOT: Hash = [0u8; 32];
+
+/// Compute the state root of a set of accounts
+///
+/// Accounts that are indistinguishable from a missing account (zero balance,
+/// zero nonce, no stake, no code and no storage) are left out, so looking an address
+/// up does not change the root.
+pub fn compute_state_root(accounts: &HashMap<PublicKeyBytes, AccountState>) -> Hash {
+    let mut leaves: Vec<(PublicKeyBytes, Hash)> = accounts
+        .iter()
+        .filter(|(_, account)| !is_empty_account(account))
+        .map(|(address, account)| (*address, leaf_hash(address, account)))
+        .collect();
+    leaves.sort_unstable_by_key(|(address, _)| *address);
+
+    subtree_root(&leaves, 0)
+}
+
+/// Hash a single account leaf
+pub fn leaf_hash(address: &PublicKeyBytes, account: &AccountState) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([LEAF_PREFIX]);
+    hasher.update(address);
+    hasher.update(account_commitment_bytes(account));
+    hasher.finalize().into()
+}
+
+/// Check whether an account carries no state at all
+fn is_empty_account(account: &AccountState) -> bool {
+    account.balance == 0
+        && account.nonce == 0
+        && account.stake == 0
+        && account.unbonding == 0
+        && account.code.is_none()
+        && account.storage.is_empty()
+}
+
+/// Encode an account deterministically for hashing
+///
+/// The storage map is written in key order, since `HashMap` iteration order
+/// differs between nodes. Stake is only appended for accounts holding some,
+/// so accounts that never staked commit to the same bytes as before staking
+/// existed.
+fn account_commitment_bytes(account: &AccountState) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(8 + 8 + 1 + 8);
+    bytes.extend_from_slice(&account.balance.to_le_bytes());
+    bytes.extend_from_slice(&account.nonce.to_le_bytes());
+
+    match &account.code {
+        Some(code) => {
+            bytes.push(1);
+            bytes.extend_from_slice(&(code.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(code);
+        }
+        None => bytes.push(0),
+    }
+
+    let mut storage: Vec<_> = account.storage.iter().collect();
+    storage.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    bytes.extend_from_slice(&(storage.len() as u64).to_le_bytes());
+    for (key, value) in storage {
+        bytes.extend_from_slice(key);
+        bytes.extend_from_slice(&(value.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(value);
+    }
+
+    if account.stake != 0 || account.unbonding != 0 {
+        bytes.extend_from_slice(&account.stake.to_le_bytes());
+        bytes.extend_from_slice(&account.unbonding.to_le_bytes());
+    }
+
+    bytes
+}
+
+/// Compute the root of the subtree holding `leaves`, which share their first `depth` bits
+fn subtree_root(leaves: &[(PublicKeyBytes, Hash)], depth: usize) -> Hash {
+    match leaves.len() {
+        0 => EMPTY_ROOT,
+        1 => leaves[0].1,
+        _ => {
+            // Leaves are sorted, so the ones with a zero bit at this depth come first
+            let split = leaves.partition_point(|(address, _)| !bit_at(address, depth));
+            let left = subtree_root(&leaves[..split], depth + 1);
+            let right = subtree_root(&leaves[split..], depth + 1);
+            node_hash(&left, &right)
+        }
+    }
+}
+
+/// Hash an internal node
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// Get the bit of an address at the given depth, most significant bit first
+fn bit_at(address: &PublicKeyBytes, depth: usize) -> bool {
+    (address[depth / 8] >> (7 - depth % 8)) & 1 == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_state_root() {
+        assert_eq!(compute_state_root(&HashMap::new()), EMPTY_ROOT);
+
+        // Empty accounts do not affect the root
+        let mut accounts = HashMap::new();
+        accounts.insert([1u8; 32], AccountState::new());
+        assert_eq!(compute_state_root(&accounts), EMPTY_ROOT);
+    }
+
+    #[test]
+    fn test_single_account_root_is_leaf() {
+        let mut accounts = HashMap::new();
+        let account = AccountState::with_balance(100);
+        accounts.insert([1u8; 32], account.clone());
+
+        assert_eq!(compute_state_root(&accounts), leaf_hash(&[1u8; 32], &account));
+    }
+
+    #[test]
+    fn test_state_root_changes_with_state() {
+        let mut accounts = HashMap::new();
+        accounts.insert([1u8; 32], AccountState::with_balance(100));
+        accounts.insert([0x80u8; 32], AccountState::with_balance(200));
+        let root = compute_state_root(&accounts);
+
+        accounts.get_mut(&[1u8; 32]).unwrap().balance = 101;
+        assert_ne!(compute_state_root(&accounts), root);
+
+        accounts.get_mut(&[1u8; 32]).unwrap().balance = 100;
+        assert_eq!(compute_state_root(&accounts), root);
+
+        // Moving coins into stake changes the account too
+        let staker = accounts.get_mut(&[1u8; 32]).unwrap();
+        staker.balance = 50;
+        staker.stake = 50;
+        assert_ne!(compute_state_root(&accounts), root);
+    }
+
+    #[test]
+    fn test_state_root_ignores_storage_order() {
+        let mut first = AccountState::with_balance(10);
+        let mut second = AccountState::with_balance(10);
+        for i in 0..16u8 {
+            first.storage.insert([i; 32], vec![i]);
+        }
+        for i in (0..16u8).rev() {
+            second.storage.insert([i; 32], vec![i]);
+        }
+
+        assert_eq!(leaf_hash(&[3u8; 32], &first), leaf_hash(&[3u8; 32], &second));
+    }
+
+    #[test]
+    fn test_bit_at() {
+        let mut address = [0u8; 32];
+        address[0] = 0b1000_0001;
+        address[31] = 0b0000_0001;
+
+        assert!(bit_at(&address, 0));
+        assert!(!bit_at(&address, 1));
+        assert!(bit_at(&address, 7));
+        assert!(bit_at(&address, 255));
+        assert!(!bit_at(&address, 254));
+    }
+}
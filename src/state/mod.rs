@@ -2,8 +2,14 @@
 //!
 //! This module handles the account state and state transitions in the blockchain.
 
-use crate::transaction::Transaction;
-use crate::types::PublicKeyBytes;
+pub mod merkle;
+pub mod staking;
+
+pub use staking::{Validator, ValidatorSet};
+
+use crate::consensus::ConsensusConfig;
+use crate::transaction::{Transaction, TransactionKind, STAKING_ADDRESS};
+use crate::types::{Hash, PublicKeyBytes};
 use serde::{Serialize, Deserialize};
 use std::collections::HashMap;
 
@@ -14,6 +20,10 @@ pub struct AccountState {
     pub balance: u64,
     /// Transaction counter for replay protection
     pub nonce: u64,
+    /// Balance locked as validator stake
+    pub stake: u64,
+    /// Unstaked coins waiting for the end of the epoch to return to the balance
+    pub unbonding: u64,
     /// Optional smart contract code (for future use)
     pub code: Option<Vec<u8>>,
     /// Account storage (for future smart contract use)
@@ -26,6 +36,8 @@ impl AccountState {
         Self {
             balance: 0,
             nonce: 0,
+            stake: 0,
+            unbonding: 0,
             code: None,
             storage: HashMap::new(),
         }
@@ -36,6 +48,8 @@ impl AccountState {
         Self {
             balance,
             nonce: 0,
+            stake: 0,
+            unbonding: 0,
             code: None,
             storage: HashMap::new(),
         }
@@ -48,6 +62,79 @@ impl Default for AccountState {
     }
 }
 
+/// Account changes produced by applying a block
+#[derive(Debug, Clone, Default)]
+pub struct StateDiff {
+    /// New state of every account the block touched
+    pub updated: HashMap<PublicKeyBytes, AccountState>,
+    /// Record restoring those accounts to their state before the block
+    pub undo: BlockUndo,
+}
+
+/// Undo record stored alongside each block
+///
+/// Holds the state every touched account had before the block, so the block
+/// can be rolled back without re-executing the chain.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+pub struct BlockUndo {
+    /// Previous account states, `None` for accounts the block created
+    pub previous: Vec<(PublicKeyBytes, Option<AccountState>)>,
+}
+
+/// A block transaction that could not be applied to the state
+#[derive(Debug)]
+pub struct ApplyError {
+    /// Position of the transaction in the block
+    pub index: usize,
+    /// Why the transaction was refused
+    pub error: crate::Error,
+}
+
+impl From<ApplyError> for crate::Error {
+    fn from(err: ApplyError) -> Self {
+        err.error
+    }
+}
+
+/// Issuance schedule for block rewards
+///
+/// Every block credits its validator with the fees of its transactions plus
+/// newly issued coins. Issuance starts at `initial_reward` and halves every
+/// `halving_interval` blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RewardConfig {
+    /// Coins issued by each block before the first halving
+    pub initial_reward: u64,
+    /// Number of blocks between halvings, 0 to never halve
+    pub halving_interval: u64,
+}
+
+impl RewardConfig {
+    /// Get the coins issued by the block at the given height
+    ///
+    /// The genesis block issues nothing.
+    pub fn block_reward(&self, height: u64) -> u64 {
+        if height == 0 {
+            return 0;
+        }
+        if self.halving_interval == 0 {
+            return self.initial_reward;
+        }
+        
+        let halvings = (height - 1) / self.halving_interval;
+        self.initial_reward.checked_shr(halvings.min(u32::MAX as u64) as u32).unwrap_or(0)
+    }
+}
+
+impl Default for RewardConfig {
+    fn default() -> Self {
+        Self {
+            initial_reward: 50,
+            halving_interval: 10_000_000,
+        }
+    }
+}
+
 /// Global blockchain state
 #[derive(Debug, Clone, Default)]
 pub struct BlockchainState {
@@ -69,7 +156,20 @@ impl BlockchainState {
     }
     
     /// Apply a transaction to the state
+    ///
+    /// Transfers credit the recipient, staking transactions move coins
+    /// between the sender's balance and its stake, and evidence slashes the
+    /// validator it proves guilty.
     pub fn apply_transaction(&mut self, tx: &Transaction) -> Result<(), crate::Error> {
+        let kind = tx.kind()?;
+        let slashing = match kind {
+            TransactionKind::Evidence => {
+                let evidence = tx.evidence()?;
+                Some((self.check_evidence(&evidence)?, evidence.height()))
+            }
+            _ => None,
+        };
+
         // Get or create sender account
         let sender_account = self.get_account_state(&tx.sender);
         
@@ -83,7 +183,7 @@ impl BlockchainState {
         }
         
         // Verify balance
-        let total_deduction = tx.amount.saturating_add(tx.fee);
+        let total_deduction = tx.balance_cost();
         if sender_account.balance < total_deduction {
             return Err(crate::Error::Validation(format!(
                 "Insufficient balance: has {}, needs {}",
@@ -91,28 +191,155 @@ impl BlockchainState {
                 total_deduction
             )));
         }
+        if kind == TransactionKind::Unstake && sender_account.stake < tx.amount {
+            return Err(crate::Error::Validation(format!(
+                "Insufficient stake: has {}, needs {}",
+                sender_account.stake,
+                tx.amount
+            )));
+        }
         
         // Deduct from sender
         sender_account.balance = sender_account.balance.saturating_sub(total_deduction);
         // Increment sender's nonce
         sender_account.nonce += 1;
         
-        // Add to recipient (create if doesn't exist)
-        let recipient_account = self.get_account_state(&tx.recipient);
-        recipient_account.balance = recipient_account.balance.saturating_add(tx.amount);
+        match kind {
+            TransactionKind::Transfer => {
+                // Add to recipient (create if doesn't exist)
+                let recipient_account = self.get_account_state(&tx.recipient);
+                recipient_account.balance = recipient_account.balance.saturating_add(tx.amount);
+            }
+            TransactionKind::Stake => {
+                sender_account.stake = sender_account.stake.saturating_add(tx.amount);
+            }
+            TransactionKind::Unstake => {
+                sender_account.stake -= tx.amount;
+                sender_account.unbonding = sender_account.unbonding.saturating_add(tx.amount);
+            }
+            TransactionKind::Evidence => {
+                if let Some((offender, height)) = slashing {
+                    self.slash(&offender, height);
+                }
+            }
+        }
         
-        // Note: Fees are collected separately by validators
+        // Note: Fees are credited to the block validator once the whole block is applied
         
         Ok(())
     }
     
-    /// Apply a block's transactions to the state
-    pub fn apply_block(&mut self, block: &crate::block::Block) -> Result<(), crate::Error> {
-        for tx in &block.transactions {
-            self.apply_transaction(tx)?;
+    /// Apply a block's transactions and validator reward to the state
+    pub fn apply_block(
+        &mut self,
+        block: &crate::block::Block,
+        rewards: &RewardConfig,
+        consensus: &ConsensusConfig,
+    ) -> Result<(), crate::Error> {
+        self.apply_block_with_diff(block, rewards, consensus)?;
+        Ok(())
+    }
+    
+    /// Apply a block and record how to undo it
+    ///
+    /// On failure the state is left exactly as it was before the call.
+    ///
+    /// # Returns
+    /// The resulting account states of every touched account, along with the
+    /// undo record that restores them
+    pub fn apply_block_with_diff(
+        &mut self,
+        block: &crate::block::Block,
+        rewards: &RewardConfig,
+        consensus: &ConsensusConfig,
+    ) -> Result<StateDiff, ApplyError> {
+        self.apply_transactions_with_diff(
+            &block.transactions,
+            &block.header.validator,
+            block.header.height,
+            rewards,
+            consensus,
+        )
+    }
+    
+    /// Apply the transactions of a block that is not built yet and record how to undo them
+    ///
+    /// Behaves like [`Self::apply_block_with_diff`] for a block at `height`
+    /// produced by `validator`: after the transactions, the validator is
+    /// credited with their fees plus the block's issuance. A block closing an
+    /// epoch of `consensus.epoch_length` blocks then releases unbonded stake
+    /// and elects the validator set of the next epoch.
+    pub fn apply_transactions_with_diff(
+        &mut self,
+        transactions: &[Transaction],
+        validator: &PublicKeyBytes,
+        height: u64,
+        rewards: &RewardConfig,
+        consensus: &ConsensusConfig,
+    ) -> Result<StateDiff, ApplyError> {
+        let mut undo = BlockUndo::default();
+        let mut seen = std::collections::HashSet::new();
+        let mut fees: u64 = 0;
+        
+        for (index, tx) in transactions.iter().enumerate() {
+            // Remember each account the first time the block touches it
+            for address in touched_accounts(tx) {
+                if seen.insert(address) {
+                    undo.previous.push((address, self.accounts.get(&address).cloned()));
+                }
+            }
+            
+            if let Err(error) = self.apply_transaction(tx) {
+                self.apply_undo(&undo);
+                return Err(ApplyError { index, error });
+            }
+            fees = fees.saturating_add(tx.fee);
         }
         
-        Ok(())
+        let reward = fees.saturating_add(rewards.block_reward(height));
+        if reward > 0 {
+            if seen.insert(*validator) {
+                undo.previous.push((*validator, self.accounts.get(validator).cloned()));
+            }
+            let validator_account = self.get_account_state(validator);
+            validator_account.balance = validator_account.balance.saturating_add(reward);
+        }
+        
+        if height > 0 && height.is_multiple_of(consensus.epoch_length) {
+            for address in self.epoch_end_accounts() {
+                if seen.insert(address) {
+                    undo.previous.push((address, self.accounts.get(&address).cloned()));
+                }
+            }
+            self.end_epoch(height / consensus.epoch_length, consensus);
+        }
+        
+        let updated = undo
+            .previous
+            .iter()
+            .map(|(address, _)| (*address, self.accounts.get(address).cloned().unwrap_or_default()))
+            .collect();
+        
+        Ok(StateDiff { updated, undo })
+    }
+    
+    /// Restore the accounts recorded in an undo record
+    pub fn apply_undo(&mut self, undo: &BlockUndo) {
+        for (address, previous) in &undo.previous {
+            match previous {
+                Some(account) => {
+                    self.accounts.insert(*address, account.clone());
+                }
+                None => {
+                    self.accounts.remove(address);
+                }
+            }
+        }
+    }
+    
+    /// Compute the Merkle root committing to every account
+    pub fn state_root(&self) -> Hash {
+        merkle::compute_state_root(&self.accounts)
     }
     
     /// Create genesis state with initial account balances
@@ -127,6 +354,22 @@ impl BlockchainState {
     }
 }
 
+/// Get the accounts applying a transaction may change
+///
+/// Staking transactions leave the staking account alone unless they report
+/// an equivocation, which changes the validator set and the offender.
+fn touched_accounts(tx: &Transaction) -> Vec<PublicKeyBytes> {
+    match tx.kind() {
+        Ok(TransactionKind::Transfer) | Err(_) => vec![tx.sender, tx.recipient],
+        Ok(TransactionKind::Stake | TransactionKind::Unstake) => vec![tx.sender],
+        Ok(TransactionKind::Evidence) => {
+            let mut accounts = vec![tx.sender, STAKING_ADDRESS];
+            accounts.extend(tx.evidence().ok().map(|evidence| evidence.first.validator));
+            accounts
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -203,4 +446,78 @@ mod tests {
         assert_eq!(sender_balance, 490); // 1000 - 500 - 10(fee)
         assert_eq!(recipient_balance, 500); // received 500
     }
+    
+    #[test]
+    fn test_apply_block_with_diff_and_undo() {
+        let mut state = BlockchainState::new();
+        let sender = [1u8; 32];
+        let recipient = [2u8; 32];
+        state.accounts.insert(sender, AccountState::with_balance(1000));
+        
+        let txs = vec![
+            Transaction::new(sender, recipient, 500, 10, 0, vec![]),
+            Transaction::new(sender, recipient, 100, 10, 1, vec![]),
+        ];
+        let block = crate::block::Block::new([0u8; 32], 1, txs, [0u8; 32]).unwrap();
+        
+        let diff = state
+            .apply_block_with_diff(&block, &RewardConfig::default(), &ConsensusConfig::default())
+            .unwrap();
+        assert_eq!(diff.updated[&sender].balance, 380);
+        assert_eq!(diff.updated[&sender].nonce, 2);
+        assert_eq!(diff.updated[&recipient].balance, 600);
+        // The validator collects the fees plus the block issuance
+        assert_eq!(diff.updated[&[0u8; 32]].balance, 20 + 50);
+        assert_eq!(diff.undo.previous.len(), 3);
+        
+        state.apply_undo(&diff.undo);
+        assert_eq!(state.accounts[&sender].balance, 1000);
+        assert_eq!(state.accounts[&sender].nonce, 0);
+        // The recipient and validator did not exist before the block
+        assert!(!state.accounts.contains_key(&recipient));
+        assert!(!state.accounts.contains_key(&[0u8; 32]));
+    }
+    
+    #[test]
+    fn test_failed_block_leaves_state_untouched() {
+        let mut state = BlockchainState::new();
+        let sender = [1u8; 32];
+        let recipient = [2u8; 32];
+        state.accounts.insert(sender, AccountState::with_balance(600));
+        
+        // The second transfer overdraws the account
+        let txs = vec![
+            Transaction::new(sender, recipient, 500, 10, 0, vec![]),
+            Transaction::new(sender, recipient, 500, 10, 1, vec![]),
+        ];
+        let block = crate::block::Block::new([0u8; 32], 1, txs, [0u8; 32]).unwrap();
+        
+        assert!(state
+            .apply_block_with_diff(&block, &RewardConfig::default(), &ConsensusConfig::default())
+            .is_err());
+        assert_eq!(state.accounts[&sender].balance, 600);
+        assert_eq!(state.accounts[&sender].nonce, 0);
+        assert!(!state.accounts.contains_key(&recipient));
+        assert!(!state.accounts.contains_key(&[0u8; 32]));
+    }
+    
+    #[test]
+    fn test_block_reward_schedule() {
+        let rewards = RewardConfig {
+            initial_reward: 100,
+            halving_interval: 10,
+        };
+        assert_eq!(rewards.block_reward(0), 0);
+        assert_eq!(rewards.block_reward(1), 100);
+        assert_eq!(rewards.block_reward(10), 100);
+        assert_eq!(rewards.block_reward(11), 50);
+        assert_eq!(rewards.block_reward(21), 25);
+        assert_eq!(rewards.block_reward(10 * 64 + 1), 0);
+        
+        let flat = RewardConfig {
+            initial_reward: 7,
+            halving_interval: 0,
+        };
+        assert_eq!(flat.block_reward(u64::MAX), 7);
+    }
 }
@@ -0,0 +1,340 @@
+//! Validator staking
+//!
+//! Validators bond coins with stake transactions, which move them from the
+//! account balance to its stake. Unstaking moves them on to `unbonding`,
+//! where they stay until the end of the epoch before returning to the
+//! balance, so a validator cannot pull its stake out from under a block it
+//! just produced.
+//!
+//! The end of every epoch also elects the validator set of the next one:
+//! the `max_validators` accounts with the most stake, counting only stakes
+//! of at least `min_stake`. The set is kept in the storage of the staking
+//! account, so it is committed to by the state root and persisted and
+//! rolled back along with the accounts. An empty set leaves block
+//! production open to anyone, which is how networks without genesis
+//! validators run.
+//!
+//! A validator caught signing two blocks at one height loses its whole
+//! bonded stake, unbonding coins included, and leaves the validator set
+//! right away. The staking account remembers each punished offence, so the
+//! same evidence cannot slash the validator again once it restakes.
+
+use super::BlockchainState;
+use crate::consensus::{ConsensusConfig, EquivocationEvidence};
+use crate::transaction::STAKING_ADDRESS;
+use crate::types::PublicKeyBytes;
+
+/// Storage slot of the staking account holding the validator set
+const VALIDATOR_SET_KEY: [u8; 32] = [0u8; 32];
+
+/// Domain separator of the storage slots marking punished offences
+const PUNISHMENT_DOMAIN: &[u8] = b"blocana-equivocation";
+
+/// A validator of the active set
+#[derive(Debug, Clone, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct Validator {
+    /// Validator public key
+    pub public_key: PublicKeyBytes,
+    /// Stake the validator had bonded when it was elected
+    pub stake: u64,
+}
+
+/// Validators allowed to produce blocks during an epoch
+#[derive(Debug, Clone, Default, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub struct ValidatorSet {
+    /// Epoch the set was elected for
+    pub epoch: u64,
+    /// Validators, largest stake first
+    pub validators: Vec<Validator>,
+}
+
+impl ValidatorSet {
+    /// Check whether the set is empty, leaving block production open
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty()
+    }
+
+    /// Get the number of validators
+    pub fn len(&self) -> usize {
+        self.validators.len()
+    }
+
+    /// Check whether a key belongs to the set
+    pub fn contains(&self, public_key: &PublicKeyBytes) -> bool {
+        self.validators.iter().any(|validator| validator.public_key == *public_key)
+    }
+}
+
+impl BlockchainState {
+    /// Get the active validator set
+    pub fn validator_set(&self) -> ValidatorSet {
+        self.accounts
+            .get(&STAKING_ADDRESS)
+            .and_then(|account| account.storage.get(&VALIDATOR_SET_KEY))
+            .and_then(|bytes| bincode::decode_from_slice(bytes, bincode::config::standard()).ok())
+            .map(|(set, _)| set)
+            .unwrap_or_default()
+    }
+
+    /// Replace the active validator set
+    ///
+    /// An empty set is not stored, so a chain nobody stakes on keeps an
+    /// empty staking account.
+    pub fn set_validator_set(&mut self, set: &ValidatorSet) {
+        if set.is_empty() {
+            if let Some(account) = self.accounts.get_mut(&STAKING_ADDRESS) {
+                account.storage.remove(&VALIDATOR_SET_KEY);
+            }
+            return;
+        }
+
+        // Encoding integers and byte arrays into a Vec cannot fail
+        let bytes = bincode::encode_to_vec(set, bincode::config::standard())
+            .expect("validator set is encodable");
+        self.get_account_state(&STAKING_ADDRESS)
+            .storage
+            .insert(VALIDATOR_SET_KEY, bytes);
+    }
+
+    /// Elect the validators with the most stake
+    ///
+    /// Only stakes of at least `min_stake` count. Equal stakes are ordered by
+    /// key, so every node elects the same set.
+    pub fn elect_validators(&self, max_validators: u32, min_stake: u64) -> Vec<Validator> {
+        let mut candidates: Vec<Validator> = self
+            .accounts
+            .iter()
+            .filter(|(_, account)| account.stake > 0 && account.stake >= min_stake)
+            .map(|(public_key, account)| Validator {
+                public_key: *public_key,
+                stake: account.stake,
+            })
+            .collect();
+        candidates.sort_by(|a, b| b.stake.cmp(&a.stake).then(a.public_key.cmp(&b.public_key)));
+        candidates.truncate(max_validators as usize);
+        candidates
+    }
+
+    /// Check that evidence proves an offence that can still be punished
+    ///
+    /// # Returns
+    /// The offending validator
+    pub fn check_evidence(&self, evidence: &EquivocationEvidence) -> Result<PublicKeyBytes, crate::Error> {
+        let offender = evidence
+            .offender()
+            .map_err(|e| crate::Error::Validation(format!("Invalid equivocation evidence: {:?}", e)))?;
+
+        let punished = self
+            .accounts
+            .get(&STAKING_ADDRESS)
+            .is_some_and(|account| account.storage.contains_key(&punishment_key(&offender, evidence.height())));
+        if punished {
+            return Err(crate::Error::Validation(format!(
+                "Equivocation of {} at height {} was already punished",
+                hex::encode(offender),
+                evidence.height()
+            )));
+        }
+
+        let bonded = self
+            .accounts
+            .get(&offender)
+            .map_or(0, |account| account.stake.saturating_add(account.unbonding));
+        if bonded == 0 {
+            return Err(crate::Error::Validation(format!(
+                "Validator {} has no stake to slash",
+                hex::encode(offender)
+            )));
+        }
+
+        Ok(offender)
+    }
+
+    /// Burn the bonded stake of a validator that equivocated at `height` and remove it from the set
+    pub(super) fn slash(&mut self, offender: &PublicKeyBytes, height: u64) {
+        let account = self.get_account_state(offender);
+        account.stake = 0;
+        account.unbonding = 0;
+
+        let mut set = self.validator_set();
+        set.validators.retain(|validator| validator.public_key != *offender);
+        self.set_validator_set(&set);
+
+        self.get_account_state(&STAKING_ADDRESS)
+            .storage
+            .insert(punishment_key(offender, height), vec![1]);
+    }
+
+    /// Get the accounts the end of an epoch changes
+    pub(super) fn epoch_end_accounts(&self) -> Vec<PublicKeyBytes> {
+        self.accounts
+            .iter()
+            .filter(|(_, account)| account.unbonding > 0)
+            .map(|(address, _)| *address)
+            .chain(std::iter::once(STAKING_ADDRESS))
+            .collect()
+    }
+
+    /// Release unbonding stake and elect the validator set of `next_epoch`
+    pub(super) fn end_epoch(&mut self, next_epoch: u64, consensus: &ConsensusConfig) {
+        for account in self.accounts.values_mut() {
+            account.balance = account.balance.saturating_add(account.unbonding);
+            account.unbonding = 0;
+        }
+
+        let set = ValidatorSet {
+            epoch: next_epoch,
+            validators: self.elect_validators(consensus.max_validators, consensus.min_stake),
+        };
+        self.set_validator_set(&set);
+    }
+}
+
+/// Get the storage slot marking a validator's equivocation at a height as punished
+fn punishment_key(offender: &PublicKeyBytes, height: u64) -> [u8; 32] {
+    crate::crypto::hash_data(&[PUNISHMENT_DOMAIN, offender.as_slice(), &height.to_le_bytes()].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{AccountState, RewardConfig};
+    use crate::transaction::Transaction;
+
+    fn consensus() -> ConsensusConfig {
+        ConsensusConfig {
+            max_validators: 2,
+            min_stake: 100,
+            epoch_length: 10,
+            ..ConsensusConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_stake_and_unstake() {
+        let mut state = BlockchainState::new();
+        let staker = [1u8; 32];
+        state.accounts.insert(staker, AccountState::with_balance(1000));
+
+        state.apply_transaction(&Transaction::new_stake(staker, 600, 10, 0)).unwrap();
+        assert_eq!(state.accounts[&staker].balance, 390);
+        assert_eq!(state.accounts[&staker].stake, 600);
+        // Nothing is sent to the staking address
+        assert!(!state.accounts.contains_key(&STAKING_ADDRESS));
+
+        // Only bonded coins can be unstaked, and only the fee comes from the balance
+        assert!(state.apply_transaction(&Transaction::new_unstake(staker, 601, 10, 1)).is_err());
+        state.apply_transaction(&Transaction::new_unstake(staker, 200, 10, 1)).unwrap();
+        assert_eq!(state.accounts[&staker].balance, 380);
+        assert_eq!(state.accounts[&staker].stake, 400);
+        assert_eq!(state.accounts[&staker].unbonding, 200);
+
+        // Bonded coins cannot be spent again
+        assert!(state.apply_transaction(&Transaction::new_stake(staker, 381, 0, 2)).is_err());
+    }
+
+    #[test]
+    fn test_election() {
+        let mut state = BlockchainState::new();
+        for (key, stake) in [([1u8; 32], 500), ([2u8; 32], 50), ([3u8; 32], 700), ([4u8; 32], 500)] {
+            state.get_account_state(&key).stake = stake;
+        }
+
+        let elected = state.elect_validators(2, 100);
+        let keys: Vec<_> = elected.iter().map(|v| v.public_key).collect();
+        assert_eq!(keys, vec![[3u8; 32], [1u8; 32]]);
+        assert_eq!(state.elect_validators(10, 100).len(), 3);
+
+        // An empty set leaves the staking account empty
+        state.set_validator_set(&ValidatorSet { epoch: 1, validators: elected });
+        assert_eq!(state.validator_set().len(), 2);
+        state.set_validator_set(&ValidatorSet::default());
+        assert!(state.validator_set().is_empty());
+        assert!(state.accounts[&STAKING_ADDRESS].storage.is_empty());
+    }
+
+    #[test]
+    fn test_epoch_boundary() {
+        let consensus = consensus();
+        let rewards = RewardConfig::default();
+        let staker = [1u8; 32];
+        let mut state = BlockchainState::new();
+        state.accounts.insert(staker, AccountState::with_balance(1000));
+
+        let stake = Transaction::new_stake(staker, 500, 10, 0);
+        state
+            .apply_transactions_with_diff(&[stake], &[9u8; 32], 9, &rewards, &consensus)
+            .unwrap();
+        let unstake = Transaction::new_unstake(staker, 100, 10, 1);
+        state
+            .apply_transactions_with_diff(&[unstake], &[9u8; 32], 9, &rewards, &consensus)
+            .unwrap();
+        assert!(state.validator_set().is_empty());
+
+        // Block 10 closes the first epoch
+        let before = state.clone();
+        let diff = state
+            .apply_transactions_with_diff(&[], &[9u8; 32], 10, &rewards, &consensus)
+            .unwrap();
+        let set = state.validator_set();
+        assert_eq!(set.epoch, 1);
+        assert_eq!(set.validators, vec![Validator { public_key: staker, stake: 400 }]);
+        assert_eq!(state.accounts[&staker].unbonding, 0);
+        assert_eq!(state.accounts[&staker].balance, 480 + 100);
+        assert_eq!(diff.updated[&STAKING_ADDRESS].storage, state.accounts[&STAKING_ADDRESS].storage);
+
+        // The undo record covers the epoch change as well
+        state.apply_undo(&diff.undo);
+        assert_eq!(state.state_root(), before.state_root());
+        assert!(state.validator_set().is_empty());
+    }
+
+    #[test]
+    fn test_equivocation_is_slashed_once() {
+        use crate::block::{BlockHeader, BLOCK_VERSION};
+        use crate::crypto::KeyPair;
+
+        let offender = KeyPair::generate().unwrap();
+        let honest = [2u8; 32];
+        let reporter = [3u8; 32];
+        let mut state = BlockchainState::new();
+        state.accounts.insert(reporter, AccountState::with_balance(1000));
+        for (key, stake) in [(offender.public_key, 500), (honest, 500)] {
+            let account = state.get_account_state(&key);
+            account.stake = stake;
+        }
+        state.get_account_state(&offender.public_key).unbonding = 100;
+        let validators = state.elect_validators(10, 100);
+        state.set_validator_set(&ValidatorSet { epoch: 1, validators });
+
+        let sign = |timestamp| {
+            let mut header = BlockHeader::new(BLOCK_VERSION, [1u8; 32], [0u8; 32], 7, offender.public_key);
+            header.timestamp = timestamp;
+            header.sign(&offender.private_key).unwrap();
+            header
+        };
+        let evidence = EquivocationEvidence { first: sign(1000), second: sign(1001) };
+
+        let before = state.clone();
+        let report = Transaction::new_evidence(reporter, &evidence, 10, 0);
+        let diff = state
+            .apply_transactions_with_diff(&[report], &honest, 8, &RewardConfig::default(), &consensus())
+            .unwrap();
+        assert_eq!(state.accounts[&offender.public_key].stake, 0);
+        assert_eq!(state.accounts[&offender.public_key].unbonding, 0);
+        assert_eq!(state.accounts[&reporter].balance, 990);
+        let set = state.validator_set();
+        assert!(!set.contains(&offender.public_key));
+        assert!(set.contains(&honest));
+
+        // Restaking does not expose the validator to the same evidence again
+        state.get_account_state(&offender.public_key).stake = 500;
+        assert!(state.check_evidence(&evidence).is_err());
+        let replay = Transaction::new_evidence(reporter, &evidence, 10, 1);
+        assert!(state.apply_transaction(&replay).is_err());
+
+        state.apply_undo(&diff.undo);
+        assert_eq!(state.state_root(), before.state_root());
+        assert!(state.check_evidence(&evidence).is_ok());
+    }
+}
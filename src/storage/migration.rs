@@ -3,11 +3,14 @@
 //! This module provides tools for managing database schema changes
 //! and migrations between different versions of the database.
 
-use super::{BlockchainStorage, Error};
+use super::{BlockMeta, BlockchainStorage, Error, CHAIN_TIP_KEY};
+use crate::state::{AccountState, BlockUndo};
+use crate::types::{Hash, PublicKeyBytes};
+use std::collections::HashMap;
 use rocksdb::DB ;
 
 /// Current database schema version
-pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+pub const CURRENT_SCHEMA_VERSION: u32 = 4;
 
 /// Migration descriptor for a database schema change
 pub struct Migration {
@@ -80,19 +83,182 @@ pub fn available_migrations() -> Vec<Migration> {
                 Ok(())
             },
         },
-        // Example future migration:
-        // Migration {
-        //     from_version: 1,
-        //     to_version: 2,
-        //     description: "Add timestamp index",
-        //     migrate_fn: |storage| {
-        //         // Migration logic would go here
-        //         Ok(())
-        //     },
-        // },
+        Migration {
+            from_version: 1,
+            to_version: 2,
+            description: "Record block metadata and chain tip for fork tracking",
+            migrate_fn: backfill_block_meta,
+        },
+        Migration {
+            from_version: 2,
+            to_version: 3,
+            description: "Flag blocks that failed to apply in their metadata",
+            migrate_fn: add_invalid_flag_to_block_meta,
+        },
+        Migration {
+            from_version: 3,
+            to_version: 4,
+            description: "Add stake and unbonding balances to account states",
+            migrate_fn: add_stake_to_account_states,
+        },
     ]
 }
 
+/// Writes block metadata for every main chain block and records the chain tip
+///
+/// Databases before version 2 only know the main chain, so each block's
+/// cumulative weight is simply its position in that chain.
+fn backfill_block_meta(storage: &BlockchainStorage) -> Result<(), Error> {
+    let cfs = storage.get_column_families()?;
+    let db = storage.raw_db();
+    let latest_height = storage.get_latest_height()?;
+
+    let mut batch = rocksdb::WriteBatch::default();
+    let mut tip = None;
+    let mut weight = 0;
+    for height in 0..=latest_height {
+        let hash = match storage.get_block_hash_by_height(height) {
+            Ok(hash) => hash,
+            Err(Error::NotFound(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        let block = storage.get_block(&hash)?.ok_or_else(|| {
+            Error::Database(format!("Block at height {} is indexed but missing", height))
+        })?;
+
+        weight += 1;
+        let meta = BlockMeta {
+            prev_hash: block.header.prev_hash,
+            height,
+            cumulative_weight: weight,
+            invalid: false,
+        };
+        batch.put_cf(cfs.block_meta, hash, bincode::encode_to_vec(&meta, bincode::config::standard())?);
+        tip = Some(hash);
+    }
+
+    if let Some(tip) = tip {
+        batch.put_cf(cfs.metadata, CHAIN_TIP_KEY, tip);
+    }
+    db.write(batch)?;
+
+    Ok(())
+}
+
+/// Block metadata as stored before version 3
+#[derive(bincode::Encode, bincode::Decode)]
+struct BlockMetaV2 {
+    prev_hash: Hash,
+    height: u64,
+    cumulative_weight: u64,
+}
+
+/// Rewrites the metadata of every stored block with the invalid flag cleared
+///
+/// Rows that do not decode as version 2 metadata in full were already
+/// written in the new layout, by the version 2 migration itself, and are
+/// left alone.
+fn add_invalid_flag_to_block_meta(storage: &BlockchainStorage) -> Result<(), Error> {
+    let cfs = storage.get_column_families()?;
+    let db = storage.raw_db();
+
+    let mut batch = rocksdb::WriteBatch::default();
+    for item in db.iterator_cf(cfs.block_meta, rocksdb::IteratorMode::Start) {
+        let (key, value) = item?;
+        let Some(legacy) = decode_legacy::<BlockMetaV2>(&value) else {
+            continue;
+        };
+
+        let meta = BlockMeta {
+            prev_hash: legacy.prev_hash,
+            height: legacy.height,
+            cumulative_weight: legacy.cumulative_weight,
+            invalid: false,
+        };
+        batch.put_cf(cfs.block_meta, key, bincode::encode_to_vec(&meta, bincode::config::standard())?);
+    }
+    db.write(batch)?;
+
+    Ok(())
+}
+
+/// Account state as stored before version 4
+#[derive(bincode::Encode, bincode::Decode)]
+struct AccountStateV3 {
+    balance: u64,
+    nonce: u64,
+    code: Option<Vec<u8>>,
+    storage: HashMap<[u8; 32], Vec<u8>>,
+}
+
+impl From<AccountStateV3> for AccountState {
+    fn from(legacy: AccountStateV3) -> Self {
+        Self {
+            balance: legacy.balance,
+            nonce: legacy.nonce,
+            stake: 0,
+            unbonding: 0,
+            code: legacy.code,
+            storage: legacy.storage,
+        }
+    }
+}
+
+/// Block undo record as stored before version 4
+#[derive(bincode::Encode, bincode::Decode)]
+struct BlockUndoV3 {
+    previous: Vec<(PublicKeyBytes, Option<AccountStateV3>)>,
+}
+
+/// Decodes a row in an old layout, if the whole row is in that layout
+fn decode_legacy<T: bincode::Decode<()>>(bytes: &[u8]) -> Option<T> {
+    match bincode::decode_from_slice::<T, _>(bytes, bincode::config::standard()) {
+        Ok((value, read)) if read == bytes.len() => Some(value),
+        _ => None,
+    }
+}
+
+/// Rewrites every account state and block undo record with no stake and nothing unbonding
+///
+/// Staking did not exist before version 4, so no account can have any.
+/// Rows that do not decode in the old layout in full are left alone.
+fn add_stake_to_account_states(storage: &BlockchainStorage) -> Result<(), Error> {
+    let cfs = storage.get_column_families()?;
+    let db = storage.raw_db();
+
+    let mut batch = rocksdb::WriteBatch::default();
+    for item in db.iterator_cf(cfs.account_state, rocksdb::IteratorMode::Start) {
+        let (key, value) = item?;
+        if let Some(legacy) = decode_legacy::<AccountStateV3>(&value) {
+            let state = AccountState::from(legacy);
+            batch.put_cf(cfs.account_state, key, bincode::encode_to_vec(&state, bincode::config::standard())?);
+        }
+    }
+    for item in db.iterator_cf(cfs.block_undo, rocksdb::IteratorMode::Start) {
+        let (key, value) = item?;
+        if let Some(legacy) = decode_legacy::<BlockUndoV3>(&value) {
+            let undo = BlockUndo {
+                previous: legacy
+                    .previous
+                    .into_iter()
+                    .map(|(address, state)| (address, state.map(AccountState::from)))
+                    .collect(),
+            };
+            batch.put_cf(cfs.block_undo, key, bincode::encode_to_vec(&undo, bincode::config::standard())?);
+        }
+    }
+    db.write(batch)?;
+
+    Ok(())
+}
+
+/// Checks whether a database holds no blocks yet
+fn is_empty(storage: &BlockchainStorage) -> Result<bool, Error> {
+    let cfs = storage.get_column_families()?;
+    let mut blocks = storage.raw_db().iterator_cf(cfs.blocks, rocksdb::IteratorMode::Start);
+    Ok(blocks.next().is_none())
+}
+
 /// Check if a database needs migration and performs any required migrations
 pub fn check_and_migrate(
     storage: &BlockchainStorage, 
@@ -192,6 +358,12 @@ pub fn check_and_migrate(
 
 /// Verify database compatibility and migrate if needed
 pub fn ensure_compatible_schema(storage: &BlockchainStorage) -> Result<(), Error> {
+    // A new database is created at the current schema
+    if get_schema_version(storage.raw_db())? == 0 && is_empty(storage)? {
+        set_schema_version(storage.raw_db(), CURRENT_SCHEMA_VERSION)?;
+        return Ok(());
+    }
+
     let migrated = check_and_migrate(storage, MigrationConfig::default())?;
     
     if migrated {
@@ -265,4 +437,96 @@ mod tests {
         drop(storage);
         temp_dir.close().unwrap();
     }
+    
+    #[test]
+    fn test_block_meta_gains_invalid_flag() {
+        let temp_dir = tempdir().unwrap();
+        let config = StorageConfig {
+            db_path: temp_dir.path().to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let storage = BlockchainStorage::open(&config).unwrap();
+        
+        // A version 2 database with metadata in the old layout
+        let legacy = BlockMetaV2 {
+            prev_hash: [1u8; 32],
+            height: 7,
+            cumulative_weight: 8,
+        };
+        let cfs = storage.get_column_families().unwrap();
+        storage.raw_db().put_cf(
+            cfs.block_meta,
+            [2u8; 32],
+            bincode::encode_to_vec(&legacy, bincode::config::standard()).unwrap(),
+        ).unwrap();
+        set_schema_version(storage.raw_db(), 2).unwrap();
+        
+        let migrated = check_and_migrate(&storage, MigrationConfig {
+            backup_before_migration: false,
+            ..Default::default()
+        }).unwrap();
+        assert!(migrated);
+        
+        let meta = storage.get_block_meta(&[2u8; 32]).unwrap().unwrap();
+        assert_eq!(meta, BlockMeta {
+            prev_hash: [1u8; 32],
+            height: 7,
+            cumulative_weight: 8,
+            invalid: false,
+        });
+    }
+    
+    #[test]
+    fn test_account_states_gain_stake() {
+        let temp_dir = tempdir().unwrap();
+        let config = StorageConfig {
+            db_path: temp_dir.path().to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let storage = BlockchainStorage::open(&config).unwrap();
+        
+        // A version 3 database with an account and an undo record in the old layout
+        let legacy = || AccountStateV3 {
+            balance: 500,
+            nonce: 3,
+            code: None,
+            storage: HashMap::new(),
+        };
+        let undo = BlockUndoV3 {
+            previous: vec![([1u8; 32], Some(legacy())), ([2u8; 32], None)],
+        };
+        let cfs = storage.get_column_families().unwrap();
+        let db = storage.raw_db();
+        db.put_cf(cfs.account_state, [1u8; 32], bincode::encode_to_vec(legacy(), bincode::config::standard()).unwrap())
+            .unwrap();
+        db.put_cf(cfs.block_undo, [3u8; 32], bincode::encode_to_vec(&undo, bincode::config::standard()).unwrap())
+            .unwrap();
+        set_schema_version(db, 3).unwrap();
+        
+        let migrated = check_and_migrate(&storage, MigrationConfig {
+            backup_before_migration: false,
+            ..Default::default()
+        }).unwrap();
+        assert!(migrated);
+        
+        let account = storage.get_account_state(&[1u8; 32]).unwrap().unwrap();
+        assert_eq!((account.balance, account.nonce, account.stake, account.unbonding), (500, 3, 0, 0));
+        let undo = storage.get_block_undo(&[3u8; 32]).unwrap().unwrap();
+        assert_eq!(undo.previous.len(), 2);
+        assert_eq!(undo.previous[0].1.as_ref().unwrap().balance, 500);
+        assert!(undo.previous[1].1.is_none());
+    }
+    
+    #[test]
+    fn test_new_database_starts_at_current_schema() {
+        let temp_dir = tempdir().unwrap();
+        let config = StorageConfig {
+            db_path: temp_dir.path().to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let storage = BlockchainStorage::open(&config).unwrap();
+        
+        ensure_compatible_schema(&storage).unwrap();
+        assert_eq!(get_schema_version(storage.raw_db()).unwrap(), CURRENT_SCHEMA_VERSION);
+    }
 }
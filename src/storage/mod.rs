@@ -11,6 +11,8 @@
 //! - `block_height`: Maps height → block hash
 //! - `transactions`: Maps transaction hash → transaction location
 //! - `account_state`: Maps account address → account state
+//! - `peers`: Maps peer id → addresses the peer was last reachable at
+//! - `pool_journal`: Maps transaction hash → transaction waiting in the pool
 //!
 //! # Examples
 //!
@@ -36,12 +38,14 @@
 //! let retrieved_block = storage.get_block(&block_hash).unwrap();
 //! ```
 
-use crate::block::Block;
-use crate::state::AccountState;
+use crate::block::{Block, BlockHeader};
+use crate::crypto::MerkleProof;
+use crate::state::{AccountState, BlockUndo, StateDiff};
 use crate::transaction::Transaction;
 use crate::types::{Hash, PublicKeyBytes};
 use hex;
 use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, Options, WriteBatch, DB};
+use std::collections::HashMap;
 use std::path::Path;
 
 /// Storage errors
@@ -142,8 +146,19 @@ pub struct BlockchainColumnFamilies<'a> {
     pub timestamp_index: &'a ColumnFamily,
     /// New metadata column family
     pub metadata: &'a ColumnFamily,
+    /// Column family for per-block chain metadata, including side chains
+    pub block_meta: &'a ColumnFamily,
+    /// Column family for per-block state undo records
+    pub block_undo: &'a ColumnFamily,
+    /// Column family for the network's peer address book
+    pub peers: &'a ColumnFamily,
+    /// Column family for the transactions waiting in the pool
+    pub pool_journal: &'a ColumnFamily,
 }
 
+/// Metadata key under which the hash of the main chain tip is stored
+pub(crate) const CHAIN_TIP_KEY: &[u8] = b"chain_tip";
+
 /// Information about where a transaction is stored in the blockchain.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
 pub struct TxLocation {
@@ -153,6 +168,68 @@ pub struct TxLocation {
     pub index: u32,
 }
 
+/// A stored transaction with a proof that it is part of its block
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct TransactionWithProof {
+    /// Hash of the block containing the transaction
+    pub block_hash: Hash,
+    /// The transaction itself
+    pub transaction: Transaction,
+    /// Merkle proof linking the transaction to the block's merkle root
+    pub proof: MerkleProof,
+}
+
+/// Chain metadata kept for every stored block, whether or not it is on the main chain.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct BlockMeta {
+    /// Hash of the parent block
+    pub prev_hash: Hash,
+    /// Height of the block
+    pub height: u64,
+    /// Total weight of the chain ending at this block
+    ///
+    /// Every block weighs the same, so this is the number of blocks from the
+    /// first stored ancestor up to and including this one.
+    pub cumulative_weight: u64,
+    /// Whether the block or one of its ancestors failed to apply
+    ///
+    /// Invalid blocks stay stored so they are recognised when seen again,
+    /// but the main chain never switches onto them.
+    pub invalid: bool,
+}
+
+/// An entry of the peer address book
+///
+/// Peers are keyed by their encoded peer id; the storage layer does not
+/// interpret it.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct PeerRecord {
+    /// Multiaddresses the peer listens on
+    pub addresses: Vec<String>,
+    /// When the peer was last connected (ms since UNIX epoch)
+    pub last_seen: u64,
+}
+
+/// A transaction waiting in the pool, kept so the pool survives a restart
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, bincode::Encode, bincode::Decode)]
+pub struct PoolJournalEntry {
+    /// The pooled transaction
+    pub transaction: Transaction,
+    /// When the transaction entered the pool (ms since UNIX epoch)
+    pub added_at: u64,
+}
+
+/// The blocks that change when the main chain switches to another branch.
+#[derive(Debug, Clone)]
+pub struct ChainReorg {
+    /// Last block shared by the old and the new main chain
+    pub common_ancestor: Hash,
+    /// Blocks leaving the main chain, old tip first
+    pub disconnected: Vec<Block>,
+    /// Blocks joining the main chain, the child of the common ancestor first
+    pub connected: Vec<Block>,
+}
+
 /// Main storage interface for the blockchain.
 pub struct BlockchainStorage {
     /// RocksDB database instance
@@ -184,6 +261,10 @@ impl BlockchainStorage {
             "account_state",
             "timestamp_index", // New timestamp index
             "metadata",        // New metadata column family
+            "block_meta",      // Parent links and weights for all known blocks
+            "block_undo",      // Account states needed to roll blocks back
+            "peers",           // Peer address book
+            "pool_journal",    // Transactions waiting in the pool
         ];
 
         // Configure database options
@@ -270,6 +351,22 @@ impl BlockchainStorage {
             .db
             .cf_handle("metadata")
             .ok_or_else(|| Error::Database("Column family 'metadata' not found".to_string()))?;
+        let block_meta = self
+            .db
+            .cf_handle("block_meta")
+            .ok_or_else(|| Error::Database("Column family 'block_meta' not found".to_string()))?;
+        let block_undo = self
+            .db
+            .cf_handle("block_undo")
+            .ok_or_else(|| Error::Database("Column family 'block_undo' not found".to_string()))?;
+        let peers = self
+            .db
+            .cf_handle("peers")
+            .ok_or_else(|| Error::Database("Column family 'peers' not found".to_string()))?;
+        let pool_journal = self
+            .db
+            .cf_handle("pool_journal")
+            .ok_or_else(|| Error::Database("Column family 'pool_journal' not found".to_string()))?;
 
         Ok(BlockchainColumnFamilies {
             blocks,
@@ -278,11 +375,21 @@ impl BlockchainStorage {
             account_state,
             timestamp_index,
             metadata,
+            block_meta,
+            block_undo,
+            peers,
+            pool_journal,
         })
     }
 
     /// Stores a block in the database.
     ///
+    /// Every block is kept by hash together with its [`BlockMeta`]. Only a block
+    /// that extends the current main chain tip (or the first block stored) is
+    /// also added to the height, timestamp and transaction indexes and becomes
+    /// the new tip; any other block is kept as a side chain block until a
+    /// reorganization connects it.
+    ///
     /// # Parameters
     /// * `block` - The block to store
     ///
@@ -294,27 +401,86 @@ impl BlockchainStorage {
     /// - The block cannot be serialized
     /// - The database write fails
     pub fn store_block(&self, block: &Block) -> Result<(), Error> {
+        let mut batch = WriteBatch::default();
+        self.add_block_to_batch(&mut batch, block)?;
+
+        // Write batch atomically
+        self.db.write(batch)?;
+
+        Ok(())
+    }
+
+    /// Stores a block together with the state changes it produced.
+    ///
+    /// The block, its indexes, the updated account states and the block's
+    /// undo record are written in a single batch, so the stored state can
+    /// never get ahead of or behind the chain.
+    ///
+    /// # Parameters
+    /// * `block` - The block to store
+    /// * `diff` - State changes produced by applying the block
+    ///
+    /// # Errors
+    /// Returns an error if serialization or the database write fails
+    pub fn store_block_with_state(&self, block: &Block, diff: &StateDiff) -> Result<(), Error> {
+        let mut batch = WriteBatch::default();
+        self.add_block_to_batch(&mut batch, block)?;
+        self.add_state_diff_to_batch(&mut batch, &block.header.hash(), diff)?;
+
+        self.db.write(batch)?;
+
+        Ok(())
+    }
+
+    /// Adds a block and its metadata to a batch, indexing it if it extends the main chain.
+    fn add_block_to_batch(&self, batch: &mut WriteBatch, block: &Block) -> Result<(), Error> {
         let cfs = self.get_column_families()?;
 
         let block_bytes = bincode::encode_to_vec(block, bincode::config::standard())?;
         let block_hash = block.header.hash();
-        let height_bytes = block.header.height.to_le_bytes();
-        let timestamp_bytes = block.header.timestamp.to_le_bytes();
-
-        // Create a write batch for atomic operations
-        let mut batch = WriteBatch::default();
 
         // Add block to blocks column family
         batch.put_cf(cfs.blocks, block_hash, &block_bytes);
 
+        // Record the parent link and weight of the chain ending here; a
+        // block inherits the invalidity of its parent
+        let (parent_weight, parent_invalid) = self
+            .get_block_meta(&block.header.prev_hash)?
+            .map(|meta| (meta.cumulative_weight, meta.invalid))
+            .unwrap_or((0, false));
+        let meta = BlockMeta {
+            prev_hash: block.header.prev_hash,
+            height: block.header.height,
+            cumulative_weight: parent_weight + 1,
+            invalid: parent_invalid,
+        };
+        let meta_bytes = bincode::encode_to_vec(&meta, bincode::config::standard())?;
+        batch.put_cf(cfs.block_meta, block_hash, meta_bytes);
+
+        let extends_tip = match self.get_chain_tip()? {
+            Some(tip) => tip == block.header.prev_hash,
+            None => true,
+        };
+        if extends_tip {
+            self.index_main_chain_block(batch, block)?;
+            batch.put_cf(cfs.metadata, CHAIN_TIP_KEY, block_hash);
+        }
+
+        Ok(())
+    }
+
+    /// Adds the height, timestamp and transaction index entries of a main chain block to a batch.
+    fn index_main_chain_block(&self, batch: &mut WriteBatch, block: &Block) -> Result<(), Error> {
+        let cfs = self.get_column_families()?;
+
+        let block_hash = block.header.hash();
+        let height_bytes = block.header.height.to_le_bytes();
+
         // Add height -> hash mapping
-        batch.put_cf(cfs.block_height, &height_bytes, block_hash);
+        batch.put_cf(cfs.block_height, height_bytes, block_hash);
 
         // Add timestamp -> hash mapping
-        let mut timestamp_key = Vec::with_capacity(16);
-        timestamp_key.extend_from_slice(&timestamp_bytes);
-        timestamp_key.extend_from_slice(&height_bytes);
-        batch.put_cf(cfs.timestamp_index, &timestamp_key, block_hash);
+        batch.put_cf(cfs.timestamp_index, Self::timestamp_key(block), block_hash);
 
         // Index each transaction
         for (i, tx) in block.transactions.iter().enumerate() {
@@ -327,7 +493,312 @@ impl BlockchainStorage {
             batch.put_cf(cfs.transactions, tx_hash, &tx_loc_bytes);
         }
 
-        // Write batch atomically
+        Ok(())
+    }
+
+    /// Removes the height, timestamp and transaction index entries of a block leaving the main chain.
+    fn unindex_main_chain_block(&self, batch: &mut WriteBatch, block: &Block) -> Result<(), Error> {
+        let cfs = self.get_column_families()?;
+
+        batch.delete_cf(cfs.block_height, block.header.height.to_le_bytes());
+        batch.delete_cf(cfs.timestamp_index, Self::timestamp_key(block));
+        for tx in &block.transactions {
+            batch.delete_cf(cfs.transactions, tx.hash());
+        }
+
+        Ok(())
+    }
+
+    /// Adds the account states and undo record of an applied block to a batch.
+    fn add_state_diff_to_batch(
+        &self,
+        batch: &mut WriteBatch,
+        block_hash: &Hash,
+        diff: &StateDiff,
+    ) -> Result<(), Error> {
+        let cfs = self.get_column_families()?;
+
+        for (address, state) in &diff.updated {
+            let state_bytes = bincode::encode_to_vec(state, bincode::config::standard())?;
+            batch.put_cf(cfs.account_state, address, state_bytes);
+        }
+
+        let undo_bytes = bincode::encode_to_vec(&diff.undo, bincode::config::standard())?;
+        batch.put_cf(cfs.block_undo, block_hash, undo_bytes);
+
+        Ok(())
+    }
+
+    /// Adds the account restores described by an undo record to a batch.
+    fn add_undo_to_batch(&self, batch: &mut WriteBatch, undo: &BlockUndo) -> Result<(), Error> {
+        let cfs = self.get_column_families()?;
+
+        for (address, previous) in &undo.previous {
+            match previous {
+                Some(state) => {
+                    let state_bytes = bincode::encode_to_vec(state, bincode::config::standard())?;
+                    batch.put_cf(cfs.account_state, address, state_bytes);
+                }
+                None => batch.delete_cf(cfs.account_state, address),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gets the undo record of a block.
+    ///
+    /// # Parameters
+    /// * `hash` - The block hash
+    ///
+    /// # Returns
+    /// The undo record if the block was applied to the state, None otherwise
+    ///
+    /// # Errors
+    /// Returns an error if the database read or deserialization fails
+    pub fn get_block_undo(&self, hash: &Hash) -> Result<Option<BlockUndo>, Error> {
+        let cfs = self.get_column_families()?;
+        match self.db.get_cf(cfs.block_undo, hash)? {
+            Some(bytes) => {
+                let (undo, _): (BlockUndo, _) =
+                    bincode::decode_from_slice(&bytes, bincode::config::standard())?;
+                Ok(Some(undo))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Builds the key of a block in the timestamp index.
+    fn timestamp_key(block: &Block) -> Vec<u8> {
+        let mut timestamp_key = Vec::with_capacity(16);
+        timestamp_key.extend_from_slice(&block.header.timestamp.to_le_bytes());
+        timestamp_key.extend_from_slice(&block.header.height.to_le_bytes());
+        timestamp_key
+    }
+
+    /// Gets the chain metadata of a stored block.
+    ///
+    /// # Parameters
+    /// * `hash` - The block hash
+    ///
+    /// # Returns
+    /// The metadata if the block is known, None otherwise
+    ///
+    /// # Errors
+    /// Returns an error if the database read or deserialization fails
+    pub fn get_block_meta(&self, hash: &Hash) -> Result<Option<BlockMeta>, Error> {
+        let cfs = self.get_column_families()?;
+        match self.db.get_cf(cfs.block_meta, hash)? {
+            Some(bytes) => {
+                let (meta, _): (BlockMeta, _) =
+                    bincode::decode_from_slice(&bytes, bincode::config::standard())?;
+                Ok(Some(meta))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Marks stored blocks as invalid.
+    ///
+    /// Used for side chain blocks that failed to apply during a
+    /// reorganization, together with their stored descendants.
+    ///
+    /// # Parameters
+    /// * `hashes` - The blocks to mark
+    ///
+    /// # Errors
+    /// Returns an error if a block has no metadata or the database write fails
+    pub fn mark_blocks_invalid(&self, hashes: &[Hash]) -> Result<(), Error> {
+        let cfs = self.get_column_families()?;
+        let mut batch = WriteBatch::default();
+
+        for hash in hashes {
+            let mut meta = self.get_block_meta(hash)?.ok_or_else(|| {
+                Error::NotFound(format!("Metadata for block {} not found", hex::encode(hash)))
+            })?;
+            meta.invalid = true;
+            let meta_bytes = bincode::encode_to_vec(&meta, bincode::config::standard())?;
+            batch.put_cf(cfs.block_meta, hash, meta_bytes);
+        }
+
+        self.db.write(batch)?;
+
+        Ok(())
+    }
+
+    /// Gets the hash of the main chain tip.
+    ///
+    /// # Returns
+    /// The tip hash, or None if no block has been stored yet
+    ///
+    /// # Errors
+    /// Returns an error if the database read fails or the stored hash is corrupted
+    pub fn get_chain_tip(&self) -> Result<Option<Hash>, Error> {
+        let cfs = self.get_column_families()?;
+        match self.db.get_cf(cfs.metadata, CHAIN_TIP_KEY)? {
+            Some(bytes) => {
+                if bytes.len() != 32 {
+                    return Err(Error::Database("Invalid chain tip length".to_string()));
+                }
+                let mut hash = [0u8; 32];
+                hash.copy_from_slice(&bytes);
+                Ok(Some(hash))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Checks whether a block is part of the main chain.
+    ///
+    /// # Parameters
+    /// * `hash` - The block hash
+    ///
+    /// # Errors
+    /// Returns an error if the database read fails
+    pub fn is_on_main_chain(&self, hash: &Hash) -> Result<bool, Error> {
+        let meta = match self.get_block_meta(hash)? {
+            Some(meta) => meta,
+            None => return Ok(false),
+        };
+
+        match self.get_block_hash_by_height(meta.height) {
+            Ok(main_hash) => Ok(main_hash == *hash),
+            Err(Error::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Works out which blocks change if the chain ending at `new_tip` becomes the main chain.
+    ///
+    /// Walks back from `new_tip` through parent links until it reaches a main
+    /// chain block, which is the common ancestor of both branches.
+    ///
+    /// # Parameters
+    /// * `new_tip` - Hash of the tip of the branch to switch to
+    ///
+    /// # Errors
+    /// Returns an error if a block on either branch is missing or the branch
+    /// does not connect to the main chain
+    pub fn plan_reorg(&self, new_tip: &Hash) -> Result<ChainReorg, Error> {
+        let load = |hash: &Hash| {
+            self.get_block(hash)?.ok_or_else(|| {
+                Error::NotFound(format!("Block {} not found", hex::encode(hash)))
+            })
+        };
+
+        // Collect the new branch down to the first block already on the main chain
+        let mut connected = Vec::new();
+        let mut cursor = *new_tip;
+        while !self.is_on_main_chain(&cursor)? {
+            let block = load(&cursor)?;
+            if block.header.height == 0 {
+                return Err(Error::Other(format!(
+                    "Branch ending at {} does not connect to the main chain",
+                    hex::encode(new_tip)
+                )));
+            }
+            cursor = block.header.prev_hash;
+            connected.push(block);
+        }
+        connected.reverse();
+        let common_ancestor = cursor;
+
+        // Collect the main chain blocks above the common ancestor
+        let ancestor_height = self
+            .get_block_meta(&common_ancestor)?
+            .map(|meta| meta.height)
+            .ok_or_else(|| Error::NotFound("Common ancestor metadata not found".to_string()))?;
+        let mut disconnected = Vec::new();
+        for height in (ancestor_height + 1..=self.get_latest_height()?).rev() {
+            let hash = self.get_block_hash_by_height(height)?;
+            disconnected.push(load(&hash)?);
+        }
+
+        Ok(ChainReorg {
+            common_ancestor,
+            disconnected,
+            connected,
+        })
+    }
+
+    /// Works out which blocks change if the top `count` main chain blocks are rolled back.
+    ///
+    /// The genesis block is never rolled back, so fewer blocks are returned
+    /// when `count` reaches past it.
+    ///
+    /// # Parameters
+    /// * `count` - Number of blocks to roll back
+    ///
+    /// # Errors
+    /// Returns an error if a main chain block is missing
+    pub fn plan_rollback(&self, count: u64) -> Result<ChainReorg, Error> {
+        let tip_height = self.get_latest_height()?;
+        let target_height = tip_height.saturating_sub(count);
+
+        let mut disconnected = Vec::new();
+        for height in (target_height + 1..=tip_height).rev() {
+            let hash = self.get_block_hash_by_height(height)?;
+            let block = self.get_block(&hash)?.ok_or_else(|| {
+                Error::NotFound(format!("Block {} not found", hex::encode(hash)))
+            })?;
+            disconnected.push(block);
+        }
+
+        Ok(ChainReorg {
+            common_ancestor: self.get_block_hash_by_height(target_height)?,
+            disconnected,
+            connected: Vec::new(),
+        })
+    }
+
+    /// Switches the main chain to another branch.
+    ///
+    /// Rolls the disconnected blocks back using their undo records and
+    /// removes them from the height, timestamp and transaction indexes, then
+    /// indexes the connected blocks and writes their state changes, and
+    /// finally moves the tip, all in one batch.
+    ///
+    /// # Parameters
+    /// * `reorg` - The reorganization produced by [`Self::plan_reorg`] or [`Self::plan_rollback`]
+    /// * `connected` - State changes of each connected block, in the same order
+    ///
+    /// # Errors
+    /// Returns an error if a disconnected block has no undo record, the
+    /// number of state changes does not match, or the database write fails
+    pub fn apply_reorg(&self, reorg: &ChainReorg, connected: &[StateDiff]) -> Result<(), Error> {
+        if connected.len() != reorg.connected.len() {
+            return Err(Error::Other(format!(
+                "Expected state changes for {} blocks, got {}",
+                reorg.connected.len(),
+                connected.len()
+            )));
+        }
+
+        let cfs = self.get_column_families()?;
+        let mut batch = WriteBatch::default();
+
+        // Undo from the old tip downwards; deletes go first so a transaction
+        // present on both branches stays indexed
+        for block in &reorg.disconnected {
+            let block_hash = block.header.hash();
+            let undo = self.get_block_undo(&block_hash)?.ok_or_else(|| {
+                Error::NotFound(format!("Undo record for block {} not found", hex::encode(block_hash)))
+            })?;
+            self.add_undo_to_batch(&mut batch, &undo)?;
+            self.unindex_main_chain_block(&mut batch, block)?;
+        }
+        for (block, diff) in reorg.connected.iter().zip(connected) {
+            self.index_main_chain_block(&mut batch, block)?;
+            self.add_state_diff_to_batch(&mut batch, &block.header.hash(), diff)?;
+        }
+
+        let new_tip = reorg
+            .connected
+            .last()
+            .map(|block| block.header.hash())
+            .unwrap_or(reorg.common_ancestor);
+        batch.put_cf(cfs.metadata, CHAIN_TIP_KEY, new_tip);
+
         self.db.write(batch)?;
 
         Ok(())
@@ -425,6 +896,53 @@ impl BlockchainStorage {
         }
     }
 
+    /// Gets consecutive main chain blocks by height.
+    ///
+    /// # Parameters
+    /// * `start_height` - Height of the first block
+    /// * `end_height` - Height of the last block (inclusive)
+    ///
+    /// # Returns
+    /// The blocks in height order, stopping early at the first height
+    /// without a block
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The database read fails
+    /// - A block cannot be deserialized
+    pub fn get_blocks_in_range(&self, start_height: u64, end_height: u64) -> Result<Vec<Block>, Error> {
+        let mut blocks = Vec::new();
+        for height in start_height..=end_height {
+            match self.get_block_by_height(height)? {
+                Some(block) => blocks.push(block),
+                None => break,
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Gets consecutive main chain block headers by height.
+    ///
+    /// # Parameters
+    /// * `start_height` - Height of the first header
+    /// * `end_height` - Height of the last header (inclusive)
+    ///
+    /// # Returns
+    /// The headers in height order, stopping early at the first height
+    /// without a block
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The database read fails
+    /// - A block cannot be deserialized
+    pub fn get_headers_in_range(&self, start_height: u64, end_height: u64) -> Result<Vec<BlockHeader>, Error> {
+        Ok(self
+            .get_blocks_in_range(start_height, end_height)?
+            .into_iter()
+            .map(|block| block.header)
+            .collect())
+    }
+
     /// Gets the latest block height.
     ///
     /// # Returns
@@ -434,6 +952,13 @@ impl BlockchainStorage {
     /// Returns an error if:
     /// - The database read fails
     pub fn get_latest_height(&self) -> Result<u64, Error> {
+        if let Some(tip) = self.get_chain_tip()? {
+            if let Some(meta) = self.get_block_meta(&tip)? {
+                return Ok(meta.height);
+            }
+        }
+
+        // Databases written before the tip was tracked
         let cfs = self.get_column_families()?;
 
         let mut iter = self
@@ -468,6 +993,43 @@ impl BlockchainStorage {
     /// - The referenced block doesn't exist
     /// - The transaction index is invalid
     pub fn get_transaction(&self, hash: &Hash) -> Result<Option<Transaction>, Error> {
+        Ok(self
+            .get_transaction_in_block(hash)?
+            .map(|(block, index)| block.transactions[index].clone()))
+    }
+
+    /// Retrieves a transaction together with a proof of its inclusion in a block
+    ///
+    /// The proof can be checked against the header of the containing block
+    /// with [`crate::block::BlockHeader::verify_transaction_proof`], so a
+    /// light client only needs that header to trust the result.
+    ///
+    /// # Parameters
+    /// * `hash` - The transaction hash
+    ///
+    /// # Returns
+    /// The transaction with its block hash and inclusion proof, or None if not found
+    ///
+    /// # Errors
+    /// Same as [`Self::get_transaction`]
+    pub fn get_transaction_with_proof(&self, hash: &Hash) -> Result<Option<TransactionWithProof>, Error> {
+        let Some((block, index)) = self.get_transaction_in_block(hash)? else {
+            return Ok(None);
+        };
+
+        let proof = block.transaction_proof(index).ok_or_else(|| {
+            Error::Database(format!("Invalid transaction index {} in block", index))
+        })?;
+
+        Ok(Some(TransactionWithProof {
+            block_hash: block.header.hash(),
+            transaction: block.transactions[index].clone(),
+            proof,
+        }))
+    }
+
+    /// Looks up the block holding a transaction and the transaction's index in it
+    fn get_transaction_in_block(&self, hash: &Hash) -> Result<Option<(Block, usize)>, Error> {
         let cfs = self.get_column_families()?;
 
         // Get transaction location
@@ -480,7 +1042,7 @@ impl BlockchainStorage {
                     Some(block) => {
                         let index = tx_location.index as usize;
                         if index < block.transactions.len() {
-                            Ok(Some(block.transactions[index].clone()))
+                            Ok(Some((block, index)))
                         } else {
                             Err(Error::Database(format!(
                                 "Invalid transaction index {} in block",
@@ -551,6 +1113,152 @@ impl BlockchainStorage {
         }
     }
 
+    /// Loads every stored account state.
+    ///
+    /// Used when a node starts up to rebuild its in-memory view of the state.
+    ///
+    /// # Returns
+    /// A result containing a map of account addresses to their states, or an error
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The database read fails
+    /// - An address key or account state cannot be decoded
+    pub fn get_all_account_states(&self) -> Result<HashMap<PublicKeyBytes, AccountState>, Error> {
+        let cfs = self.get_column_families()?;
+
+        let mut states = HashMap::new();
+        for item in self
+            .db
+            .iterator_cf(cfs.account_state, rocksdb::IteratorMode::Start)
+        {
+            let (key, value) = item?;
+            if key.len() != 32 {
+                return Err(Error::Database("Invalid account address length".to_string()));
+            }
+
+            let mut address = [0u8; 32];
+            address.copy_from_slice(&key);
+            let (state, _): (AccountState, _) =
+                bincode::decode_from_slice(&value, bincode::config::standard())?;
+            states.insert(address, state);
+        }
+
+        Ok(states)
+    }
+
+    /// Stores or replaces a peer address book entry.
+    ///
+    /// # Parameters
+    /// * `peer_id` - The encoded peer id
+    /// * `record` - The addresses of the peer
+    ///
+    /// # Returns
+    /// A result indicating success or an error
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The record cannot be serialized
+    /// - The database write fails
+    pub fn store_peer(&self, peer_id: &[u8], record: &PeerRecord) -> Result<(), Error> {
+        let cfs = self.get_column_families()?;
+        let bytes = bincode::encode_to_vec(record, bincode::config::standard())?;
+        self.db.put_cf(cfs.peers, peer_id, bytes)?;
+        Ok(())
+    }
+
+    /// Removes a peer from the address book.
+    ///
+    /// # Parameters
+    /// * `peer_id` - The encoded peer id
+    ///
+    /// # Returns
+    /// A result indicating success or an error
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The database write fails
+    pub fn remove_peer(&self, peer_id: &[u8]) -> Result<(), Error> {
+        let cfs = self.get_column_families()?;
+        self.db.delete_cf(cfs.peers, peer_id)?;
+        Ok(())
+    }
+
+    /// Loads the whole peer address book.
+    ///
+    /// # Returns
+    /// A result containing the encoded peer ids and their records, or an error
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The database read fails
+    /// - A record cannot be deserialized
+    pub fn get_all_peers(&self) -> Result<Vec<(Vec<u8>, PeerRecord)>, Error> {
+        let cfs = self.get_column_families()?;
+
+        let mut peers = Vec::new();
+        for item in self.db.iterator_cf(cfs.peers, rocksdb::IteratorMode::Start) {
+            let (key, value) = item?;
+            let (record, _): (PeerRecord, _) =
+                bincode::decode_from_slice(&value, bincode::config::standard())?;
+            peers.push((key.to_vec(), record));
+        }
+
+        Ok(peers)
+    }
+
+    /// Records changes to the transaction pool in its journal.
+    ///
+    /// # Parameters
+    /// * `saved` - Transactions that entered the pool, by hash
+    /// * `removed` - Hashes of transactions that left the pool
+    ///
+    /// # Returns
+    /// A result indicating success or an error
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - An entry cannot be serialized
+    /// - The database write fails
+    pub fn update_pool_journal(&self, saved: &[(Hash, PoolJournalEntry)], removed: &[Hash]) -> Result<(), Error> {
+        let cfs = self.get_column_families()?;
+
+        let mut batch = WriteBatch::default();
+        for (hash, entry) in saved {
+            let bytes = bincode::encode_to_vec(entry, bincode::config::standard())?;
+            batch.put_cf(cfs.pool_journal, hash, bytes);
+        }
+        for hash in removed {
+            batch.delete_cf(cfs.pool_journal, hash);
+        }
+        self.db.write(batch)?;
+
+        Ok(())
+    }
+
+    /// Loads every transaction recorded in the pool journal.
+    ///
+    /// # Returns
+    /// A result containing the journaled transactions, or an error
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The database read fails
+    /// - An entry cannot be deserialized
+    pub fn get_pool_journal(&self) -> Result<Vec<PoolJournalEntry>, Error> {
+        let cfs = self.get_column_families()?;
+
+        let mut entries = Vec::new();
+        for item in self.db.iterator_cf(cfs.pool_journal, rocksdb::IteratorMode::Start) {
+            let (_, value) = item?;
+            let (entry, _): (PoolJournalEntry, _) =
+                bincode::decode_from_slice(&value, bincode::config::standard())?;
+            entries.push(entry);
+        }
+
+        Ok(entries)
+    }
+
     /// Creates a database backup.
     ///
     /// # Parameters
@@ -934,6 +1642,16 @@ mod tests {
 
             // Verify it's the correct transaction
             assert_eq!(tx.hash(), tx_hash);
+
+            // The proof ties the transaction to the block header
+            let with_proof = storage.get_transaction_with_proof(&tx_hash).unwrap().unwrap();
+            assert_eq!(with_proof.block_hash, block.header.hash());
+            assert_eq!(with_proof.proof.index, 1);
+            assert!(block
+                .header
+                .verify_transaction_proof(&with_proof.transaction, &with_proof.proof)
+                .is_ok());
+            assert!(storage.get_transaction_with_proof(&[9u8; 32]).unwrap().is_none());
         }
 
         // Clean up
@@ -977,6 +1695,30 @@ mod tests {
         temp_dir.close().unwrap();
     }
 
+    #[test]
+    fn test_peer_store() {
+        let temp_dir = tempdir().unwrap();
+        let config = StorageConfig {
+            db_path: temp_dir.path().to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+
+        let record = PeerRecord {
+            addresses: vec!["/ip4/10.0.0.1/tcp/8080".to_string()],
+            last_seen: 1000,
+        };
+        {
+            let storage = BlockchainStorage::open(&config).unwrap();
+            storage.store_peer(b"peer-a", &record).unwrap();
+            storage.store_peer(b"peer-b", &record).unwrap();
+            storage.remove_peer(b"peer-b").unwrap();
+        }
+
+        // The address book survives a restart
+        let storage = BlockchainStorage::open(&config).unwrap();
+        assert_eq!(storage.get_all_peers().unwrap(), vec![(b"peer-a".to_vec(), record)]);
+    }
+
     #[test]
     fn test_chain_integrity() {
         // Create a temporary directory for the test database
@@ -1032,6 +1774,14 @@ mod tests {
                            "Error en enlace entre bloque {} y {}", height, height-1);
             }
 
+            // Range queries stop at the tip
+            let range = storage.get_blocks_in_range(1, 10).unwrap();
+            assert_eq!(range.len(), 3);
+            assert_eq!(range[2].header.hash(), block3.header.hash());
+            let headers = storage.get_headers_in_range(0, 1).unwrap();
+            let hashes: Vec<Hash> = headers.iter().map(|header| header.hash()).collect();
+            assert_eq!(hashes, vec![genesis_block.header.hash(), block1_hash]);
+
             // Verify integrity
             assert!(storage.verify_integrity().unwrap());
         }
@@ -1040,6 +1790,100 @@ mod tests {
         temp_dir.close().unwrap();
     }
 
+    #[test]
+    fn test_fork_tracking() {
+        let temp_dir = tempdir().unwrap();
+        let config = StorageConfig {
+            db_path: temp_dir.path().to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let storage = BlockchainStorage::open(&config).unwrap();
+
+        let genesis = create_test_block(0, [0u8; 32], 0);
+        let genesis_hash = genesis.header.hash();
+        storage.store_block(&genesis).unwrap();
+
+        let main1 = create_test_block(1, genesis_hash, 1);
+        storage.store_block_with_state(&main1, &StateDiff::default()).unwrap();
+
+        // A second block at height 1 is kept on a side chain
+        let mut side1 = create_test_block(1, genesis_hash, 2);
+        side1.header.validator = [9u8; 32];
+        let side1_hash = side1.header.hash();
+        storage.store_block(&side1).unwrap();
+        let side2 = create_test_block(2, side1_hash, 0);
+        storage.store_block(&side2).unwrap();
+
+        assert_eq!(storage.get_chain_tip().unwrap(), Some(main1.header.hash()));
+        assert_eq!(storage.get_block_hash_by_height(1).unwrap(), main1.header.hash());
+        assert!(storage.get_block(&side1_hash).unwrap().is_some());
+        assert!(!storage.is_on_main_chain(&side1_hash).unwrap());
+
+        let side_meta = storage.get_block_meta(&side2.header.hash()).unwrap().unwrap();
+        assert_eq!(side_meta.prev_hash, side1_hash);
+        assert_eq!(side_meta.cumulative_weight, 3);
+
+        // Switch to the side chain
+        let reorg = storage.plan_reorg(&side2.header.hash()).unwrap();
+        assert_eq!(reorg.common_ancestor, genesis_hash);
+        assert_eq!(reorg.disconnected.len(), 1);
+        assert_eq!(reorg.connected.len(), 2);
+        let diffs = vec![StateDiff::default(); reorg.connected.len()];
+        storage.apply_reorg(&reorg, &diffs).unwrap();
+
+        assert_eq!(storage.get_chain_tip().unwrap(), Some(side2.header.hash()));
+        assert_eq!(storage.get_latest_height().unwrap(), 2);
+        assert!(storage.is_on_main_chain(&side1_hash).unwrap());
+        assert!(!storage.is_on_main_chain(&main1.header.hash()).unwrap());
+        assert!(storage.get_transaction(&main1.transactions[0].hash()).unwrap().is_some());
+        assert!(storage.verify_integrity().unwrap());
+    }
+
+    #[test]
+    fn test_state_diff_and_rollback() {
+        let temp_dir = tempdir().unwrap();
+        let config = StorageConfig {
+            db_path: temp_dir.path().to_str().unwrap().to_string(),
+            ..Default::default()
+        };
+        let storage = BlockchainStorage::open(&config).unwrap();
+
+        let genesis = create_test_block(0, [0u8; 32], 0);
+        storage.store_block(&genesis).unwrap();
+
+        let existing = [1u8; 32];
+        let created = [2u8; 32];
+        storage.store_account_state(&existing, &AccountState::with_balance(1000)).unwrap();
+
+        // The block debits an existing account and creates a new one
+        let block = create_test_block(1, genesis.header.hash(), 1);
+        let diff = StateDiff {
+            updated: HashMap::from([
+                (existing, AccountState::with_balance(400)),
+                (created, AccountState::with_balance(600)),
+            ]),
+            undo: BlockUndo {
+                previous: vec![(existing, Some(AccountState::with_balance(1000))), (created, None)],
+            },
+        };
+        storage.store_block_with_state(&block, &diff).unwrap();
+
+        assert_eq!(storage.get_account_state(&existing).unwrap().unwrap().balance, 400);
+        assert_eq!(storage.get_account_state(&created).unwrap().unwrap().balance, 600);
+        assert!(storage.get_block_undo(&block.header.hash()).unwrap().is_some());
+
+        // Rolling back restores the previous states from the undo record
+        let rollback = storage.plan_rollback(5).unwrap();
+        assert_eq!(rollback.common_ancestor, genesis.header.hash());
+        assert_eq!(rollback.disconnected.len(), 1);
+        storage.apply_reorg(&rollback, &[]).unwrap();
+
+        assert_eq!(storage.get_latest_height().unwrap(), 0);
+        assert_eq!(storage.get_account_state(&existing).unwrap().unwrap().balance, 1000);
+        assert!(storage.get_account_state(&created).unwrap().is_none());
+        assert!(storage.get_transaction(&block.transactions[0].hash()).unwrap().is_none());
+    }
+
     #[test]
     fn test_timestamp_index() {
         // Create a temporary directory for the test database
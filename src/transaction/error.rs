@@ -47,6 +47,17 @@ pub enum TransactionError {
         required: u64,
     },
     
+    /// Transaction sender has less stake bonded than it tries to unstake
+    #[error("Insufficient stake for {sender:?}: has {stake}, needs {required}")]
+    InsufficientStake {
+        /// Transaction sender
+        sender: PublicKeyBytes,
+        /// Currently bonded stake
+        stake: u64,
+        /// Stake the transaction unbonds
+        required: u64,
+    },
+    
     /// Transaction fee is too low
     #[error("Transaction fee too low: {fee_per_byte} per byte, minimum is {min_required}")]
     FeeTooLow {
@@ -103,6 +114,15 @@ pub enum TransactionError {
         expiry_time: u64,
     },
 
+    /// Transaction was signed for another network
+    #[error("Transaction is for network {actual}, this node is on network {expected}")]
+    WrongNetwork {
+        /// Network id of this node
+        expected: u64,
+        /// Network id in the transaction
+        actual: u64,
+    },
+
     /// Transaction rate limiting
     #[error("Rate limited: too many transactions from sender {sender:?}")]
     RateLimited {
@@ -203,6 +223,14 @@ impl TransactionError {
             TransactionError::InsufficientBalance { sender, balance, required } =>
                 format!("Balance error for {}: has {}, needs {}", 
                         hex::encode(&sender[0..4]), balance, required),
+
+            TransactionError::InsufficientStake { sender, stake, required } =>
+                format!("Stake error for {}: has {}, needs {}", 
+                        hex::encode(&sender[0..4]), stake, required),
+
+            TransactionError::RateLimited { sender } =>
+                format!("Rate limited: too many transactions from {}", 
+                        hex::encode(&sender[0..4])),
             
             TransactionError::PoolFull { current_size, max_size } =>
                 format!("Pool capacity at {}/{} ({:.1}%)", 
@@ -241,6 +269,25 @@ impl From<TransactionError> for crate::Error {
             TransactionError::Validation(msg) => crate::Error::Validation(msg),
             TransactionError::Crypto(msg) => crate::Error::Crypto(msg),
             TransactionError::Database(msg) => crate::Error::DB(format!("Database error: {}", msg)),
+            TransactionError::AlreadyExists { .. } => {
+                crate::Error::Validation("Transaction already in pool".into())
+            }
+            TransactionError::InvalidNonce { expected, actual, .. } => crate::Error::Validation(
+                format!("Invalid nonce: expected {}, got {}", expected, actual),
+            ),
+            TransactionError::FeeTooLow { fee_per_byte, min_required } => crate::Error::Validation(
+                format!("Fee too low: {} per byte, minimum is {}", fee_per_byte, min_required),
+            ),
+            TransactionError::InsufficientBalance { balance, required, .. } => crate::Error::Validation(
+                format!("Insufficient balance: has {}, needs {}", balance, required),
+            ),
+            TransactionError::InsufficientStake { stake, required, .. } => crate::Error::Validation(
+                format!("Insufficient stake: has {}, needs {}", stake, required),
+            ),
+            TransactionError::RateLimited { sender } => crate::Error::Validation(format!(
+                "Rate limited: too many transactions from {}",
+                hex::encode(&sender[0..4])
+            )),
             // For all other specific transaction errors, convert to validation errors with descriptive messages
             _ => crate::Error::Validation(err.to_string()),
         }
@@ -329,6 +376,14 @@ mod tests {
             _ => panic!("Wrong error type after conversion"),
         }
         
+        let error: crate::Error = TransactionError::RateLimited { sender: [1u8; 32] }.into();
+        match error {
+            crate::Error::Validation(msg) => {
+                assert_eq!(msg, "Rate limited: too many transactions from 01010101");
+            },
+            _ => panic!("Wrong error type after conversion"),
+        }
+        
         // Test conversion from Error to TransactionError
         let error = crate::Error::Validation("Test validation error".to_string());
         let tx_error = TransactionError::from_error(&error);
@@ -25,6 +25,10 @@ pub struct PoolMetrics {
     pub peak_memory_usage: usize,
     /// Peak transaction count observed
     pub peak_transaction_count: usize,
+    /// Transactions ready for inclusion
+    pub pending_transactions: usize,
+    /// Transactions queued behind a nonce gap
+    pub queued_transactions: usize,
     /// Average fee per byte (in Blocana units)
     pub avg_fee_per_byte: f64,
     /// History of memory usage over time
@@ -110,6 +114,8 @@ impl Default for PoolMetrics {
             avg_validation_time_us: 0,
             peak_memory_usage: 0,
             peak_transaction_count: 0,
+            pending_transactions: 0,
+            queued_transactions: 0,
             avg_fee_per_byte: 0.0,
             memory_history: Vec::new(),
             count_history: Vec::new(),
@@ -316,6 +322,16 @@ impl MetricsCollector {
         }
     }
     
+    /// Update the numbers of pending and queued transactions
+    pub fn update_queue_counts(&mut self, pending: usize, queued: usize) {
+        if !self.enabled {
+            return;
+        }
+        
+        self.metrics.pending_transactions = pending;
+        self.metrics.queued_transactions = queued;
+    }
+    
     /// Record a transaction's fee information
     pub fn record_transaction_fee(&mut self, fee_per_byte: f64, tx_size: usize) {
         if !self.enabled {
@@ -389,6 +405,8 @@ impl MetricsCollector {
         report.push_str(&format!("Transactions rejected: {}\n", metrics.transactions_rejected));
         report.push_str(&format!("Transactions removed:  {}\n", metrics.transactions_removed));
         report.push_str(&format!("Transactions expired:  {}\n", metrics.transactions_expired));
+        report.push_str(&format!("Pending transactions:  {}\n", metrics.pending_transactions));
+        report.push_str(&format!("Queued transactions:   {}\n", metrics.queued_transactions));
         report.push_str("\n");
         
         report.push_str(&format!("Avg processing time:   {} μs\n", metrics.avg_processing_time_us));
@@ -3,17 +3,64 @@
 //! This module provides the core transaction implementation including
 //! creation, validation, and processing of transactions.
 
+use crate::consensus::EquivocationEvidence;
 use crate::crypto;
 use crate::types::{Hash, PrivateKeyBytes, PublicKeyBytes, SignatureBytes};
 use crate::Error;
+use bincode::de::{Decode, Decoder};
+use bincode::enc::{Encode, Encoder};
+use bincode::error::{DecodeError, EncodeError};
 use serde::{Deserialize, Serialize};
 use serde_big_array::BigArray;
 
+/// Transaction format version produced by this node
+///
+/// Version 2 binds the network id into the signed payload, so a transaction
+/// signed for one network cannot be replayed on another.
+pub const TRANSACTION_VERSION: u8 = 2;
+
+/// First transaction version that binds the network id into its signature
+///
+/// Older transactions carry no network id. They still decode and verify on
+/// [`crate::DEFAULT_NETWORK_ID`] so that blocks stored before the upgrade
+/// load, but [`Transaction::verify_for_network`] refuses them.
+pub const NETWORK_ID_TRANSACTION_VERSION: u8 = 2;
+
+/// Address staking transactions are sent to
+///
+/// Nobody holds its key and nothing is ever transferred to it. A transaction
+/// to this address carries a [`TransactionKind`] in its data saying what to
+/// do with the sender's stake, and the account itself holds the validator set.
+pub const STAKING_ADDRESS: PublicKeyBytes = [0xff; 32];
+
+/// What a transaction does with its amount
+#[derive(Debug, Clone, Copy, PartialEq, Eq, bincode::Encode, bincode::Decode)]
+pub enum TransactionKind {
+    /// Move the amount from the sender to the recipient
+    Transfer,
+    /// Lock the amount of the sender's balance as validator stake
+    Stake,
+    /// Unlock the amount of the sender's stake
+    ///
+    /// The coins stay bonded until the end of the epoch, then return to the
+    /// sender's balance.
+    Unstake,
+    /// Report a validator that signed two blocks at one height
+    ///
+    /// The data carries the [`EquivocationEvidence`] after the kind, and the
+    /// amount is zero.
+    Evidence,
+}
+
 /// Transaction structure representing a transfer of value
-#[derive(Debug, Clone, Serialize, Deserialize, bincode::Encode, bincode::Decode)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Transaction {
     /// Transaction format version
     pub version: u8,
+    /// Network the transaction is valid on
+    ///
+    /// Only present from [`NETWORK_ID_TRANSACTION_VERSION`] on.
+    pub network_id: u64,
     /// Sender public key
     pub sender: PublicKeyBytes,
     /// Recipient public key
@@ -31,12 +78,57 @@ pub struct Transaction {
     pub signature: SignatureBytes,
 }
 
+// The binary layout depends on the version byte, so transactions stored
+// before the network id existed keep decoding.
+impl Encode for Transaction {
+    fn encode<E: Encoder>(&self, encoder: &mut E) -> Result<(), EncodeError> {
+        self.version.encode(encoder)?;
+        if self.has_network_id() {
+            self.network_id.encode(encoder)?;
+        }
+        self.sender.encode(encoder)?;
+        self.recipient.encode(encoder)?;
+        self.amount.encode(encoder)?;
+        self.fee.encode(encoder)?;
+        self.nonce.encode(encoder)?;
+        self.data.encode(encoder)?;
+        self.signature.encode(encoder)
+    }
+}
+
+impl<Context> Decode<Context> for Transaction {
+    fn decode<D: Decoder<Context = Context>>(decoder: &mut D) -> Result<Self, DecodeError> {
+        let version = u8::decode(decoder)?;
+        let network_id = if version >= NETWORK_ID_TRANSACTION_VERSION {
+            u64::decode(decoder)?
+        } else {
+            crate::DEFAULT_NETWORK_ID
+        };
+
+        Ok(Self {
+            version,
+            network_id,
+            sender: PublicKeyBytes::decode(decoder)?,
+            recipient: PublicKeyBytes::decode(decoder)?,
+            amount: u64::decode(decoder)?,
+            fee: u64::decode(decoder)?,
+            nonce: u64::decode(decoder)?,
+            data: Vec::<u8>::decode(decoder)?,
+            signature: SignatureBytes::decode(decoder)?,
+        })
+    }
+}
+
+bincode::impl_borrow_decode!(Transaction);
+
 pub mod pool;
 pub mod metrics;  // Make the metrics module public
 pub mod error;
 
 impl Transaction {
-    /// Create a new unsigned transaction
+    /// Create a new unsigned transaction for the default network
+    ///
+    /// Use [`Self::with_network_id`] to target another network before signing.
     pub fn new(
         sender: PublicKeyBytes,
         recipient: PublicKeyBytes,
@@ -46,7 +138,8 @@ impl Transaction {
         data: Vec<u8>,
     ) -> Self {
         Self {
-            version: 1,
+            version: TRANSACTION_VERSION,
+            network_id: crate::DEFAULT_NETWORK_ID,
             sender,
             recipient,
             amount,
@@ -57,6 +150,35 @@ impl Transaction {
         }
     }
 
+    /// Create a new unsigned transaction locking `amount` of the sender's balance as stake
+    pub fn new_stake(sender: PublicKeyBytes, amount: u64, fee: u64, nonce: u64) -> Self {
+        Self::new(sender, STAKING_ADDRESS, amount, fee, nonce, staking_data(TransactionKind::Stake))
+    }
+
+    /// Create a new unsigned transaction unlocking `amount` of the sender's stake
+    pub fn new_unstake(sender: PublicKeyBytes, amount: u64, fee: u64, nonce: u64) -> Self {
+        Self::new(sender, STAKING_ADDRESS, amount, fee, nonce, staking_data(TransactionKind::Unstake))
+    }
+
+    /// Create a new unsigned transaction reporting an equivocating validator
+    pub fn new_evidence(sender: PublicKeyBytes, evidence: &EquivocationEvidence, fee: u64, nonce: u64) -> Self {
+        let mut data = staking_data(TransactionKind::Evidence);
+        // Headers are plain integers and byte arrays, which always encode
+        data.extend(
+            bincode::encode_to_vec(evidence, bincode::config::standard())
+                .expect("evidence is encodable"),
+        );
+        Self::new(sender, STAKING_ADDRESS, 0, fee, nonce, data)
+    }
+
+    /// Set the network the transaction is valid on
+    ///
+    /// Must be called before signing, as the network id is part of the signed payload.
+    pub fn with_network_id(mut self, network_id: u64) -> Self {
+        self.network_id = network_id;
+        self
+    }
+
     /// Sign a transaction with the sender's private key
     pub fn sign(&mut self, private_key: &PrivateKeyBytes) -> Result<(), Error> {
         // Create a message to sign (hash of transaction data without signature)
@@ -79,15 +201,36 @@ impl Transaction {
     /// `Ok(())` if the transaction is valid, otherwise an `Error`
     pub fn verify(&self) -> Result<(), Error> {
         // Check transaction version
-        if self.version != 1 {
+        if self.version == 0 || self.version > TRANSACTION_VERSION {
             return Err(Error::Validation(format!(
                 "Invalid transaction version: {}",
                 self.version
             )));
         }
 
-        // Check for zero amount
-        if self.amount == 0 {
+        // Transactions that do not sign a network id belong to the default network
+        if !self.has_network_id() && self.network_id != crate::DEFAULT_NETWORK_ID {
+            return Err(Error::Validation(format!(
+                "Version {} transactions are only valid on network {}",
+                self.version,
+                crate::DEFAULT_NETWORK_ID
+            )));
+        }
+
+        // Transactions to the staking address must say what to do with the stake
+        let kind = self.kind()?;
+
+        // Check the amount; evidence moves none, everything else must move some
+        if kind == TransactionKind::Evidence {
+            if self.amount != 0 {
+                return Err(Error::Validation(
+                    "Evidence transactions carry no amount".into(),
+                ));
+            }
+            self.evidence()?
+                .offender()
+                .map_err(|e| Error::Validation(format!("Invalid equivocation evidence: {:?}", e)))?;
+        } else if self.amount == 0 {
             return Err(Error::Validation(
                 "Transaction amount cannot be zero".into(),
             ));
@@ -128,6 +271,85 @@ impl Transaction {
         crypto::verify_signature(&self.sender, &self.signature, &message)
     }
 
+    /// Check whether this transaction version signs a network id
+    pub fn has_network_id(&self) -> bool {
+        self.version >= NETWORK_ID_TRANSACTION_VERSION
+    }
+
+    /// Get what the transaction does
+    ///
+    /// Fails for a transaction to [`STAKING_ADDRESS`] whose data is not a
+    /// staking operation.
+    pub fn kind(&self) -> Result<TransactionKind, Error> {
+        if self.recipient != STAKING_ADDRESS {
+            return Ok(TransactionKind::Transfer);
+        }
+
+        match bincode::decode_from_slice(&self.data, bincode::config::standard()) {
+            Ok((TransactionKind::Evidence, read)) if read < self.data.len() => Ok(TransactionKind::Evidence),
+            Ok((kind @ (TransactionKind::Stake | TransactionKind::Unstake), read)) if read == self.data.len() => {
+                Ok(kind)
+            }
+            _ => Err(Error::Validation(
+                "Transaction to the staking address carries no staking operation".into(),
+            )),
+        }
+    }
+
+    /// Get the equivocation evidence an evidence transaction carries
+    ///
+    /// Only decodes the evidence, [`EquivocationEvidence::offender`] checks it.
+    pub fn evidence(&self) -> Result<EquivocationEvidence, Error> {
+        if self.kind()? != TransactionKind::Evidence {
+            return Err(Error::Validation("Transaction carries no evidence".into()));
+        }
+
+        let payload = &self.data[staking_data(TransactionKind::Evidence).len()..];
+        match bincode::decode_from_slice(payload, bincode::config::standard()) {
+            Ok((evidence, read)) if read == payload.len() => Ok(evidence),
+            _ => Err(Error::Validation("Malformed equivocation evidence".into())),
+        }
+    }
+
+    /// Get how much the transaction takes from the sender's balance
+    ///
+    /// Unstaking only pays the fee from the balance, its amount comes out of
+    /// the stake.
+    pub fn balance_cost(&self) -> u64 {
+        match self.kind() {
+            Ok(TransactionKind::Unstake) => self.fee,
+            _ => self.amount.saturating_add(self.fee),
+        }
+    }
+
+    /// Verify the transaction and check that it was signed for the given network
+    ///
+    /// Transactions from before [`NETWORK_ID_TRANSACTION_VERSION`] are
+    /// refused: they sign no network id, so one signed on any network could
+    /// be replayed here.
+    ///
+    /// # Parameters
+    /// * `network_id` - Network id of the node, from `BlockchainConfig::network_id`
+    ///
+    /// # Returns
+    /// `Ok(())` if the transaction is valid on this network, otherwise an `Error`
+    pub fn verify_for_network(&self, network_id: u64) -> Result<(), Error> {
+        if !self.has_network_id() {
+            return Err(Error::Validation(format!(
+                "Version {} transactions sign no network id and are no longer accepted",
+                self.version
+            )));
+        }
+        if self.network_id != network_id {
+            return Err(Error::Validation(format!(
+                "Transaction is for network {}, expected {}",
+                self.network_id, network_id
+            )));
+        }
+
+        self.verify()
+    }
+
     /// Calculate the transaction hash
     ///
     /// This hash uniquely identifies the transaction and is used for:
@@ -159,6 +381,11 @@ impl Transaction {
         // Add version
         data.push(self.version);
 
+        // Add network id (8 bytes, little-endian)
+        if self.has_network_id() {
+            data.extend_from_slice(&self.network_id.to_le_bytes());
+        }
+
         // Add sender
         data.extend_from_slice(&self.sender);
 
@@ -195,6 +422,7 @@ impl Transaction {
     pub fn estimate_size(&self) -> usize {
         // Base size (fixed overhead)
         let base_size = 1 +                 // version (u8)
+                   8 +                  // network id (u64)
                    32 +                 // sender (32 bytes)
                    32 +                 // recipient (32 bytes)
                    8 +                  // amount (u64)
@@ -230,6 +458,12 @@ impl Transaction {
     }
 }
 
+/// Encode a staking operation as transaction data
+fn staking_data(kind: TransactionKind) -> Vec<u8> {
+    // Encoding a fieldless enum into a Vec cannot fail
+    bincode::encode_to_vec(kind, bincode::config::standard()).expect("staking operation is encodable")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -242,7 +476,8 @@ mod tests {
 
         let tx = Transaction::new(sender, recipient, 100, 10, 0, vec![1, 2, 3]);
 
-        assert_eq!(tx.version, 1);
+        assert_eq!(tx.version, TRANSACTION_VERSION);
+        assert_eq!(tx.network_id, crate::DEFAULT_NETWORK_ID);
         assert_eq!(tx.sender, sender);
         assert_eq!(tx.recipient, recipient);
         assert_eq!(tx.amount, 100);
@@ -316,6 +551,41 @@ mod tests {
 
         // Verify should now fail with the modified data
         assert!(tx2.verify().is_err());
+
+        // The network id is part of the signed payload
+        assert!(tx.verify_for_network(crate::DEFAULT_NETWORK_ID).is_ok());
+        assert!(tx.verify_for_network(crate::DEFAULT_NETWORK_ID + 1).is_err());
+        let mut replayed = tx.clone();
+        replayed.network_id += 1;
+        assert!(replayed.verify().is_err());
+    }
+
+    #[test]
+    fn test_encoding_follows_version() {
+        use bincode::config::standard;
+
+        let keypair = KeyPair::generate().unwrap();
+        let mut tx = Transaction::new(keypair.public_key, [2u8; 32], 100, 10, 0, vec![1, 2, 3])
+            .with_network_id(7);
+        tx.sign(&keypair.private_key).unwrap();
+        let encoded = bincode::encode_to_vec(&tx, standard()).unwrap();
+        let (decoded, _): (Transaction, usize) = bincode::decode_from_slice(&encoded, standard()).unwrap();
+        assert_eq!(decoded.network_id, 7);
+        assert_eq!(decoded.hash(), tx.hash());
+
+        // Version 1 transactions carry no network id on disk or in their hash
+        let mut legacy = Transaction::new(keypair.public_key, [2u8; 32], 100, 10, 0, vec![1, 2, 3]);
+        legacy.version = 1;
+        legacy.sign(&keypair.private_key).unwrap();
+        let legacy_encoded = bincode::encode_to_vec(&legacy, standard()).unwrap();
+        assert!(legacy_encoded.len() < encoded.len());
+
+        let (decoded, _): (Transaction, usize) = bincode::decode_from_slice(&legacy_encoded, standard()).unwrap();
+        assert_eq!(decoded.version, 1);
+        assert_eq!(decoded.network_id, crate::DEFAULT_NETWORK_ID);
+        assert_eq!(decoded.hash(), legacy.hash());
+        assert!(decoded.verify().is_ok());
+        assert!(decoded.verify_for_network(crate::DEFAULT_NETWORK_ID).is_err());
     }
 
     #[test]
@@ -325,10 +595,18 @@ mod tests {
 
         // Test invalid version
         let mut tx = Transaction::new(keypair.public_key, recipient, 100, 10, 0, vec![]);
-        tx.version = 2; // Currently only version 1 is supported
+        tx.version = TRANSACTION_VERSION + 1;
         tx.sign(&keypair.private_key).unwrap();
         assert!(tx.verify().is_err());
 
+        // Version 1 is not bound to a network, so it only verifies on the default one
+        let mut tx = Transaction::new(keypair.public_key, recipient, 100, 10, 0, vec![]);
+        tx.version = 1;
+        tx.sign(&keypair.private_key).unwrap();
+        assert!(tx.verify().is_ok());
+        tx.network_id = crate::DEFAULT_NETWORK_ID + 1;
+        assert!(tx.verify().is_err());
+
         // Test zero amount
         let mut tx = Transaction::new(
             keypair.public_key,
@@ -424,9 +702,9 @@ mod tests {
         let recipient = [2u8; 32];
         let tx_no_data = Transaction::new(sender, recipient, 100, 10, 0, vec![]);
 
-        // Expected size: 1 (version) + 32 (sender) + 32 (recipient) +
-        // 8 (amount) + 8 (fee) + 8 (nonce) + 64 (signature) + 8 (data length) = 161 bytes
-        assert_eq!(tx_no_data.estimate_size(), 153);
+        // Expected size: 1 (version) + 8 (network id) + 32 (sender) + 32 (recipient) +
+        // 8 (amount) + 8 (fee) + 8 (nonce) + 64 (signature) = 161 bytes
+        assert_eq!(tx_no_data.estimate_size(), 161);
 
         // Create a transaction with data
         let tx_with_data = Transaction::new(
@@ -438,7 +716,72 @@ mod tests {
             vec![0u8; 50], // 50 bytes of data
         );
 
-        // Expected size: 153 (base size) + 50 (data) = 203 bytes
-        assert_eq!(tx_with_data.estimate_size(), 203);
+        // Expected size: 161 (base size) + 50 (data) = 211 bytes
+        assert_eq!(tx_with_data.estimate_size(), 211);
+    }
+
+    #[test]
+    fn test_staking_transactions() {
+        let keypair = KeyPair::generate().unwrap();
+
+        let mut stake = Transaction::new_stake(keypair.public_key, 500, 10, 0);
+        stake.sign(&keypair.private_key).unwrap();
+        assert!(stake.verify().is_ok());
+        assert_eq!(stake.kind().unwrap(), TransactionKind::Stake);
+        assert_eq!(stake.balance_cost(), 510);
+
+        // Unstaking pays only the fee from the balance
+        let unstake = Transaction::new_unstake(keypair.public_key, 500, 10, 1);
+        assert_eq!(unstake.kind().unwrap(), TransactionKind::Unstake);
+        assert_eq!(unstake.balance_cost(), 10);
+
+        let transfer = Transaction::new(keypair.public_key, [2u8; 32], 100, 10, 0, vec![1]);
+        assert_eq!(transfer.kind().unwrap(), TransactionKind::Transfer);
+
+        // Plain transfers to the staking address are refused
+        let mut garbage = Transaction::new(keypair.public_key, STAKING_ADDRESS, 100, 10, 0, vec![]);
+        garbage.sign(&keypair.private_key).unwrap();
+        assert!(garbage.kind().is_err());
+        assert!(garbage.verify().is_err());
+        garbage.data = staking_data(TransactionKind::Transfer);
+        assert!(garbage.kind().is_err());
+    }
+
+    #[test]
+    fn test_evidence_transactions() {
+        use crate::block::{BlockHeader, BLOCK_VERSION};
+
+        let reporter = KeyPair::generate().unwrap();
+        let validator = KeyPair::generate().unwrap();
+        let sign = |timestamp| {
+            let mut header = BlockHeader::new(BLOCK_VERSION, [1u8; 32], [0u8; 32], 3, validator.public_key);
+            header.timestamp = timestamp;
+            header.sign(&validator.private_key).unwrap();
+            header
+        };
+        let evidence = EquivocationEvidence { first: sign(1000), second: sign(2000) };
+
+        let mut tx = Transaction::new_evidence(reporter.public_key, &evidence, 10, 0);
+        tx.sign(&reporter.private_key).unwrap();
+        assert!(tx.verify().is_ok());
+        assert_eq!(tx.kind().unwrap(), TransactionKind::Evidence);
+        assert_eq!(tx.evidence().unwrap().offender().unwrap(), validator.public_key);
+        assert_eq!(tx.balance_cost(), 10);
+
+        // Evidence moves no coins
+        let mut paying = tx.clone();
+        paying.amount = 1;
+        paying.sign(&reporter.private_key).unwrap();
+        assert!(paying.verify().is_err());
+
+        // Evidence that proves nothing is refused
+        let same = EquivocationEvidence { first: sign(1000), second: sign(1000) };
+        let mut bogus = Transaction::new_evidence(reporter.public_key, &same, 10, 0);
+        bogus.sign(&reporter.private_key).unwrap();
+        assert!(bogus.verify().is_err());
+
+        let mut truncated = tx.clone();
+        truncated.data.pop();
+        assert!(truncated.evidence().is_err());
     }
 }
@@ -1,181 +1,36 @@
 //! Transaction pool for managing pending transactions
+//!
+//! Transactions whose nonce follows on from their sender's account nonce,
+//! directly or through the sender's other pending transactions, are
+//! pending and can go into the next block. A transaction further ahead
+//! waits in the sender's queue until the missing nonces arrive or get
+//! mined, and is then promoted to the pending set.
+//!
+//! No sender may hold more than its configured share of the pool, in
+//! transactions or in bytes, or have more transactions accepted per
+//! submission window than configured.
+//!
+//! Every transaction that enters or leaves the pool is remembered until the
+//! owner saves the changes to the pool journal in storage, from which a
+//! restarted node restores the pool.
 
 use crate::state::BlockchainState;
+use crate::storage::PoolJournalEntry;
 use crate::transaction::metrics::{MetricsCollector, OperationType};
-use crate::transaction::Transaction;
+use crate::transaction::{Transaction, TransactionKind};
 use crate::types::{Hash, PublicKeyBytes};
 use crate::Error;
 use bincode;
 use log::debug;
-use std::collections::{HashMap, HashSet};
-use std::time::{Duration, Instant};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub use crate::transaction::error::TransactionError;
 
 /// Result type for transaction-specific operations
 pub type TxResult<T> = Result<T, TransactionError>;
 
-/// Detailed error type for transaction pool operations
-#[derive(Debug)]
-pub enum TransactionError {
-    /// Transaction already exists in the pool
-    AlreadyExists { tx_hash: Hash },
-    /// Transaction has an invalid signature
-    InvalidSignature,
-    /// Transaction nonce doesn't match account state
-    InvalidNonce {
-        sender: PublicKeyBytes,
-        expected: u64,
-        actual: u64,
-    },
-    /// Transaction fee is too low for inclusion
-    FeeTooLow {
-        fee_per_byte: u64,
-        min_required: u64,
-    },
-    /// Transaction replacement fee is too low
-    ReplacementFeeTooLow { actual: u64, required: u64 },
-    /// Account has insufficient balance
-    InsufficientBalance {
-        sender: PublicKeyBytes,
-        balance: u64,
-        required: u64,
-    },
-    /// Transaction pool is full
-    PoolFull {
-        current_size: usize,
-        max_size: usize,
-    },
-    /// Memory limit reached
-    MemoryLimitReached {
-        current_bytes: usize,
-        max_bytes: usize,
-    },
-    /// General error
-    Other(String),
-}
-
-impl TransactionError {
-    /// Get additional context for logging purposes
-    pub fn log_context(&self) -> String {
-        match self {
-            Self::AlreadyExists { tx_hash } => format!(
-                "Transaction already exists with hash: {}",
-                hex::encode(&tx_hash[0..4])
-            ),
-            Self::InvalidSignature => "Transaction has an invalid signature".to_string(),
-            Self::InvalidNonce {
-                sender,
-                expected,
-                actual,
-            } => format!(
-                "Invalid nonce for {}: expected {}, got {}",
-                hex::encode(&sender[0..4]),
-                expected,
-                actual
-            ),
-            Self::FeeTooLow {
-                fee_per_byte,
-                min_required,
-            } => format!(
-                "Fee too low: {} per byte, minimum is {}",
-                fee_per_byte, min_required
-            ),
-            Self::ReplacementFeeTooLow { actual, required } => format!(
-                "Replacement fee too low: {} provided, {} required",
-                actual, required
-            ),
-            Self::InsufficientBalance {
-                sender,
-                balance,
-                required,
-            } => format!(
-                "Insufficient balance for {}: has {}, needs {}",
-                hex::encode(&sender[0..4]),
-                balance,
-                required
-            ),
-            Self::PoolFull {
-                current_size,
-                max_size,
-            } => format!(
-                "Transaction pool full: {} of {} slots used",
-                current_size, max_size
-            ),
-            Self::MemoryLimitReached {
-                current_bytes,
-                max_bytes,
-            } => format!(
-                "Memory limit reached: {} of {} bytes used",
-                current_bytes, max_bytes
-            ),
-            Self::Other(msg) => format!("Other error: {}", msg),
-        }
-    }
-}
-
-impl std::fmt::Display for TransactionError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::AlreadyExists { .. } => write!(f, "Transaction already exists"),
-            Self::InvalidSignature => write!(f, "Invalid transaction signature"),
-            Self::InvalidNonce { .. } => write!(f, "Invalid transaction nonce"),
-            Self::FeeTooLow { .. } => write!(f, "Transaction fee too low"),
-            Self::ReplacementFeeTooLow { .. } => write!(f, "Replacement fee too low"),
-            Self::InsufficientBalance { .. } => write!(f, "Insufficient balance"),
-            Self::PoolFull { .. } => write!(f, "Transaction pool is full"),
-            Self::MemoryLimitReached { .. } => write!(f, "Memory limit reached"),
-            Self::Other(msg) => write!(f, "Other error: {}", msg),
-        }
-    }
-}
-
-impl std::error::Error for TransactionError {}
-
-impl From<TransactionError> for Error {
-    fn from(error: TransactionError) -> Self {
-        match error {
-            TransactionError::AlreadyExists { .. } => {
-                Error::Validation("Transaction already in pool".into())
-            }
-            TransactionError::InvalidSignature => {
-                Error::Validation("Invalid transaction signature".into())
-            }
-            TransactionError::InvalidNonce {
-                expected, actual, ..
-            } => Error::Validation(format!(
-                "Invalid nonce: expected {}, got {}",
-                expected, actual
-            )),
-            TransactionError::FeeTooLow {
-                fee_per_byte,
-                min_required,
-                ..
-            } => Error::Validation(format!(
-                "Fee too low: {} per byte, minimum is {}",
-                fee_per_byte, min_required
-            )),
-            TransactionError::ReplacementFeeTooLow { actual, required } => {
-                Error::Validation(format!(
-                    "Replacement fee too low: {} provided, {} required",
-                    actual, required
-                ))
-            }
-            TransactionError::InsufficientBalance {
-                balance, required, ..
-            } => Error::Validation(format!(
-                "Insufficient balance: has {}, needs {}",
-                balance, required
-            )),
-            TransactionError::PoolFull { .. } => {
-                Error::Validation("Transaction pool is full".into())
-            }
-            TransactionError::MemoryLimitReached { .. } => {
-                Error::Validation("Memory limit reached".into())
-            }
-            TransactionError::Other(msg) => Error::Validation(msg),
-        }
-    }
-}
-
 /// Configuration for the transaction pool
 #[derive(Debug, Clone)]
 pub struct TransactionPoolConfig {
@@ -189,6 +44,20 @@ pub struct TransactionPoolConfig {
     pub min_fee_per_byte: u64,
     /// Minimum fee increase percentage for replacements (e.g., 10 = 10% increase required)
     pub replacement_fee_bump: u64,
+    /// Network id transactions must be signed for
+    pub network_id: u64,
+    /// Maximum number of transactions a single sender may have queued behind a nonce gap
+    pub max_queued_per_sender: usize,
+    /// Maximum number of transactions queued behind nonce gaps in total
+    pub max_queued: usize,
+    /// Maximum number of pending transactions from a single sender
+    pub max_pending_per_sender: usize,
+    /// Maximum size in bytes of a single sender's pending and queued transactions
+    pub max_bytes_per_sender: usize,
+    /// Maximum number of transactions a single sender may submit per submission window
+    pub max_submissions_per_sender: usize,
+    /// Length of the submission window (in seconds)
+    pub submission_window: u64,
 }
 
 impl Default for TransactionPoolConfig {
@@ -199,6 +68,13 @@ impl Default for TransactionPoolConfig {
             max_memory: 32 * 1024 * 1024, // 32 MB
             min_fee_per_byte: 1,
             replacement_fee_bump: 10, // Require 10% fee increase for replacements
+            network_id: crate::DEFAULT_NETWORK_ID,
+            max_queued_per_sender: 16,
+            max_queued: 1024,
+            max_pending_per_sender: 256,
+            max_bytes_per_sender: 512 * 1024, // 512 KB
+            max_submissions_per_sender: 120,
+            submission_window: 60, // 1 minute
         }
     }
 }
@@ -240,6 +116,23 @@ impl std::fmt::Display for PoolError {
 // También es buena práctica implementar std::error::Error
 impl std::error::Error for PoolError {}
 
+impl From<TransactionError> for PoolError {
+    fn from(error: TransactionError) -> Self {
+        match error {
+            TransactionError::AlreadyExists { .. } => PoolError::DuplicateTransaction,
+            TransactionError::InvalidSignature => PoolError::InvalidSignature,
+            TransactionError::FeeTooLow { .. } | TransactionError::ReplacementFeeTooLow { .. } => {
+                PoolError::FeeTooLow
+            }
+            TransactionError::InsufficientBalance { .. } => PoolError::InsufficientBalance,
+            TransactionError::PoolFull { .. } | TransactionError::MemoryLimitReached { .. } => {
+                PoolError::PoolFull
+            }
+            other => PoolError::Other(other.log_context()),
+        }
+    }
+}
+
 /// A pooled transaction with metadata
 struct PooledTransaction {
     /// The transaction
@@ -252,6 +145,53 @@ struct PooledTransaction {
     size: usize,
 }
 
+impl PooledTransaction {
+    /// Wrap a transaction that enters the pool now
+    fn new(transaction: Transaction) -> Self {
+        Self {
+            size: transaction.estimate_size(),
+            transaction,
+            added_time: Instant::now(),
+            is_valid: true,
+        }
+    }
+}
+
+/// A sender's next executable transaction during block selection
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct SelectionCandidate {
+    /// Fee per byte, the priority
+    fee_per_byte: u64,
+    /// When the transaction was added, older first on equal fees
+    added_time: Instant,
+    /// Transaction hash, to keep the order total
+    tx_hash: Hash,
+}
+
+impl Ord for SelectionCandidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.fee_per_byte
+            .cmp(&other.fee_per_byte)
+            .then_with(|| other.added_time.cmp(&self.added_time))
+            .then_with(|| other.tx_hash.cmp(&self.tx_hash))
+    }
+}
+
+impl PartialOrd for SelectionCandidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Where a transaction that passed validation goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Admission {
+    /// Into the pending set, ready for inclusion
+    Pending,
+    /// Into the sender's queue, behind a nonce gap
+    Queued,
+}
+
 /// Fee-indexed transaction entry
 #[derive(Clone)]
 struct TransactionWithFee {
@@ -273,12 +213,28 @@ pub struct TransactionPool {
     by_fee: Vec<TransactionWithFee>,
     /// Transactions indexed by sender address
     by_address: HashMap<Hash, HashSet<Hash>>,
+    /// Transactions waiting for a nonce gap to close, by sender and nonce
+    queued: HashMap<PublicKeyBytes, BTreeMap<u64, PooledTransaction>>,
+    /// Sender and nonce of every queued transaction
+    queued_index: HashMap<Hash, (PublicKeyBytes, u64)>,
+    /// Transactions that entered or left the pool since the journal was last saved
+    unjournaled: HashSet<Hash>,
+    /// When each sender's recently accepted submissions arrived, oldest first
+    submissions: HashMap<PublicKeyBytes, VecDeque<Instant>>,
     /// Current memory usage estimate
     memory_usage: usize,
     /// Metrics collector for performance monitoring
     metrics: MetricsCollector,
 }
 
+/// Get the current time in milliseconds since the UNIX epoch
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
 impl TransactionPool {
     /// Initialize a new transaction pool with default configuration
     pub fn new() -> Self {
@@ -291,6 +247,10 @@ impl TransactionPool {
             txs: HashMap::new(),
             by_fee: Vec::new(),
             by_address: HashMap::new(),
+            queued: HashMap::new(),
+            queued_index: HashMap::new(),
+            unjournaled: HashSet::new(),
+            submissions: HashMap::new(),
             config,
             memory_usage: 0,
             metrics: MetricsCollector::new(100), // Track the last 100 data points
@@ -355,344 +315,8 @@ impl TransactionPool {
         state: &mut BlockchainState,
         allow_replacement: bool,
     ) -> Result<Hash, Error> {
-        // Start metrics for this operation
-        self.metrics.start_operation(OperationType::Add);
-        let process_start = Instant::now();
-
-        // Start validation timing
-        self.metrics.start_operation(OperationType::Validate);
-
-        // Verify transaction signature
-        tx.verify()?;
-
-        // Calculate hash
-        let tx_hash = tx.hash();
-
-        // Check for duplicate - but if replacement is allowed, we'll check differently
-        if self.txs.contains_key(&tx_hash) {
-            self.metrics.record_transaction_rejected();
-            self.metrics.stop_operation(OperationType::Validate);
-            self.metrics.stop_operation(OperationType::Add);
-            return Err(Error::Validation("Transaction already in pool".into()));
-        }
-
-        // Get current account state
-        let sender_state = state.get_account_state(&tx.sender);
-
-        // Comprobar primero si existe una transacción con el mismo remitente y nonce
-        let existing_tx = self.find_transaction_by_sender_and_nonce(&tx.sender, tx.nonce);
-        if existing_tx.is_some() {
-            // Ya existe una transacción con este remitente y nonce
-            if allow_replacement {
-                // Si se permite el reemplazo, procesarlo
-                let existing_tx = existing_tx.unwrap();
-                self.metrics.stop_operation(OperationType::Validate);
-                return self.process_replacement_transaction(tx, existing_tx.hash(), state);
-            } else {
-                // No se permite el reemplazo
-                self.metrics.record_transaction_rejected();
-                self.metrics.stop_operation(OperationType::Validate);
-                self.metrics.stop_operation(OperationType::Add);
-                return Err(Error::Validation(
-                    "Transaction with this nonce already exists".into(),
-                ));
-            }
-        }
-
-        // Validate nonce
-        if tx.nonce != sender_state.nonce {
-            self.metrics.record_transaction_rejected();
-            self.metrics.stop_operation(OperationType::Validate);
-            self.metrics.stop_operation(OperationType::Add);
-            return Err(Error::Validation(format!(
-                "Invalid nonce: expected {}, got {}",
-                sender_state.nonce, tx.nonce
-            )));
-        }
-
-        // Validate balance
-        let total_cost = tx.amount.saturating_add(tx.fee);
-        if sender_state.balance < total_cost {
-            self.metrics.record_transaction_rejected();
-            self.metrics.stop_operation(OperationType::Validate);
-            self.metrics.stop_operation(OperationType::Add);
-            return Err(Error::Validation(format!(
-                "Insufficient balance: has {}, needs {}",
-                sender_state.balance, total_cost
-            )));
-        }
-
-        // Calculate fee per byte for metrics
-        let tx_size = tx.estimate_size();
-        let tx_size_u64 = tx_size as u64;
-        let fee_per_byte = if tx_size_u64 > 0 {
-            tx.fee / tx_size_u64
-        } else {
-            tx.fee
-        };
-
-        // Record fee metrics
-        self.metrics
-            .record_transaction_fee(fee_per_byte as f64, tx_size);
-
-        // Check minimum fee requirement
-        if fee_per_byte < self.config.min_fee_per_byte {
-            self.metrics.record_transaction_rejected();
-            self.metrics.stop_operation(OperationType::Validate);
-            self.metrics.stop_operation(OperationType::Add);
-            return Err(Error::Validation(format!(
-                "Fee too low: {} per byte, minimum is {}",
-                fee_per_byte, self.config.min_fee_per_byte
-            )));
-        }
-
-        // End validation timing
-        self.metrics.stop_operation(OperationType::Validate);
-        let validation_time = process_start.elapsed().as_micros() as u64;
-
-        // Continue with the regular transaction addition process
-        // Check if pool is at capacity
-        if self.txs.len() >= self.config.max_size {
-            // If we're at capacity, check if this transaction has higher fee than lowest
-            if let Some(lowest_fee_tx) = self.get_lowest_fee_transaction() {
-                let lowest_tx_size = lowest_fee_tx.estimate_size() as u64;
-                let lowest_fee_per_byte = if lowest_tx_size > 0 {
-                    lowest_fee_tx.fee / lowest_tx_size
-                } else {
-                    lowest_fee_tx.fee
-                };
-
-                if fee_per_byte <= lowest_fee_per_byte {
-                    // New transaction doesn't have higher fee-per-byte, reject it
-                    self.metrics.record_transaction_rejected();
-                    self.metrics.stop_operation(OperationType::Add);
-                    return Err(Error::Validation(
-                        "Transaction pool full and fee too low".into(),
-                    ));
-                }
-
-                // New transaction has higher fee, remove the lowest fee transaction
-                self.remove_transaction(&lowest_fee_tx.hash());
-            }
-        }
-
-        // Create pooled transaction
-        let pooled_tx = PooledTransaction {
-            transaction: tx.clone(),
-            added_time: self.get_current_time(),
-            is_valid: true,
-            size: tx_size,
-        };
-
-        // Create fee record for priority
-        let tx_with_fee = TransactionWithFee {
-            tx_hash,
-            fee: tx.fee,
-            fee_per_byte,
-            timestamp: pooled_tx.added_time,
-        };
-
-        // Update memory usage estimate
-        self.memory_usage += tx_size
-            + std::mem::size_of::<PooledTransaction>()
-            + std::mem::size_of::<TransactionWithFee>();
-
-        // Update memory usage metrics
-        self.metrics.update_memory_usage(self.memory_usage);
-
-        // Calcular el uso de memoria proyectado después de añadir la transacción
-        let projected_memory = self.memory_usage
-            + tx_size
-            + std::mem::size_of::<PooledTransaction>()
-            + std::mem::size_of::<TransactionWithFee>();
-
-        // Activar optimización si estamos por encima del 75% o si la adición nos pondría por encima del límite
-        if self.memory_usage > (self.config.max_memory * 3 / 4)
-            || projected_memory > self.config.max_memory
-        {
-            self.metrics.start_operation(OperationType::Optimize);
-            let removed = self.optimize_memory();
-            self.metrics.stop_operation(OperationType::Optimize);
-
-            if removed == 0 && projected_memory > self.config.max_memory {
-                // If we couldn't optimize, reject this transaction
-                self.memory_usage -= tx_size
-                    + std::mem::size_of::<PooledTransaction>()
-                    + std::mem::size_of::<TransactionWithFee>();
-                self.metrics.update_memory_usage(self.memory_usage);
-                self.metrics.record_transaction_rejected();
-                self.metrics.stop_operation(OperationType::Add);
-                return Err(Error::Validation(
-                    "Cannot add transaction due to memory constraints".into(),
-                ));
-            }
-
-            // Double-check we're still within limits
-            if self.memory_usage > self.config.max_memory {
-                // Still over limit, reject
-                self.memory_usage -= tx_size
-                    + std::mem::size_of::<PooledTransaction>()
-                    + std::mem::size_of::<TransactionWithFee>();
-                self.metrics.update_memory_usage(self.memory_usage);
-                self.metrics.record_transaction_rejected();
-                self.metrics.stop_operation(OperationType::Add);
-                return Err(Error::Validation(
-                    "Cannot add transaction due to memory constraints".into(),
-                ));
-            }
-        }
-
-        // Add to primary index
-        self.txs.insert(tx_hash, pooled_tx);
-
-        // Add to fee index
-        self.by_fee.push(tx_with_fee);
-
-        // Add to sender index
-        self.by_address
-            .entry(tx.sender)
-            .or_insert_with(HashSet::new)
-            .insert(tx_hash);
-
-        // Update transaction count metrics
-        self.metrics.update_transaction_count(self.txs.len());
-
-        // Record successful addition
-        let processing_time = process_start.elapsed().as_micros() as u64;
-        self.metrics
-            .record_transaction_added(processing_time, validation_time);
-
-        // End total operation timing
-        self.metrics.stop_operation(OperationType::Add);
-
-        debug!("Added transaction to pool: {}", hex::encode(&tx_hash[0..4]));
-        Ok(tx_hash)
-    }
-
-    /// Process a transaction that is replacing an existing one
-    ///
-    /// This method handles the logic for replacing a transaction that is already in the pool.
-    ///
-    /// # Parameters
-    /// * `new_tx` - The new transaction that is replacing an existing one
-    /// * `existing_hash` - Hash of the existing transaction to be replaced
-    /// * `state` - Current blockchain state for validation
-    ///
-    /// # Returns
-    /// Ok(hash) if the replacement was successful, Error otherwise
-    fn process_replacement_transaction(
-        &mut self,
-        new_tx: Transaction,
-        existing_hash: Hash,
-        _state: &mut BlockchainState,
-    ) -> Result<Hash, Error> {
-        // Get the existing transaction
-        let existing_tx = match self.get_transaction(&existing_hash) {
-            Some(tx) => tx,
-            None => {
-                // This shouldn't happen since we already found it above
-                return Err(Error::Validation("Existing transaction not found".into()));
-            }
-        };
-
-        // Calculate fee for both transactions
-        let new_fee = new_tx.fee;
-        let existing_fee = existing_tx.fee;
-
-        // Calculate the minimum required fee increase (percentage-based)
-        let min_fee = existing_fee.saturating_add(
-            existing_fee
-                .checked_mul(self.config.replacement_fee_bump)
-                .unwrap_or(u64::MAX)
-                / 100,
-        );
-
-        // Check if the new transaction has enough fee increase
-        if new_fee < min_fee {
-            self.metrics.record_transaction_rejected();
-            return Err(Error::Validation(format!(
-                "Replacement fee too low: got {}, need at least {}",
-                new_fee, min_fee
-            )));
-        }
-
-        // The new transaction has a sufficient fee increase, remove the old one
-        // before adding the new one
-        let removed = self.remove_transaction(&existing_hash);
-        if !removed {
-            // This shouldn't happen since we already found the transaction
-            return Err(Error::Validation(
-                "Failed to remove existing transaction during replacement".into(),
-            ));
-        }
-
-        debug!(
-            "Replaced transaction {} with higher fee version",
-            hex::encode(&existing_hash[0..4])
-        );
-
-        // Now add the new transaction using the regular process
-        // We need to adjust the transaction to use the current expected nonce
-        let new_tx_hash = new_tx.hash();
-        let tx_size = new_tx.estimate_size();
-        let tx_size_u64 = tx_size as u64;
-        let fee_per_byte = if tx_size_u64 > 0 {
-            new_tx.fee / tx_size_u64
-        } else {
-            new_tx.fee
-        };
-
-        // Create pooled transaction
-        let pooled_tx = PooledTransaction {
-            transaction: new_tx.clone(),
-            added_time: self.get_current_time(),
-            is_valid: true,
-            size: tx_size,
-        };
-
-        // Create fee record for priority
-        let tx_with_fee = TransactionWithFee {
-            tx_hash: new_tx_hash,
-            fee: new_tx.fee,
-            fee_per_byte,
-            timestamp: pooled_tx.added_time,
-        };
-
-        // Update memory usage before adding
-        self.memory_usage += tx_size;
-        self.metrics.update_memory_usage(self.memory_usage);
-
-        // Check memory limit and optimize if needed
-        if self.memory_usage > self.config.max_memory {
-            self.optimize_memory();
-
-            // If still over limit, reject the transaction
-            if self.memory_usage > self.config.max_memory {
-                self.memory_usage -= tx_size;
-                self.metrics.update_memory_usage(self.memory_usage);
-                self.metrics.record_transaction_rejected();
-                return Err(Error::Validation(
-                    "Memory limit reached, cannot replace transaction".into(),
-                ));
-            }
-        }
-
-        // Add to primary index
-        self.txs.insert(new_tx_hash, pooled_tx);
-
-        // Add to fee index
-        self.by_fee.push(tx_with_fee);
-
-        // Add to sender index
-        self.by_address
-            .entry(new_tx.sender)
-            .or_insert_with(HashSet::new)
-            .insert(new_tx_hash);
-
-        // Update metrics
-        self.metrics.update_transaction_count(self.txs.len());
-
-        Ok(new_tx_hash)
+        self.add_transaction_with_replacement_detailed(tx, state, allow_replacement)
+            .map_err(Into::into)
     }
 
     /// Find a transaction with the specified sender and nonce
@@ -721,6 +345,31 @@ impl TransactionPool {
         None
     }
 
+    /// Get the next nonce a new transaction from `sender` should use
+    ///
+    /// Starts at the sender's confirmed nonce and skips over every consecutive
+    /// nonce that already has a transaction waiting in the pool.
+    ///
+    /// # Parameters
+    /// * `sender` - The transaction sender
+    /// * `state_nonce` - The sender's nonce in the current blockchain state
+    pub fn next_nonce(&self, sender: &PublicKeyBytes, state_nonce: u64) -> u64 {
+        let pending: HashSet<u64> = match self.by_address.get(sender) {
+            Some(tx_hashes) => tx_hashes
+                .iter()
+                .filter_map(|hash| self.txs.get(hash))
+                .map(|pooled_tx| pooled_tx.transaction.nonce)
+                .collect(),
+            None => return state_nonce,
+        };
+
+        let mut nonce = state_nonce;
+        while pending.contains(&nonce) {
+            nonce += 1;
+        }
+        nonce
+    }
+
     /// Add a transaction to the pool (wrapper for backward compatibility)
     ///
     /// # Parameters
@@ -734,12 +383,71 @@ impl TransactionPool {
         tx: Transaction,
         state: &mut BlockchainState,
     ) -> Result<Hash, Error> {
+        self.add_transaction_detailed(tx, state).map_err(Into::into)
+    }
+
+    /// Add a transaction to the pool, using detailed error reporting
+    ///
+    /// # Parameters
+    /// * `tx` - The transaction to add
+    /// * `state` - Current blockchain state (for validation)
+    ///
+    /// # Returns
+    /// `Ok(hash)` if transaction was added successfully, otherwise the
+    /// reason it was refused
+    pub fn add_transaction_detailed(
+        &mut self,
+        tx: Transaction,
+        state: &mut BlockchainState,
+    ) -> TxResult<Hash> {
+        let sender = tx.sender;
+        if let Err(e) = self.check_submission_rate(&sender) {
+            debug!("Transaction rejected: {}", e.log_context());
+            self.metrics.record_transaction_rejected();
+            return Err(e);
+        }
+
+        let added_time = self.get_current_time();
+        let tx_hash = self.admit_transaction(tx, state, added_time)?;
+        self.record_submission(sender, added_time);
+        Ok(tx_hash)
+    }
+
+    /// Put a transaction back into the pool without counting it as a new submission
+    ///
+    /// Used for transactions orphaned by a chain reorganization. They are
+    /// validated like new submissions, but do not count against their
+    /// sender's submission rate.
+    ///
+    /// # Parameters
+    /// * `tx` - The transaction to put back
+    /// * `state` - Current blockchain state (for validation)
+    ///
+    /// # Returns
+    /// `Ok(hash)` if the transaction is back in the pool, otherwise the
+    /// reason it was refused
+    pub fn readmit_transaction(
+        &mut self,
+        tx: Transaction,
+        state: &mut BlockchainState,
+    ) -> TxResult<Hash> {
+        let added_time = self.get_current_time();
+        self.admit_transaction(tx, state, added_time)
+    }
+
+    /// Validate a transaction and add it as having entered the pool at `added_time`
+    fn admit_transaction(
+        &mut self,
+        tx: Transaction,
+        state: &mut BlockchainState,
+        added_time: Instant,
+    ) -> TxResult<Hash> {
         self.metrics.start_operation(OperationType::Add);
         let process_start = Instant::now();
 
         // Validate using the detailed error system internally
-        match self.validate_transaction_internal(&tx, state) {
-            Ok(_) => {}
+        let admission = match self.validate_transaction_internal(&tx, state) {
+            Ok(admission) => admission,
             Err(e) => {
                 // Log with enhanced context
                 debug!("Transaction rejected: {}", e.log_context());
@@ -747,10 +455,9 @@ impl TransactionPool {
                 self.metrics.record_transaction_rejected();
                 self.metrics.stop_operation(OperationType::Add);
 
-                // Convert to the general error type for API compatibility
-                return Err(e.into());
+                return Err(e);
             }
-        }
+        };
 
         // Calcular hash y métricas
         let tx_hash = tx.hash();
@@ -765,10 +472,21 @@ impl TransactionPool {
         self.metrics
             .record_transaction_fee(fee_per_byte as f64, tx_size);
 
+        if admission == Admission::Queued {
+            self.queue_transaction(PooledTransaction {
+                added_time,
+                ..PooledTransaction::new(tx)
+            });
+            let processing_time = process_start.elapsed().as_micros() as u64;
+            self.metrics.record_transaction_added(processing_time, 0);
+            self.metrics.stop_operation(OperationType::Add);
+            return Ok(tx_hash);
+        }
+
         // Create pooled transaction
         let pooled_tx = PooledTransaction {
             transaction: tx.clone(),
-            added_time: self.get_current_time(),
+            added_time,
             is_valid: true,
             size: tx_size,
         };
@@ -792,7 +510,10 @@ impl TransactionPool {
                 // If we couldn't optimize and are over limit, reject
                 self.metrics.record_transaction_rejected();
                 self.metrics.stop_operation(OperationType::Add);
-                return Err(Error::Validation("Memory limit reached".into()));
+                return Err(TransactionError::MemoryLimitReached {
+                    current_bytes: self.memory_usage,
+                    max_bytes: self.config.max_memory,
+                });
             }
         }
 
@@ -801,6 +522,7 @@ impl TransactionPool {
             if let Some(lowest) = self
                 .by_fee
                 .iter()
+                .filter(|entry| self.txs.contains_key(&entry.tx_hash))
                 .min_by(|a, b| a.fee_per_byte.cmp(&b.fee_per_byte))
             {
                 // Solo si la nueva transacción tiene una tarifa más alta
@@ -823,6 +545,7 @@ impl TransactionPool {
 
         // Add to primary index
         self.txs.insert(tx_hash, pooled_tx);
+        self.unjournaled.insert(tx_hash);
 
         // Add to fee index
         self.by_fee.push(tx_with_fee);
@@ -834,7 +557,7 @@ impl TransactionPool {
             .insert(tx_hash);
 
         // Update transaction count metrics
-        self.metrics.update_transaction_count(self.txs.len());
+        self.update_count_metrics();
 
         // Record successful addition
         let processing_time = process_start.elapsed().as_micros() as u64;
@@ -842,6 +565,9 @@ impl TransactionPool {
         self.metrics
             .record_transaction_added(processing_time, validation_time);
 
+        // The new transaction may close the gap in front of queued ones
+        self.promote_queued(&tx.sender, state);
+
         // End operation timing
         self.metrics.stop_operation(OperationType::Add);
 
@@ -851,9 +577,8 @@ impl TransactionPool {
 
     /// Add multiple transactions to the pool in a batch operation
     ///
-    /// This is more efficient than adding transactions individually when many transactions
-    /// need to be added at once, as it allows for optimized database operations and
-    /// minimizes redundant calculations.
+    /// Each sender's transactions are added in nonce order, so a batch may
+    /// carry transactions that depend on each other in any order.
     ///
     /// # Parameters
     /// * `transactions` - Vector of transactions to add
@@ -866,127 +591,60 @@ impl TransactionPool {
         transactions: Vec<Transaction>,
         state: &mut BlockchainState,
     ) -> (Vec<Hash>, Vec<(usize, crate::Error)>) {
+        let (successes, failures) = self.add_transactions_batch_detailed(transactions, state);
+        let failures = failures
+            .into_iter()
+            .map(|(idx, e)| (idx, e.into()))
+            .collect();
+        (successes, failures)
+    }
+
+    /// Add multiple transactions to the pool, using detailed error reporting
+    ///
+    /// Each sender's transactions are added in nonce order against a scratch
+    /// state the ones already accepted are applied to, so later transactions
+    /// of the batch, queued ones included, must be affordable after them.
+    ///
+    /// # Parameters
+    /// * `transactions` - Vector of transactions to add
+    /// * `state` - Current blockchain state (for validation)
+    ///
+    /// # Returns
+    /// The hashes of the added transactions, and the position in the batch
+    /// of every refused one with the reason it was refused
+    pub fn add_transactions_batch_detailed(
+        &mut self,
+        transactions: Vec<Transaction>,
+        state: &mut BlockchainState,
+    ) -> (Vec<Hash>, Vec<(usize, TransactionError)>) {
         let mut successes = Vec::new();
         let mut failures = Vec::new();
 
-        // Agrupar las transacciones por remitente
+        // Group the transactions by sender
         let mut groups: HashMap<PublicKeyBytes, Vec<(usize, Transaction)>> = HashMap::new();
         for (idx, tx) in transactions.into_iter().enumerate() {
             groups.entry(tx.sender).or_default().push((idx, tx));
         }
 
-        // Para cada remitente, ordenar por nonce ascendente y procesar secuencialmente
-        for (_sender, mut txs_with_indices) in groups {
-            txs_with_indices.sort_by_key(|&(_, ref tx)| tx.nonce);
+        for (sender, mut txs_with_indices) in groups {
+            txs_with_indices.sort_by_key(|(_, tx)| tx.nonce);
 
-            // Crear una copia del estado para validación secuencial
             let mut temp_state = state.clone();
-
-            for (orig_idx, tx) in &txs_with_indices {
-                // Verificar firmas y validaciones básicas
-                if let Err(e) = tx.verify() {
-                    failures.push((*orig_idx, e));
-                    continue;
-                }
-
-                // Comprobar duplicados en el pool
-                let tx_hash = tx.hash();
-                if self.txs.contains_key(&tx_hash) {
-                    failures.push((
-                        *orig_idx,
-                        Error::Validation("Transaction already in pool".into()),
-                    ));
-                    continue;
-                }
-
-                // Validar nonce
-                let sender_state = temp_state.get_account_state(&tx.sender);
-                if tx.nonce != sender_state.nonce {
-                    failures.push((
-                        *orig_idx,
-                        Error::Validation(format!(
-                            "Invalid nonce: expected {}, got {}",
-                            sender_state.nonce, tx.nonce
-                        )),
-                    ));
-                    continue;
-                }
-
-                // Validar balance
-                let total_cost = tx.amount.saturating_add(tx.fee);
-                if sender_state.balance < total_cost {
-                    failures.push((
-                        *orig_idx,
-                        Error::Validation(format!(
-                            "Insufficient balance: has {}, needs {}",
-                            sender_state.balance, total_cost
-                        )),
-                    ));
-                    continue;
-                }
-
-                // Validar tarifa mínima
-                let tx_size = tx.estimate_size() as u64;
-                let fee_per_byte = if tx_size > 0 {
-                    tx.fee / tx_size
-                } else {
-                    tx.fee
-                };
-
-                if fee_per_byte < self.config.min_fee_per_byte {
-                    failures.push((
-                        *orig_idx,
-                        Error::Validation(format!(
-                            "Fee too low: {} per byte, minimum is {}",
-                            fee_per_byte, self.config.min_fee_per_byte
-                        )),
-                    ));
-                    continue;
+            for (idx, tx) in txs_with_indices {
+                match self.add_transaction_detailed(tx.clone(), &mut temp_state) {
+                    Ok(tx_hash) => {
+                        // Later transactions of the sender are checked against what this one leaves
+                        let state_nonce = temp_state.get_account_state(&sender).nonce;
+                        if tx.nonce == state_nonce && self.txs.contains_key(&tx_hash) {
+                            let _ = temp_state.apply_transaction(&tx);
+                        }
+                        successes.push(tx_hash);
+                    }
+                    Err(e) => failures.push((idx, e)),
                 }
-
-                // Si pasa todas las validaciones, actualizar el estado temporal
-                temp_state.get_account_state(&tx.sender).balance -= total_cost;
-                temp_state.get_account_state(&tx.recipient).balance += tx.amount;
-                temp_state.get_account_state(&tx.sender).nonce += 1;
-
-                // Añadir al pool sin modificar el estado real
-                let added_time = Instant::now(); // Usar Instant::now() directamente
-
-                // Calcular uso de memoria
-                let tx_memory_usage = self.calculate_transaction_memory_usage(&tx);
-
-                let pooled_tx = PooledTransaction {
-                    transaction: tx.clone(),
-                    added_time,
-                    is_valid: true,
-                    size: tx_memory_usage,
-                };
-
-                // Add to primary index
-                self.txs.insert(tx_hash, pooled_tx);
-                successes.push(tx_hash);
-
-                // Update secondary indices - fee index and address index
-                let tx_with_fee = TransactionWithFee {
-                    tx_hash,
-                    fee: tx.fee,
-                    fee_per_byte,
-                    timestamp: added_time,
-                };
-
-                // Add to fee index
-                self.by_fee.push(tx_with_fee);
-
-                // Add to sender index
-                self.by_address
-                    .entry(tx.sender)
-                    .or_insert_with(HashSet::new)
-                    .insert(tx_hash);
-
-                // Update memory usage
-                self.memory_usage += tx_memory_usage;
             }
         }
+
         (successes, failures)
     }
 
@@ -1035,96 +693,69 @@ impl TransactionPool {
         (successful, failed)
     }
 
+    /// Select the transactions for the next block, highest fee per byte first
+    ///
+    /// Each sender's transactions come out in nonce order starting at its
+    /// account nonce, and only as long as its balance covers them. Among the
+    /// next executable transaction of every sender, the one paying the most
+    /// per byte is taken, the older one on equal fees. A heap over those
+    /// candidates keeps selecting `k` of `n` transactions at O(n log n).
+    ///
+    /// # Parameters
+    /// * `max_count` - Maximum number of transactions to select
+    /// * `state` - Current blockchain state
     pub fn select_transactions(
         &mut self,
         max_count: usize,
-        state: &mut BlockchainState,
+        state: &BlockchainState,
     ) -> Vec<Transaction> {
         self.metrics.start_operation(OperationType::Select);
 
-        let mut result = Vec::new();
-
-        // En vez de usar estados mantenidos internamente, usar directamente los valores del state pasado como parámetro
-        let mut sender_states: HashMap<PublicKeyBytes, (u64, u64)> = HashMap::new();
-
-        // Obtener los estados iniciales para todos los remitentes
-        for pooled_tx in self.txs.values() {
-            if pooled_tx.is_valid {
-                let sender = pooled_tx.transaction.sender;
-                if !sender_states.contains_key(&sender) {
-                    let account = state.get_account_state(&sender);
-                    sender_states.insert(sender, (account.balance, account.nonce));
-                }
+        // Valid pending transactions per sender by nonce
+        let mut by_sender: HashMap<PublicKeyBytes, BTreeMap<u64, SelectionCandidate>> = HashMap::new();
+        for (hash, pooled_tx) in &self.txs {
+            if !pooled_tx.is_valid {
+                continue;
             }
+            let tx = &pooled_tx.transaction;
+            let candidate = SelectionCandidate {
+                fee_per_byte: self.calculate_fee_per_byte(tx),
+                added_time: pooled_tx.added_time,
+                tx_hash: *hash,
+            };
+            by_sender.entry(tx.sender).or_default().insert(tx.nonce, candidate);
         }
 
-        // Eliminar las transacciones ya procesadas
-        let mut processed_hashes = HashSet::new();
-
-        // Procesar todas las transacciones válidas
-        for _ in 0..max_count {
-            // Encontrar la próxima transacción válida para cada remitente
-            let mut valid_txs = Vec::new();
-
-            for (hash, pooled_tx) in &self.txs {
-                if processed_hashes.contains(hash) || !pooled_tx.is_valid {
-                    continue;
-                }
-
-                let tx = &pooled_tx.transaction;
-                let sender = tx.sender;
-
-                // Obtener el estado actual para este remitente
-                let (current_balance, current_nonce) =
-                    if let Some(&state_values) = sender_states.get(&sender) {
-                        state_values
-                    } else {
-                        let account = state.get_account_state(&sender);
-                        let values = (account.balance, account.nonce);
-                        sender_states.insert(sender, values);
-                        values
-                    };
-
-                // Verificar nonce
-                if tx.nonce != current_nonce {
-                    continue;
-                }
-
-                // Verificar balance
-                let total_cost = tx.amount.saturating_add(tx.fee);
-                if current_balance < total_cost {
-                    continue;
-                }
-
-                // Transacción válida - añadir a candidatas
-                valid_txs.push((hash, pooled_tx, self.calculate_fee_per_byte(tx)));
+        // Balance and nonce of every sender as selection goes on
+        let mut accounts: HashMap<PublicKeyBytes, (u64, u64)> = HashMap::with_capacity(by_sender.len());
+        let mut heap = BinaryHeap::with_capacity(by_sender.len());
+        for sender in by_sender.keys() {
+            let (balance, nonce) = state
+                .accounts
+                .get(sender)
+                .map_or((0, 0), |account| (account.balance, account.nonce));
+            accounts.insert(*sender, (balance, nonce));
+            if let Some(candidate) = self.next_candidate(&by_sender, sender, balance, nonce) {
+                heap.push(candidate);
             }
+        }
 
-            if valid_txs.is_empty() {
+        let mut result = Vec::with_capacity(max_count.min(self.txs.len()));
+        while result.len() < max_count {
+            let Some(candidate) = heap.pop() else {
                 break;
-            }
-
-            // Ordenar por fee (mayor primero) y luego por timestamp (más antiguo primero)
-            valid_txs.sort_by(|&(_, a, fee_a), &(_, b, fee_b)| {
-                fee_b
-                    .partial_cmp(&fee_a)
-                    .unwrap_or(std::cmp::Ordering::Equal)
-                    .then_with(|| a.added_time.cmp(&b.added_time))
-            });
-
-            // Seleccionar la transacción de mayor prioridad
-            let (selected_hash, selected_tx, _) = valid_txs[0];
-            let tx = &selected_tx.transaction;
+            };
+            let tx = &self.txs[&candidate.tx_hash].transaction;
 
-            // Actualizar el estado
-            let (balance, nonce) = sender_states.get_mut(&tx.sender).unwrap();
-            *balance -= tx.amount + tx.fee;
+            let (balance, nonce) = accounts
+                .get_mut(&tx.sender)
+                .expect("every candidate's sender has an account entry");
+            *balance -= tx.balance_cost();
             *nonce += 1;
 
-            // Marcar como procesada
-            processed_hashes.insert(*selected_hash);
-
-            // Añadir a resultados
+            if let Some(next) = self.next_candidate(&by_sender, &tx.sender, *balance, *nonce) {
+                heap.push(next);
+            }
             result.push(tx.clone());
         }
 
@@ -1132,6 +763,19 @@ impl TransactionPool {
         result
     }
 
+    /// Get a sender's transaction at `nonce` if the balance covers it
+    fn next_candidate(
+        &self,
+        by_sender: &HashMap<PublicKeyBytes, BTreeMap<u64, SelectionCandidate>>,
+        sender: &PublicKeyBytes,
+        balance: u64,
+        nonce: u64,
+    ) -> Option<SelectionCandidate> {
+        let candidate = by_sender.get(sender)?.get(&nonce)?;
+        let tx = &self.txs.get(&candidate.tx_hash)?.transaction;
+        (balance >= tx.balance_cost()).then_some(*candidate)
+    }
+
     pub fn select_transactions_for_test(&self, max_count: usize) -> Vec<Transaction> {
         let mut result = Vec::new();
 
@@ -1183,6 +827,11 @@ impl TransactionPool {
                 expired_hashes.push(*hash);
             }
         }
+        for pooled_tx in self.queued.values().flat_map(BTreeMap::values) {
+            if now.duration_since(pooled_tx.added_time) > max_age {
+                expired_hashes.push(pooled_tx.transaction.hash());
+            }
+        }
 
         let count = expired_hashes.len();
         for hash in expired_hashes {
@@ -1197,33 +846,117 @@ impl TransactionPool {
         count
     }
 
-    /// Validate a transaction before adding to pool
-    fn validate_transaction_basic(&self, tx: &Transaction) -> Result<(), PoolError> {
-        // Validate signature
-        if tx.verify().is_err() {
-            return Err(PoolError::InvalidSignature);
+    /// Get the journal changes since the journal was last saved
+    ///
+    /// # Returns
+    /// The transactions that are in the pool now, with their journal entries,
+    /// and the hashes of the ones that have left it
+    pub fn journal_changes(&self) -> (Vec<(Hash, PoolJournalEntry)>, Vec<Hash>) {
+        let now = Instant::now();
+        let now_ms = now_ms();
+
+        let mut saved = Vec::new();
+        let mut removed = Vec::new();
+        for hash in &self.unjournaled {
+            let pooled_tx = self.txs.get(hash).or_else(|| {
+                let (sender, nonce) = self.queued_index.get(hash)?;
+                self.queued.get(sender)?.get(nonce)
+            });
+            match pooled_tx {
+                Some(pooled_tx) => {
+                    let age_ms = now.duration_since(pooled_tx.added_time).as_millis() as u64;
+                    saved.push((
+                        *hash,
+                        PoolJournalEntry {
+                            transaction: pooled_tx.transaction.clone(),
+                            added_at: now_ms.saturating_sub(age_ms),
+                        },
+                    ));
+                }
+                None => removed.push(*hash),
+            }
+        }
+
+        (saved, removed)
+    }
+
+    /// Forget the journal changes once they have been saved
+    pub fn clear_journal_changes(&mut self) {
+        self.unjournaled.clear();
+    }
+
+    /// Restore transactions from the pool journal after a restart
+    ///
+    /// Transactions older than the configured expiry time are dropped, and
+    /// the rest are validated against `state` like new submissions, keeping
+    /// the time they originally entered the pool. Restored transactions do
+    /// not count against their sender's submission rate. Every journaled
+    /// transaction is part of the next journal changes, so the ones that did
+    /// not make it back leave the journal.
+    ///
+    /// # Parameters
+    /// * `entries` - The journaled transactions
+    /// * `state` - Current blockchain state (for validation)
+    ///
+    /// # Returns
+    /// The number of transactions restored
+    pub fn restore_journal(&mut self, mut entries: Vec<PoolJournalEntry>, state: &mut BlockchainState) -> usize {
+        let max_age = Duration::from_secs(self.config.expiry_time);
+        let now = Instant::now();
+        let now_ms = now_ms();
+
+        // Lower nonces first, so no transaction waits behind a gap the journal closes
+        entries.sort_by_key(|entry| (entry.transaction.nonce, entry.added_at));
+
+        let mut restored = 0;
+        let mut expired = 0;
+        for entry in entries {
+            let tx_hash = entry.transaction.hash();
+            self.unjournaled.insert(tx_hash);
+
+            let age = Duration::from_millis(now_ms.saturating_sub(entry.added_at));
+            let Some(added_time) = now.checked_sub(age).filter(|_| age <= max_age) else {
+                debug!("Journaled transaction {} expired", hex::encode(&tx_hash[0..4]));
+                expired += 1;
+                continue;
+            };
+
+            match self.admit_transaction(entry.transaction, state, added_time) {
+                Ok(_) => restored += 1,
+                Err(e) => debug!(
+                    "Dropped journaled transaction {}: {}",
+                    hex::encode(&tx_hash[0..4]),
+                    e
+                ),
+            }
         }
 
-        // Basic validation
-        if tx.amount == 0 && tx.data.is_empty() {
-            return Err(PoolError::InvalidFormat);
+        if expired > 0 {
+            self.metrics.record_transactions_expired(expired);
         }
 
-        Ok(())
+        restored
     }
 
-    /// Remove a transaction from the pool
+    /// Remove a pending or queued transaction from the pool
     pub fn remove_transaction(&mut self, hash: &Hash) -> bool {
         self.metrics.start_operation(OperationType::Remove);
 
+        let removed = self.detach_pending(hash).is_some() || self.unqueue(hash).is_some();
+        if removed {
+            self.update_count_metrics();
+            self.metrics.record_transaction_removed();
+        }
+
+        self.metrics.stop_operation(OperationType::Remove);
+        removed
+    }
+
+    /// Take a transaction out of the pending set and its indices
+    fn detach_pending(&mut self, hash: &Hash) -> Option<PooledTransaction> {
         // Remove from main index and get the transaction
-        let pooled_tx = match self.txs.remove(hash) {
-            Some(tx) => tx,
-            None => {
-                self.metrics.stop_operation(OperationType::Remove);
-                return false;
-            }
-        };
+        let pooled_tx = self.txs.remove(hash)?;
+        self.unjournaled.insert(*hash);
 
         let tx = &pooled_tx.transaction;
 
@@ -1260,8 +993,6 @@ impl TransactionPool {
 
         // Update metrics
         self.metrics.update_memory_usage(self.memory_usage);
-        self.metrics.update_transaction_count(self.txs.len());
-        self.metrics.record_transaction_removed();
 
         // Remove from sender index
         if let Some(sender_txs) = self.by_address.get_mut(&tx.sender) {
@@ -1275,8 +1006,135 @@ impl TransactionPool {
         // Instead, we'll filter them out when selecting transactions
         // This avoids O(n) removal cost from the heap
 
-        self.metrics.stop_operation(OperationType::Remove);
-        true
+        Some(pooled_tx)
+    }
+
+    /// Add a transaction to the pending set and its indices
+    fn insert_pending(&mut self, pooled_tx: PooledTransaction) {
+        let tx = &pooled_tx.transaction;
+        self.by_fee.push(TransactionWithFee {
+            tx_hash: tx.hash(),
+            fee: tx.fee,
+            fee_per_byte: self.calculate_fee_per_byte(tx),
+            timestamp: pooled_tx.added_time,
+        });
+        self.index_pending(pooled_tx);
+    }
+
+    /// Add a transaction to the pending set and every index but the fee index
+    ///
+    /// For a transaction that left the pending set so recently that its fee
+    /// entry, which is only dropped lazily, is still there.
+    fn index_pending(&mut self, pooled_tx: PooledTransaction) {
+        let tx = &pooled_tx.transaction;
+        let tx_hash = tx.hash();
+
+        self.memory_usage += self.calculate_transaction_memory_usage(tx);
+        self.metrics.update_memory_usage(self.memory_usage);
+
+        self.by_address.entry(tx.sender).or_default().insert(tx_hash);
+        self.txs.insert(tx_hash, pooled_tx);
+        self.unjournaled.insert(tx_hash);
+    }
+
+    /// Put a transaction into its sender's queue until the nonce gap in front of it closes
+    fn queue_transaction(&mut self, pooled_tx: PooledTransaction) {
+        let tx_hash = pooled_tx.transaction.hash();
+        let (sender, nonce) = (pooled_tx.transaction.sender, pooled_tx.transaction.nonce);
+
+        self.queued_index.insert(tx_hash, (sender, nonce));
+        self.queued.entry(sender).or_default().insert(nonce, pooled_tx);
+        self.unjournaled.insert(tx_hash);
+        self.update_count_metrics();
+
+        debug!(
+            "Queued transaction {} behind a nonce gap",
+            hex::encode(&tx_hash[0..4])
+        );
+    }
+
+    /// Take a transaction out of its sender's queue
+    fn unqueue(&mut self, hash: &Hash) -> Option<PooledTransaction> {
+        let (sender, nonce) = self.queued_index.remove(hash)?;
+        self.unjournaled.insert(*hash);
+        let queue = self.queued.get_mut(&sender)?;
+        let pooled_tx = queue.remove(&nonce);
+        if queue.is_empty() {
+            self.queued.remove(&sender);
+        }
+        pooled_tx
+    }
+
+    /// Move a sender's queued transactions to the pending set as far as their nonces follow on
+    ///
+    /// Queued transactions the sender's account nonce has already passed
+    /// can never be included and are dropped. Promotion stops at the first
+    /// transaction the balance left after the pending ones cannot pay for,
+    /// at the first one that would take the sender past its share of the
+    /// pool, or once the pool is full.
+    ///
+    /// # Returns
+    /// The number of transactions promoted
+    fn promote_queued(&mut self, sender: &PublicKeyBytes, state: &mut BlockchainState) -> usize {
+        let Some(queue) = self.queued.get(sender) else {
+            return 0;
+        };
+        let account = state.get_account_state(sender);
+        let (state_nonce, balance) = (account.nonce, account.balance);
+
+        let stale: Vec<Hash> = queue
+            .range(..state_nonce)
+            .map(|(_, pooled_tx)| pooled_tx.transaction.hash())
+            .collect();
+        for hash in stale {
+            self.unqueue(&hash);
+            self.metrics.record_transaction_removed();
+        }
+
+        let mut promoted = 0;
+        loop {
+            let next_nonce = self.next_nonce(sender, state_nonce);
+            let Some(pooled_tx) = self.queued.get(sender).and_then(|queue| queue.get(&next_nonce)) else {
+                break;
+            };
+            let tx = &pooled_tx.transaction;
+            let available = balance.saturating_sub(self.pending_cost(sender, state_nonce));
+            if available < tx.balance_cost()
+                || self.txs.len() >= self.config.max_size
+                || self.memory_usage + self.calculate_transaction_memory_usage(tx) > self.config.max_memory
+                || self.check_sender_quota(tx, Admission::Pending).is_err()
+            {
+                break;
+            }
+
+            let hash = tx.hash();
+            if let Some(pooled_tx) = self.unqueue(&hash) {
+                self.insert_pending(pooled_tx);
+                promoted += 1;
+                debug!("Promoted queued transaction {}", hex::encode(&hash[0..4]));
+            }
+        }
+
+        self.update_count_metrics();
+        promoted
+    }
+
+    /// Get what a sender's pending transactions not yet covered by `state_nonce` take from its balance
+    fn pending_cost(&self, sender: &PublicKeyBytes, state_nonce: u64) -> u64 {
+        self.by_address.get(sender).map_or(0, |tx_hashes| {
+            tx_hashes
+                .iter()
+                .filter_map(|hash| self.txs.get(hash))
+                .filter(|pooled_tx| pooled_tx.transaction.nonce >= state_nonce)
+                .fold(0u64, |cost, pooled_tx| cost.saturating_add(pooled_tx.transaction.balance_cost()))
+        })
+    }
+
+    /// Record the pending and queued transaction counts
+    fn update_count_metrics(&mut self) {
+        self.metrics.update_transaction_count(self.txs.len());
+        self.metrics
+            .update_queue_counts(self.txs.len(), self.queued_index.len());
     }
 
     // Implementation moved to a single location below
@@ -1448,6 +1306,15 @@ impl TransactionPool {
         // Remove expired transactions
         removed += self.remove_expired();
 
+        // Forget senders that have not submitted anything for a whole window
+        let window = Duration::from_secs(self.config.submission_window);
+        let now = Instant::now();
+        self.submissions.retain(|_, times| {
+            times
+                .back()
+                .is_some_and(|latest| now.duration_since(*latest) < window)
+        });
+
         // Optimize memory usage if needed
         removed += self.optimize_memory();
 
@@ -1473,22 +1340,35 @@ impl TransactionPool {
         removed
     }
 
-    /// Get the number of transactions in the pool
+    /// Get the number of pending transactions in the pool
     pub fn len(&self) -> usize {
         self.txs.len()
     }
 
+    /// Get the number of transactions queued behind nonce gaps
+    pub fn queued_len(&self) -> usize {
+        self.queued_index.len()
+    }
+
     /// Check if the pool is empty
     pub fn is_empty(&self) -> bool {
         self.txs.is_empty()
     }
 
-    /// Get a transaction from the pool
+    /// Get a pending or queued transaction from the pool
     pub fn get_transaction(&self, hash: &Hash) -> Option<&Transaction> {
-        self.txs.get(hash).map(|pooled_tx| &pooled_tx.transaction)
+        if let Some(pooled_tx) = self.txs.get(hash) {
+            return Some(&pooled_tx.transaction);
+        }
+
+        let (sender, nonce) = self.queued_index.get(hash)?;
+        self.queued
+            .get(sender)
+            .and_then(|queue| queue.get(nonce))
+            .map(|pooled_tx| &pooled_tx.transaction)
     }
 
-    /// Get all transactions currently in the pool
+    /// Get all pending transactions currently in the pool
     ///
     /// # Returns
     /// An iterator over all transactions
@@ -1501,35 +1381,93 @@ impl TransactionPool {
     /// This is typically called after a block is processed to update
     /// the validity status of pending transactions.
     ///
+    /// Each sender's pending transactions are checked in nonce order from
+    /// its account nonce, so every one must be affordable after the ones
+    /// before it. Pending transactions left behind a nonce gap, for example
+    /// after an earlier one was evicted, go back to the queue, and queued
+    /// transactions whose gap has closed are promoted.
+    ///
     /// # Parameters
     /// * `state` - Current blockchain state
     pub fn revalidate_transactions(&mut self, state: &mut BlockchainState) {
         self.metrics.start_operation(OperationType::Revalidate);
 
-        for (tx_hash, pooled_tx) in self.txs.iter_mut() {
+        let mut by_sender: HashMap<PublicKeyBytes, Vec<(u64, Hash)>> = HashMap::new();
+        for (tx_hash, pooled_tx) in &self.txs {
             let tx = &pooled_tx.transaction;
+            by_sender.entry(tx.sender).or_default().push((tx.nonce, *tx_hash));
+        }
+
+        let mut gapped = Vec::new();
+        for (sender, mut pending) in by_sender {
+            pending.sort_unstable();
 
             // Get sender's current balance and nonce
-            let sender_state = state.get_account_state(&tx.sender);
+            let sender_state = state.get_account_state(&sender);
+            let (mut next_nonce, mut balance, stake) =
+                (sender_state.nonce, sender_state.balance, sender_state.stake);
+            // A transaction that cannot execute blocks every later nonce
+            let mut executable = true;
+
+            for (nonce, tx_hash) in pending {
+                if nonce > next_nonce {
+                    gapped.push(tx_hash);
+                    continue;
+                }
+                let Some(pooled_tx) = self.txs.get_mut(&tx_hash) else {
+                    continue;
+                };
+                let tx = &pooled_tx.transaction;
 
-            // Check if sender has enough balance
-            let required = tx.amount.saturating_add(tx.fee);
-            let has_sufficient_balance = sender_state.balance >= required;
+                // Evidence goes stale once its offence was punished
+                let has_applicable_evidence = tx
+                    .evidence()
+                    .map_or(true, |evidence| state.check_evidence(&evidence).is_ok());
 
-            // Check if nonce is still valid (should be current nonce)
-            let has_valid_nonce = tx.nonce == sender_state.nonce;
+                // Check if sender has enough balance left by its earlier transactions
+                let required = tx.balance_cost();
+                let has_sufficient_balance = balance >= required
+                    && (!matches!(tx.kind(), Ok(TransactionKind::Unstake)) || stake >= tx.amount);
 
-            // Update transaction validity
-            pooled_tx.is_valid = has_sufficient_balance && has_valid_nonce;
+                // Check if nonce is still valid (should be the next one to execute)
+                let has_valid_nonce = nonce == next_nonce;
+                if has_valid_nonce {
+                    next_nonce += 1;
+                }
 
-            if !pooled_tx.is_valid {
-                debug!(
-                    "Transaction {} invalidated during revalidation",
-                    hex::encode(&tx_hash[0..4])
-                );
+                // Update transaction validity
+                pooled_tx.is_valid =
+                    executable && has_sufficient_balance && has_valid_nonce && has_applicable_evidence;
+
+                if pooled_tx.is_valid {
+                    balance -= required;
+                } else {
+                    executable &= !has_valid_nonce;
+                    debug!(
+                        "Transaction {} invalidated during revalidation",
+                        hex::encode(&tx_hash[0..4])
+                    );
+                }
+            }
+        }
+
+        for tx_hash in gapped {
+            let Some(pooled_tx) = self.detach_pending(&tx_hash) else {
+                continue;
+            };
+            if self.check_queue(&pooled_tx.transaction).is_ok() {
+                self.queue_transaction(pooled_tx);
+            } else {
+                self.metrics.record_transaction_removed();
             }
         }
 
+        let senders: Vec<PublicKeyBytes> = self.queued.keys().copied().collect();
+        for sender in senders {
+            self.promote_queued(&sender, state);
+        }
+        self.update_count_metrics();
+
         self.metrics.stop_operation(OperationType::Revalidate);
     }
 
@@ -1556,6 +1494,7 @@ impl TransactionPool {
         // Get the transaction with the lowest fee from the by_fee vector
         self.by_fee
             .iter()
+            .filter(|entry| self.txs.contains_key(&entry.tx_hash))
             .min_by_key(|tx| tx.fee_per_byte)
             .and_then(|tx_with_fee| self.txs.get(&tx_with_fee.tx_hash))
             .map(|pooled_tx| &pooled_tx.transaction)
@@ -1569,15 +1508,34 @@ impl TransactionPool {
         self.memory_usage
     }
 
+    /// Verify a transaction and check that it was signed for the network this pool serves
+    fn verify_for_network(&self, tx: &Transaction) -> TxResult<()> {
+        tx.verify_for_network(self.config.network_id).map_err(|e| {
+            if !tx.has_network_id() {
+                TransactionError::Validation(e.to_string())
+            } else if tx.network_id != self.config.network_id {
+                TransactionError::WrongNetwork {
+                    expected: self.config.network_id,
+                    actual: tx.network_id,
+                }
+            } else {
+                TransactionError::InvalidSignature
+            }
+        })
+    }
+
     /// Validate a transaction and create rich error information (internal helper)
+    ///
+    /// # Returns
+    /// Whether the transaction is pending or has to wait behind a nonce gap
     fn validate_transaction_internal(
         &self,
         tx: &Transaction,
         state: &mut BlockchainState,
-    ) -> TxResult<()> {
+    ) -> TxResult<Admission> {
         // Step 1: Check if transaction already exists
         let tx_hash = tx.hash();
-        if self.txs.contains_key(&tx_hash) {
+        if self.txs.contains_key(&tx_hash) || self.queued_index.contains_key(&tx_hash) {
             return Err(TransactionError::AlreadyExists { tx_hash });
         }
             
@@ -1587,33 +1545,62 @@ impl TransactionPool {
                 tx_hash: existing_tx.hash() 
             });
         }
+        if let Some(queued) = self.queued.get(&tx.sender).and_then(|queue| queue.get(&tx.nonce)) {
+            return Err(TransactionError::AlreadyExists {
+                tx_hash: queued.transaction.hash(),
+            });
+        }
 
-        // Step 2: Verify transaction signature if not already done
-        if let Err(_) = tx.verify() {
-            return Err(TransactionError::InvalidSignature);
+        // Step 2: Verify the transaction and reject ones signed for another network
+        self.verify_for_network(tx)?;
+
+        // Step 2b: Evidence must prove an offence that was not punished yet
+        if let Ok(evidence) = tx.evidence() {
+            state
+                .check_evidence(&evidence)
+                .map_err(|e| TransactionError::Validation(e.to_string()))?;
         }
 
         // Step 3: Get account state
         let sender_state = state.get_account_state(&tx.sender);
 
-        // Step 4: Validate nonce
-        if tx.nonce != sender_state.nonce {
+        // Step 4: Validate nonce; nonces past the sender's pending ones wait in the queue
+        let next_nonce = self.next_nonce(&tx.sender, sender_state.nonce);
+        if tx.nonce < next_nonce {
             return Err(TransactionError::InvalidNonce {
                 sender: tx.sender,
-                expected: sender_state.nonce,
+                expected: next_nonce,
                 actual: tx.nonce,
             });
         }
+        let admission = if tx.nonce == next_nonce {
+            Admission::Pending
+        } else {
+            Admission::Queued
+        };
 
-        // Step 5: Validate balance
-        let total_cost = tx.amount.saturating_add(tx.fee);
-        if sender_state.balance < total_cost {
+        // Step 5: Validate balance, after what the sender's pending transactions spend
+        let total_cost = tx.balance_cost();
+        let available = match admission {
+            Admission::Pending => sender_state
+                .balance
+                .saturating_sub(self.pending_cost(&tx.sender, sender_state.nonce)),
+            Admission::Queued => sender_state.balance,
+        };
+        if available < total_cost {
             return Err(TransactionError::InsufficientBalance {
                 sender: tx.sender,
-                balance: sender_state.balance,
+                balance: available,
                 required: total_cost,
             });
         }
+        if matches!(tx.kind(), Ok(TransactionKind::Unstake)) && sender_state.stake < tx.amount {
+            return Err(TransactionError::InsufficientStake {
+                sender: tx.sender,
+                stake: sender_state.stake,
+                required: tx.amount,
+            });
+        }
 
         // Step 6: Validate minimum fee
         let tx_size = tx.estimate_size() as u64;
@@ -1630,7 +1617,16 @@ impl TransactionPool {
             });
         }
 
-        // Step 7: Check pool size limit - MODIFICAR AQUÍ
+        // Step 6b: No sender may take more than its share of the pool
+        self.check_sender_quota(tx, admission)?;
+
+        // Step 7: Queued transactions only count against the queue limits
+        if admission == Admission::Queued {
+            self.check_queue(tx)?;
+            return Ok(Admission::Queued);
+        }
+
+        // Step 7b: Check pool size limit
         if self.txs.len() >= self.config.max_size {
             // Si el pool está lleno, verificar si esta transacción tiene una tarifa más alta
             // que alguna ya existente
@@ -1640,12 +1636,13 @@ impl TransactionPool {
             if let Some(lowest) = self
                 .by_fee
                 .iter()
+                .filter(|entry| self.txs.contains_key(&entry.tx_hash))
                 .min_by(|a, b| a.fee_per_byte.cmp(&b.fee_per_byte))
             {
                 // Si la nueva transacción tiene una tarifa más alta que la más baja
                 if tx_fee_per_byte > lowest.fee_per_byte {
                     // Esta transacción es válida y puede reemplazar a la más baja
-                    return Ok(());
+                    return Ok(Admission::Pending);
                 }
             }
 
@@ -1665,9 +1662,100 @@ impl TransactionPool {
             });
         }
 
+        Ok(Admission::Pending)
+    }
+
+    /// Check that a transaction fits into its sender's queue
+    fn check_queue(&self, tx: &Transaction) -> TxResult<()> {
+        let sender_queue = self.queued.get(&tx.sender);
+        if let Some(existing) = sender_queue.and_then(|queue| queue.get(&tx.nonce)) {
+            return Err(TransactionError::AlreadyExists {
+                tx_hash: existing.transaction.hash(),
+            });
+        }
+
+        let sender_queued = sender_queue.map_or(0, BTreeMap::len);
+        if sender_queued >= self.config.max_queued_per_sender {
+            return Err(TransactionError::PoolFull {
+                current_size: sender_queued,
+                max_size: self.config.max_queued_per_sender,
+            });
+        }
+        if self.queued_index.len() >= self.config.max_queued {
+            return Err(TransactionError::PoolFull {
+                current_size: self.queued_index.len(),
+                max_size: self.config.max_queued,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Check that a transaction stays within its sender's share of the pool
+    ///
+    /// Only pending transactions count against the pending limit, queued ones
+    /// have their own, but both count against the size limit. A queued
+    /// transaction with the same nonce is not counted, so a queued
+    /// transaction can be checked again before it is promoted.
+    fn check_sender_quota(&self, tx: &Transaction, admission: Admission) -> TxResult<()> {
+        let pending = self.by_address.get(&tx.sender);
+        if admission == Admission::Pending
+            && pending.map_or(0, HashSet::len) >= self.config.max_pending_per_sender
+        {
+            return Err(TransactionError::RateLimited { sender: tx.sender });
+        }
+
+        let pending_bytes: usize = pending
+            .into_iter()
+            .flatten()
+            .filter_map(|hash| self.txs.get(hash))
+            .map(|pooled_tx| pooled_tx.transaction.estimate_size())
+            .sum();
+        let queued_bytes: usize = self
+            .queued
+            .get(&tx.sender)
+            .into_iter()
+            .flat_map(BTreeMap::values)
+            .filter(|pooled_tx| pooled_tx.transaction.nonce != tx.nonce)
+            .map(|pooled_tx| pooled_tx.transaction.estimate_size())
+            .sum();
+        if pending_bytes + queued_bytes + tx.estimate_size() > self.config.max_bytes_per_sender {
+            return Err(TransactionError::RateLimited { sender: tx.sender });
+        }
+
+        Ok(())
+    }
+
+    /// Check that a sender has not used up its submissions for the current window
+    fn check_submission_rate(&self, sender: &PublicKeyBytes) -> TxResult<()> {
+        let window = Duration::from_secs(self.config.submission_window);
+        let now = Instant::now();
+        let recent = self.submissions.get(sender).map_or(0, |times| {
+            times
+                .iter()
+                .filter(|time| now.duration_since(**time) < window)
+                .count()
+        });
+        if recent >= self.config.max_submissions_per_sender {
+            return Err(TransactionError::RateLimited { sender: *sender });
+        }
+
         Ok(())
     }
 
+    /// Count an accepted submission against its sender's rate
+    fn record_submission(&mut self, sender: PublicKeyBytes, time: Instant) {
+        let window = Duration::from_secs(self.config.submission_window);
+        let times = self.submissions.entry(sender).or_default();
+        while times
+            .front()
+            .is_some_and(|oldest| time.duration_since(*oldest) >= window)
+        {
+            times.pop_front();
+        }
+        times.push_back(time);
+    }
+
     /// Public API for validating a transaction using the detailed error system
     pub fn validate_transaction(
         &self,
@@ -1681,111 +1769,137 @@ impl TransactionPool {
         let result = self.validate_transaction_internal(tx, state);
 
         // Return the result
-        result
+        result.map(|_| ())
     }
 
     /// Add a transaction with replacement option, using detailed error reporting
+    ///
+    /// A transaction from the same sender with the same nonce, pending or
+    /// queued, is replaced if `allow_replacement` is set and the new one
+    /// pays at least the configured fee bump more. The new transaction is
+    /// validated as if the old one had never been in the pool, and the old
+    /// one stays if it is refused.
+    ///
+    /// # Parameters
+    /// * `tx` - The transaction to add
+    /// * `state` - Current blockchain state (for validation)
+    /// * `allow_replacement` - Whether to allow replacing existing transactions
+    ///
+    /// # Returns
+    /// `Ok(hash)` if transaction was added successfully, otherwise the
+    /// reason it was refused
     pub fn add_transaction_with_replacement_detailed(
         &mut self,
         tx: Transaction,
         state: &mut BlockchainState,
         allow_replacement: bool,
     ) -> TxResult<Hash> {
-        let tx_hash = tx.hash();
-
-        // Check for existing transaction with same sender and nonce
-        let existing_tx = self.find_transaction_by_sender_and_nonce(&tx.sender, tx.nonce);
-
-        if let Some(existing_tx) = existing_tx {
-            // Found existing transaction with same sender/nonce
-            if !allow_replacement {
-                // If replacement not allowed, return an error
-                return Err(TransactionError::AlreadyExists {
-                    tx_hash: existing_tx.hash(),
-                });
-            }
-
-            // Calculate minimum required fee for replacement
-            let min_required_fee = existing_tx
-                .fee
-                .saturating_mul(100 + self.config.replacement_fee_bump)
-                .saturating_div(100);
-
-            // Check if new transaction has sufficient fee for replacement
-            if tx.fee < min_required_fee {
-                return Err(TransactionError::ReplacementFeeTooLow {
-                    actual: tx.fee,
-                    required: min_required_fee,
-                });
-            }
-
-            // Validate the new transaction (other than duplicate check)
-            self.validate_transaction_internal(&tx, state)?;
+        let Some(existing_hash) = self.find_hash_by_sender_and_nonce(&tx.sender, tx.nonce) else {
+            return self.add_transaction_detailed(tx, state);
+        };
 
-            // Remove the existing transaction
-            self.remove_transaction(&existing_tx.hash());
+        let result = self.replace_transaction(tx, existing_hash, state, allow_replacement);
+        if let Err(e) = &result {
+            debug!("Replacement rejected: {}", e.log_context());
+            self.metrics.record_transaction_rejected();
+        }
+        result
+    }
 
-            // Add the new transaction (implementation left out for brevity)
+    /// Replace the pending or queued transaction `existing_hash` with `tx`
+    fn replace_transaction(
+        &mut self,
+        tx: Transaction,
+        existing_hash: Hash,
+        state: &mut BlockchainState,
+        allow_replacement: bool,
+    ) -> TxResult<Hash> {
+        if !allow_replacement || tx.hash() == existing_hash {
+            return Err(TransactionError::AlreadyExists {
+                tx_hash: existing_hash,
+            });
+        }
+        self.check_submission_rate(&tx.sender)?;
+
+        let existing_fee = self
+            .get_transaction(&existing_hash)
+            .map_or(0, |existing_tx| existing_tx.fee);
+        let min_required_fee = existing_fee
+            .saturating_mul(100 + self.config.replacement_fee_bump)
+            .saturating_div(100);
+        if tx.fee < min_required_fee {
+            return Err(TransactionError::ReplacementFeeTooLow {
+                actual: tx.fee,
+                required: min_required_fee,
+            });
+        }
 
-            Ok(tx_hash)
-        } else {
-            // No existing transaction with this sender/nonce
-            // Just validate and add normally
-            self.validate_transaction_internal(&tx, state)?;
+        // Take the old transaction out, and put it back if the new one is refused
+        let (existing, was_pending) = match self.detach_pending(&existing_hash) {
+            Some(pooled_tx) => (pooled_tx, true),
+            None => match self.unqueue(&existing_hash) {
+                Some(pooled_tx) => (pooled_tx, false),
+                None => {
+                    return Err(TransactionError::Internal(
+                        "Replaced transaction left the pool".into(),
+                    ))
+                }
+            },
+        };
 
-            // Implementation left out for brevity
+        let sender = tx.sender;
+        let added_time = self.get_current_time();
+        match self.admit_transaction(tx, state, added_time) {
+            Ok(tx_hash) => {
+                self.record_submission(sender, added_time);
+                self.metrics.record_transaction_removed();
+                self.update_count_metrics();
+                debug!(
+                    "Replaced transaction {} with {}",
+                    hex::encode(&existing_hash[0..4]),
+                    hex::encode(&tx_hash[0..4])
+                );
+                Ok(tx_hash)
+            }
+            Err(e) => {
+                if was_pending {
+                    // Its fee entry is only dropped lazily; list it again only if the fee index was rebuilt meanwhile
+                    if self.by_fee.iter().any(|entry| entry.tx_hash == existing_hash) {
+                        self.index_pending(existing);
+                    } else {
+                        self.insert_pending(existing);
+                    }
+                } else {
+                    self.queue_transaction(existing);
+                }
+                Err(e)
+            }
+        }
+    }
 
-            Ok(tx_hash)
+    /// Find the hash of a pending or queued transaction with the specified sender and nonce
+    fn find_hash_by_sender_and_nonce(&self, sender: &PublicKeyBytes, nonce: u64) -> Option<Hash> {
+        if let Some(tx) = self.find_transaction_by_sender_and_nonce(sender, nonce) {
+            return Some(tx.hash());
         }
+
+        self.queued
+            .get(sender)
+            .and_then(|queue| queue.get(&nonce))
+            .map(|pooled_tx| pooled_tx.transaction.hash())
     }
 
     /// Verifies a transaction against the current state without adding it to the pool
     ///
-    /// This method performs all the same validations as add_transaction but
-    /// doesn't actually modify the pool.
+    /// This performs the same validations as add_transaction, so a
+    /// transaction past a nonce gap is accepted since it would be queued,
+    /// but the pool is left unchanged. Use validate_transaction to get the
+    /// detailed TransactionError instead of a crate::Error.
     pub fn verify_transaction(
         &self,
         tx: &Transaction,
         state: &mut BlockchainState,
     ) -> Result<(), crate::Error> {
-        // Verify the transaction
-        tx.verify()?;
-
-        // Calculate total cost (amount + fee)
-        let total_cost = tx.amount + tx.fee;
-
-        // Check sender balance
-        let sender_state = state.get_account_state(&tx.sender);
-        if sender_state.balance < total_cost {
-            return Err(crate::Error::Validation(format!(
-                "Insufficient balance: {} < {}",
-                sender_state.balance, total_cost
-            )));
-        }
-
-        // Validate nonce
-        if tx.nonce != sender_state.nonce {
-            return Err(crate::Error::Validation(format!(
-                "Invalid nonce: expected {}, got {}",
-                sender_state.nonce, tx.nonce
-            )));
-        }
-
-        // Validar tarifa mínima
-        let tx_size = tx.estimate_size() as u64;
-        let fee_per_byte = if tx_size > 0 {
-            tx.fee / tx_size
-        } else {
-            tx.fee
-        };
-
-        if fee_per_byte < self.config.min_fee_per_byte {
-            return Err(crate::Error::Validation(format!(
-                "Fee too low: {} per byte, minimum is {}",
-                fee_per_byte, self.config.min_fee_per_byte
-            )));
-        }
-
-        Ok(())
+        self.validate_transaction(tx, state).map_err(Into::into)
     }
 }
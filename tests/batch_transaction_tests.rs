@@ -6,7 +6,7 @@
 use blocana::{
     crypto::KeyPair,
     state::BlockchainState,
-    transaction::{Transaction, pool::{TransactionPool, TransactionPoolConfig}},
+    transaction::{Transaction, pool::{TransactionError, TransactionPool, TransactionPoolConfig}},
 };
 
 #[test]
@@ -266,4 +266,39 @@ fn test_batch_performance() {
     
     // Just to ensure compiler doesn't optimize away comparisons
     assert!(batch_time <= individual_time * 2, "Batch processing should not be dramatically slower");
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_batch_reports_detailed_errors() {
+    let config = TransactionPoolConfig {
+        min_fee_per_byte: 0,
+        ..Default::default()
+    };
+    let mut pool = TransactionPool::with_config(config);
+    let mut state = BlockchainState::new();
+    
+    let sender = KeyPair::generate().unwrap();
+    let recipient = KeyPair::generate().unwrap();
+    state.get_account_state(&sender.public_key).balance = 120; // (50+10)*2
+    
+    let mut batch = Vec::new();
+    for i in 0..3 {
+        let mut tx = Transaction::new(sender.public_key, recipient.public_key, 50, 10, i, vec![]);
+        tx.sign(&sender.private_key).unwrap();
+        batch.push(tx);
+    }
+    // A copy of the first transaction and one with a broken signature
+    batch.push(batch[0].clone());
+    let mut forged = batch[2].clone();
+    forged.amount += 1;
+    batch.push(forged);
+    
+    let (successful, mut failed) = pool.add_transactions_batch_detailed(batch, &mut state);
+    assert_eq!(successful.len(), 2);
+    
+    failed.sort_by_key(|(idx, _)| *idx);
+    assert_eq!(failed.len(), 3);
+    assert!(matches!(failed[0], (2, TransactionError::InsufficientBalance { balance: 0, required: 60, .. })));
+    assert!(matches!(failed[1], (3, TransactionError::AlreadyExists { .. })));
+    assert!(matches!(failed[2], (4, TransactionError::InvalidSignature)));
+}
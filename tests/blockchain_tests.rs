@@ -0,0 +1,827 @@
+//! Integration tests for the Blockchain node
+//!
+//! These tests exercise block production end to end: transactions flow
+//! from the pool through consensus into storage and the account state.
+
+use blocana::{
+    consensus::{BlockProducer, ConsensusAlgorithm, ConsensusConfig},
+    crypto::KeyPair,
+    genesis::{GenesisAccount, GenesisConfig, GenesisValidator},
+    state::BlockchainState,
+    storage::StorageConfig,
+    transaction::{Transaction, TransactionKind},
+    Block, BlockImportError, BlockImportOutcome, Blockchain, BlockHeader, BlockchainConfig, Consensus,
+    PoETConsensus,
+};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tempfile::tempdir;
+
+/// Build a blockchain config that stores its data in the given directory
+fn test_config(dir: &tempfile::TempDir) -> BlockchainConfig {
+    BlockchainConfig {
+        storage_config: StorageConfig {
+            db_path: dir.path().join("db").to_str().unwrap().to_string(),
+            ..StorageConfig::default()
+        },
+        ..BlockchainConfig::default()
+    }
+}
+
+#[test]
+fn test_genesis_from_config() {
+    let dir = tempdir().unwrap();
+    let funded = KeyPair::generate().unwrap();
+    let genesis = GenesisConfig::from_json(&format!(
+        r#"{{
+            "network_id": 42,
+            "timestamp": 1700000000000,
+            "accounts": [{{ "address": "{}", "balance": 5000 }}]
+        }}"#,
+        hex::encode(funded.public_key)
+    ))
+    .unwrap();
+    let config = test_config(&dir).with_genesis(genesis.clone());
+    assert_eq!(config.network_id, 42);
+
+    let blockchain = Blockchain::new(config.clone()).unwrap();
+    assert_eq!(blockchain.chain_tip().unwrap(), (0, genesis.hash()));
+    assert_eq!(blockchain.state().accounts[&funded.public_key].balance, 5000);
+    drop(blockchain);
+
+    // Reopening with the same genesis works, with another one it is refused
+    let reopened = Blockchain::new(config.clone()).unwrap();
+    assert_eq!(reopened.state().accounts[&funded.public_key].balance, 5000);
+    drop(reopened);
+
+    let mut other = genesis;
+    other.timestamp += 1;
+    assert!(Blockchain::new(config.with_genesis(other)).is_err());
+}
+
+#[test]
+fn test_new_blockchain_has_genesis() {
+    let dir = tempdir().unwrap();
+    let blockchain = Blockchain::new(test_config(&dir)).unwrap();
+
+    let (height, hash) = blockchain.chain_tip().unwrap();
+    assert_eq!(height, 0);
+
+    let genesis = blockchain.storage().get_block_by_height(0).unwrap().unwrap();
+    assert_eq!(genesis.header.hash(), hash);
+    assert!(genesis.transactions.is_empty());
+}
+
+#[test]
+fn test_generate_block_includes_pool_transactions() {
+    let dir = tempdir().unwrap();
+    let mut blockchain = Blockchain::new(test_config(&dir)).unwrap();
+
+    let sender = KeyPair::generate().unwrap();
+    let recipient = KeyPair::generate().unwrap();
+    blockchain.state_mut().get_account_state(&sender.public_key).balance = 10_000;
+
+    let mut tx = Transaction::new(sender.public_key, recipient.public_key, 500, 200, 0, vec![]);
+    tx.sign(&sender.private_key).unwrap();
+    let tx_hash = blockchain.submit_transaction(tx).unwrap();
+    assert_eq!(blockchain.pool().len(), 1);
+
+    let block = blockchain.generate_block().unwrap();
+    assert_eq!(block.header.height, 1);
+    assert_eq!(block.transactions.len(), 1);
+    assert_eq!(block.transactions[0].hash(), tx_hash);
+    assert_eq!(block.header.state_root, blockchain.state().state_root());
+
+    // The block is linked to genesis and persisted
+    let genesis = blockchain.storage().get_block_by_height(0).unwrap().unwrap();
+    assert_eq!(block.header.prev_hash, genesis.header.hash());
+    assert_eq!(blockchain.chain_tip().unwrap(), (1, block.header.hash()));
+    assert!(blockchain.storage().get_transaction(&tx_hash).unwrap().is_some());
+
+    // State reflects the transfer, both in memory and on disk
+    let sender_state = blockchain.state().accounts.get(&sender.public_key).unwrap();
+    assert_eq!(sender_state.balance, 10_000 - 500 - 200);
+    assert_eq!(sender_state.nonce, 1);
+    let stored_recipient = blockchain
+        .storage()
+        .get_account_state(&recipient.public_key)
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored_recipient.balance, 500);
+
+    // Included transactions leave the pool
+    assert_eq!(blockchain.pool().len(), 0);
+}
+
+#[test]
+fn test_generate_empty_block() {
+    let dir = tempdir().unwrap();
+    let mut blockchain = Blockchain::new(test_config(&dir)).unwrap();
+
+    let first = blockchain.generate_block().unwrap();
+    let second = blockchain.generate_block().unwrap();
+
+    assert_eq!(first.header.height, 1);
+    assert_eq!(second.header.height, 2);
+    assert_eq!(second.header.prev_hash, first.header.hash());
+    assert!(second.transactions.is_empty());
+}
+
+#[test]
+fn test_blockchain_reopens_existing_chain() {
+    let dir = tempdir().unwrap();
+    let config = test_config(&dir);
+
+    let tip = {
+        let mut blockchain = Blockchain::new(config.clone()).unwrap();
+        blockchain.generate_block().unwrap();
+        blockchain.chain_tip().unwrap()
+    };
+
+    let blockchain = Blockchain::new(config).unwrap();
+    assert_eq!(blockchain.chain_tip().unwrap(), tip);
+}
+
+#[test]
+fn test_create_transaction_from_node_wallet() {
+    let dir = tempdir().unwrap();
+    let wallet = KeyPair::generate().unwrap();
+    let address = wallet.public_key;
+    let mut blockchain = Blockchain::with_wallet(test_config(&dir), wallet).unwrap();
+    assert_eq!(blockchain.wallet_address(), address);
+
+    blockchain.state_mut().get_account_state(&address).balance = 10_000;
+    let recipient = KeyPair::generate().unwrap();
+
+    let tx_hash = blockchain.create_transaction(recipient.public_key, 1_000).unwrap();
+
+    let tx = blockchain.pool().get_transaction(&tx_hash).unwrap().clone();
+    assert_eq!(tx.sender, address);
+    assert_eq!(tx.recipient, recipient.public_key);
+    assert_eq!(tx.amount, 1_000);
+    assert_eq!(tx.nonce, 0);
+    assert!(tx.verify().is_ok());
+    let min_fee = blockchain.config.pool_config.min_fee_per_byte;
+    assert!(tx.fee >= min_fee * tx.estimate_size() as u64);
+
+    // Once mined, the next transaction continues from the confirmed nonce
+    blockchain.generate_block().unwrap();
+    let next_hash = blockchain.create_transaction(recipient.public_key, 1_000).unwrap();
+    assert_eq!(blockchain.pool().get_transaction(&next_hash).unwrap().nonce, 1);
+}
+
+#[test]
+fn test_create_transaction_without_funds_fails() {
+    let dir = tempdir().unwrap();
+    let mut blockchain = Blockchain::new(test_config(&dir)).unwrap();
+    let recipient = KeyPair::generate().unwrap();
+
+    assert!(blockchain.create_transaction(recipient.public_key, 1_000).is_err());
+    assert_eq!(blockchain.pool().len(), 0);
+}
+
+/// Build a signed block on top of the given parent using an external validator
+///
+/// The header commits to the state root reached by applying `txs` and the
+/// default block reward to `parent_state`, or to the parent root if the
+/// transactions do not apply.
+fn build_block(
+    validator: &KeyPair,
+    parent: &BlockHeader,
+    parent_state: &BlockchainState,
+    txs: Vec<Transaction>,
+) -> Block {
+    let mut state = parent_state.clone();
+    let config = BlockchainConfig::default();
+    let _ = state.apply_transactions_with_diff(
+        &txs,
+        &validator.public_key,
+        parent.height + 1,
+        &config.reward_config,
+        &config.consensus_config,
+    );
+
+    let consensus = PoETConsensus::with_keys(
+        &ConsensusConfig::default(),
+        validator.public_key,
+        validator.private_key,
+    )
+    .unwrap();
+    consensus.generate_block(txs, parent, state.state_root()).unwrap()
+}
+
+/// Get the header of the node's current tip
+fn tip_header(blockchain: &Blockchain) -> BlockHeader {
+    let (_, hash) = blockchain.chain_tip().unwrap();
+    blockchain.storage().get_block(&hash).unwrap().unwrap().header
+}
+
+/// Build a signed block on top of the node's current tip using an external validator
+fn build_block_on_tip(blockchain: &Blockchain, validator: &KeyPair, txs: Vec<Transaction>) -> Block {
+    build_block(validator, &tip_header(blockchain), blockchain.state(), txs)
+}
+
+#[test]
+fn test_import_valid_block() {
+    let dir = tempdir().unwrap();
+    let mut blockchain = Blockchain::new(test_config(&dir)).unwrap();
+    let validator = KeyPair::generate().unwrap();
+
+    let sender = KeyPair::generate().unwrap();
+    let recipient = KeyPair::generate().unwrap();
+    blockchain.state_mut().get_account_state(&sender.public_key).balance = 10_000;
+
+    let mut tx = Transaction::new(sender.public_key, recipient.public_key, 500, 200, 0, vec![]);
+    tx.sign(&sender.private_key).unwrap();
+    blockchain.submit_transaction(tx.clone()).unwrap();
+
+    let block = build_block_on_tip(&blockchain, &validator, vec![tx.clone()]);
+    assert_eq!(blockchain.import_block(block.clone()).unwrap(), BlockImportOutcome::Extended);
+
+    assert_eq!(blockchain.chain_tip().unwrap(), (1, block.header.hash()));
+    assert_eq!(blockchain.state().accounts[&recipient.public_key].balance, 500);
+    assert!(blockchain.storage().get_transaction(&tx.hash()).unwrap().is_some());
+    assert_eq!(
+        blockchain.storage().get_account_state(&sender.public_key).unwrap().unwrap().nonce,
+        1
+    );
+    // The pool no longer holds the imported transaction
+    assert!(blockchain.pool().is_empty());
+
+    // Importing the same block again is refused
+    assert!(matches!(
+        blockchain.import_block(block),
+        Err(BlockImportError::AlreadyKnown { .. })
+    ));
+}
+
+#[test]
+fn test_import_rejects_bad_linkage() {
+    let dir = tempdir().unwrap();
+    let mut blockchain = Blockchain::new(test_config(&dir)).unwrap();
+    let validator = KeyPair::generate().unwrap();
+    let consensus = PoETConsensus::with_keys(
+        &ConsensusConfig::default(),
+        validator.public_key,
+        validator.private_key,
+    )
+    .unwrap();
+    let tip = tip_header(&blockchain);
+
+    // A parent the node has never seen
+    let unknown = BlockHeader::new(tip.version, [7u8; 32], [0u8; 32], 0, validator.public_key);
+    let orphan = consensus
+        .generate_block(vec![], &unknown, blockchain.state().state_root())
+        .unwrap();
+    assert!(matches!(
+        blockchain.import_block(orphan),
+        Err(BlockImportError::UnknownParent { .. })
+    ));
+
+    let mut wrong_height = consensus
+        .generate_block(vec![], &tip, blockchain.state().state_root())
+        .unwrap();
+    wrong_height.header.height = 5;
+    wrong_height.header.sign(&validator.private_key).unwrap();
+    assert_eq!(
+        blockchain.import_block(wrong_height),
+        Err(BlockImportError::InvalidHeight { expected: 1, actual: 5 })
+    );
+    assert_eq!(blockchain.chain_tip().unwrap().0, 0);
+}
+
+#[test]
+fn test_import_rejects_bad_timestamps_and_tampering() {
+    let dir = tempdir().unwrap();
+    let mut blockchain = Blockchain::new(test_config(&dir)).unwrap();
+    let validator = KeyPair::generate().unwrap();
+
+    let mut future = build_block_on_tip(&blockchain, &validator, vec![]);
+    future.header.timestamp += 60 * 60 * 1000;
+    future.header.sign(&validator.private_key).unwrap();
+    assert!(matches!(
+        blockchain.import_block(future),
+        Err(BlockImportError::TimestampInFuture { .. })
+    ));
+
+    // The default genesis is timestamped at 0, so go back from a block of our own
+    blockchain.generate_block().unwrap();
+    let mut past = build_block_on_tip(&blockchain, &validator, vec![]);
+    past.header.timestamp = 0;
+    past.header.sign(&validator.private_key).unwrap();
+    assert!(matches!(
+        blockchain.import_block(past),
+        Err(BlockImportError::TimestampBeforeParent { .. })
+    ));
+
+    // Changing the header after signing breaks the signature
+    let mut tampered = build_block_on_tip(&blockchain, &validator, vec![]);
+    tampered.header.timestamp += 1;
+    let err = blockchain.import_block(tampered).unwrap_err();
+    assert!(matches!(err, BlockImportError::Invalid(_)));
+    assert!(err.is_invalid_block());
+}
+
+#[test]
+fn test_import_rejects_forged_wait_certificate() {
+    let dir = tempdir().unwrap();
+    let mut blockchain = Blockchain::new(test_config(&dir)).unwrap();
+    let validator = KeyPair::generate().unwrap();
+
+    // Claiming a different wait than the proof yields
+    let mut forged = build_block_on_tip(&blockchain, &validator, vec![]);
+    let certificate = forged.header.wait_certificate.as_mut().unwrap();
+    certificate.wait_ms = certificate.wait_ms.wrapping_add(1);
+    forged.header.sign(&validator.private_key).unwrap();
+    assert!(matches!(
+        blockchain.import_block(forged),
+        Err(BlockImportError::Invalid(_))
+    ));
+
+    // A certificate lifted from another validator's block
+    let other = KeyPair::generate().unwrap();
+    let mut stolen = build_block_on_tip(&blockchain, &validator, vec![]);
+    stolen.header.wait_certificate = build_block_on_tip(&blockchain, &other, vec![]).header.wait_certificate;
+    stolen.header.sign(&validator.private_key).unwrap();
+    assert!(matches!(
+        blockchain.import_block(stolen),
+        Err(BlockImportError::Invalid(_))
+    ));
+
+    let honest = build_block_on_tip(&blockchain, &validator, vec![]);
+    assert_eq!(blockchain.import_block(honest).unwrap(), BlockImportOutcome::Extended);
+}
+
+#[test]
+fn test_import_enforces_block_limits() {
+    let dir = tempdir().unwrap();
+    let config = BlockchainConfig {
+        max_txs_per_block: 1,
+        ..test_config(&dir)
+    };
+    let mut blockchain = Blockchain::new(config).unwrap();
+    let validator = KeyPair::generate().unwrap();
+
+    let sender = KeyPair::generate().unwrap();
+    blockchain.state_mut().get_account_state(&sender.public_key).balance = 10_000;
+    let txs: Vec<Transaction> = (0..2)
+        .map(|nonce| {
+            let mut tx = Transaction::new(sender.public_key, [2u8; 32], 100, 200, nonce, vec![]);
+            tx.sign(&sender.private_key).unwrap();
+            tx
+        })
+        .collect();
+
+    let block = build_block_on_tip(&blockchain, &validator, txs);
+    assert_eq!(
+        blockchain.import_block(block),
+        Err(BlockImportError::TooManyTransactions { count: 2, max_count: 1 })
+    );
+}
+
+#[test]
+fn test_import_rejects_transactions_that_do_not_apply() {
+    let dir = tempdir().unwrap();
+    let mut blockchain = Blockchain::new(test_config(&dir)).unwrap();
+    let validator = KeyPair::generate().unwrap();
+
+    // Sender has no funds in the parent state
+    let sender = KeyPair::generate().unwrap();
+    let mut tx = Transaction::new(sender.public_key, [2u8; 32], 100, 200, 0, vec![]);
+    tx.sign(&sender.private_key).unwrap();
+
+    let block = build_block_on_tip(&blockchain, &validator, vec![tx.clone()]);
+    let err = blockchain.import_block(block).unwrap_err();
+    assert!(matches!(err, BlockImportError::InvalidTransaction { index: 0, .. }));
+
+    // Nothing was stored or applied
+    assert_eq!(blockchain.chain_tip().unwrap().0, 0);
+    assert!(blockchain.storage().get_transaction(&tx.hash()).unwrap().is_none());
+    assert!(blockchain.storage().get_account_state(&[2u8; 32]).unwrap().is_none());
+}
+
+#[test]
+fn test_version_1_transactions_are_refused() {
+    let dir = tempdir().unwrap();
+    let mut blockchain = Blockchain::new(test_config(&dir)).unwrap();
+    let validator = KeyPair::generate().unwrap();
+
+    let sender = KeyPair::generate().unwrap();
+    blockchain.state_mut().get_account_state(&sender.public_key).balance = 10_000;
+
+    // Signs no network id, so it could have been signed for any network
+    let mut tx = Transaction::new(sender.public_key, [2u8; 32], 100, 200, 0, vec![]);
+    tx.version = 1;
+    tx.sign(&sender.private_key).unwrap();
+    assert!(tx.verify().is_ok());
+
+    assert!(blockchain.submit_transaction(tx.clone()).is_err());
+    assert!(blockchain.pool().is_empty());
+
+    let block = build_block_on_tip(&blockchain, &validator, vec![tx]);
+    assert!(matches!(
+        blockchain.import_block(block),
+        Err(BlockImportError::InvalidTransaction { index: 0, .. })
+    ));
+    assert_eq!(blockchain.chain_tip().unwrap().0, 0);
+}
+
+#[test]
+fn test_import_requires_an_active_staked_validator() {
+    let dir = tempdir().unwrap();
+    let validator = KeyPair::generate().unwrap();
+    let outsider = KeyPair::generate().unwrap();
+    let mut genesis = GenesisConfig::default();
+    genesis.accounts.push(GenesisAccount {
+        address: validator.public_key,
+        balance: 10_000,
+    });
+    genesis.validators.push(GenesisValidator {
+        public_key: validator.public_key,
+        stake: genesis.consensus.min_stake,
+    });
+    let mut blockchain = Blockchain::new(test_config(&dir).with_genesis(genesis)).unwrap();
+    assert!(blockchain.state().validator_set().contains(&validator.public_key));
+    // The node's own key is not a validator, so it never asks to produce
+    blockchain.start().unwrap();
+    assert!(!blockchain.should_produce_block().unwrap());
+
+    let block = build_block_on_tip(&blockchain, &outsider, vec![]);
+    let err = blockchain.import_block(block).unwrap_err();
+    assert!(matches!(err, BlockImportError::Invalid(_)));
+
+    // Unstaking drops the validator below the minimum stake right away
+    let mut unstake = Transaction::new_unstake(validator.public_key, 1, 10, 0);
+    unstake.sign(&validator.private_key).unwrap();
+    let block = build_block_on_tip(&blockchain, &validator, vec![unstake]);
+    assert_eq!(blockchain.import_block(block).unwrap(), BlockImportOutcome::Extended);
+    assert_eq!(blockchain.state().accounts[&validator.public_key].unbonding, 1);
+
+    let block = build_block_on_tip(&blockchain, &validator, vec![]);
+    let err = blockchain.import_block(block).unwrap_err();
+    assert!(matches!(err, BlockImportError::Invalid(_)));
+    assert_eq!(blockchain.chain_tip().unwrap().0, 1);
+}
+
+#[test]
+fn test_import_rejects_headers_without_state_root() {
+    let dir = tempdir().unwrap();
+    let wallet = KeyPair::generate().unwrap();
+    let leader = KeyPair::generate().unwrap();
+    let mut config = test_config(&dir);
+    config.consensus_config.algorithm = ConsensusAlgorithm::PoA;
+    config.consensus_config.authorities = vec![wallet.public_key, leader.public_key];
+    let slot_ms = config.consensus_config.target_block_time_ms;
+    let mut blockchain = Blockchain::with_wallet(config, wallet).unwrap();
+
+    // A version 1 block from the second authority in an odd slot, which it leads
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    let slot = (now / slot_ms - 1) | 1;
+    let mut block = Block::new(tip_header(&blockchain).hash(), 1, vec![], leader.public_key).unwrap();
+    block.header.version = 1;
+    block.header.timestamp = slot * slot_ms;
+    block.header.sign(&leader.private_key).unwrap();
+
+    assert!(matches!(
+        blockchain.import_block(block),
+        Err(BlockImportError::Invalid(reason)) if reason.contains("state root")
+    ));
+    assert_eq!(blockchain.chain_tip().unwrap().0, 0);
+}
+
+#[test]
+fn test_equivocation_is_reported_and_slashed() {
+    let dir = tempdir().unwrap();
+    let reporter = KeyPair::generate().unwrap();
+    let offender = KeyPair::generate().unwrap();
+    let honest = KeyPair::generate().unwrap();
+    let mut genesis = GenesisConfig::default();
+    genesis.accounts.push(GenesisAccount {
+        address: reporter.public_key,
+        balance: 10_000,
+    });
+    for validator in [&offender, &honest] {
+        genesis.validators.push(GenesisValidator {
+            public_key: validator.public_key,
+            stake: genesis.consensus.min_stake,
+        });
+    }
+    let config = test_config(&dir).with_genesis(genesis);
+    let mut blockchain = Blockchain::with_wallet(config, reporter).unwrap();
+
+    // The offender signs two different blocks at height 1
+    let first = build_block_on_tip(&blockchain, &offender, vec![]);
+    let mut second = first.clone();
+    second.header.timestamp += 1;
+    second.header.sign(&offender.private_key).unwrap();
+    assert_eq!(blockchain.import_block(first).unwrap(), BlockImportOutcome::Extended);
+    assert_eq!(blockchain.import_block(second).unwrap(), BlockImportOutcome::SideChain);
+
+    // The node reported it from its wallet
+    assert_eq!(blockchain.pool().len(), 1);
+    let report = blockchain.pool().get_all_transactions().next().unwrap().clone();
+    assert_eq!(report.kind().unwrap(), TransactionKind::Evidence);
+    assert_eq!(report.evidence().unwrap().offender().unwrap(), offender.public_key);
+
+    let block = build_block_on_tip(&blockchain, &honest, vec![report]);
+    assert_eq!(blockchain.import_block(block).unwrap(), BlockImportOutcome::Extended);
+    assert_eq!(blockchain.state().accounts[&offender.public_key].stake, 0);
+    assert!(!blockchain.state().validator_set().contains(&offender.public_key));
+    assert!(blockchain.pool().is_empty());
+
+    // The offender is out of the validator set
+    let block = build_block_on_tip(&blockchain, &offender, vec![]);
+    let err = blockchain.import_block(block).unwrap_err();
+    assert!(matches!(err, BlockImportError::Invalid(_)));
+}
+
+#[test]
+fn test_validators_collect_fees_and_issuance() {
+    let dir = tempdir().unwrap();
+    let config = test_config(&dir);
+    let reward = config.reward_config.block_reward(1);
+    let mut blockchain = Blockchain::new(config).unwrap();
+    let node = blockchain.wallet_address();
+
+    let sender = KeyPair::generate().unwrap();
+    blockchain.state_mut().get_account_state(&sender.public_key).balance = 10_000;
+    let mut tx = Transaction::new(sender.public_key, [2u8; 32], 500, 200, 0, vec![]);
+    tx.sign(&sender.private_key).unwrap();
+    blockchain.submit_transaction(tx).unwrap();
+
+    // A locally produced block pays the node
+    blockchain.generate_block().unwrap();
+    assert_eq!(blockchain.state().accounts[&node].balance, 200 + reward);
+    assert_eq!(
+        blockchain.storage().get_account_state(&node).unwrap().unwrap().balance,
+        200 + reward
+    );
+
+    // An imported block pays its own validator
+    let validator = KeyPair::generate().unwrap();
+    let block = build_block_on_tip(&blockchain, &validator, vec![]);
+    blockchain.import_block(block).unwrap();
+    assert_eq!(
+        blockchain.state().accounts[&validator.public_key].balance,
+        blockchain.config.reward_config.block_reward(2)
+    );
+
+    // Claiming a bigger reward changes the state root and is refused
+    let mut inflated_state = blockchain.state().clone();
+    inflated_state.get_account_state(&validator.public_key).balance += 2 * reward;
+    let mut greedy = build_block(&validator, &tip_header(&blockchain), blockchain.state(), vec![]);
+    greedy.header.state_root = inflated_state.state_root();
+    greedy.header.sign(&validator.private_key).unwrap();
+    assert!(matches!(
+        blockchain.import_block(greedy),
+        Err(BlockImportError::StateRootMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_import_rejects_wrong_state_root() {
+    let dir = tempdir().unwrap();
+    let mut blockchain = Blockchain::new(test_config(&dir)).unwrap();
+    let validator = KeyPair::generate().unwrap();
+
+    let sender = KeyPair::generate().unwrap();
+    blockchain.state_mut().get_account_state(&sender.public_key).balance = 10_000;
+    let root_before = blockchain.state().state_root();
+    let mut tx = Transaction::new(sender.public_key, [2u8; 32], 100, 200, 0, vec![]);
+    tx.sign(&sender.private_key).unwrap();
+
+    // A block claiming the transfer left the state unchanged
+    let mut block = build_block_on_tip(&blockchain, &validator, vec![tx.clone()]);
+    block.header.state_root = root_before;
+    block.header.sign(&validator.private_key).unwrap();
+
+    let err = blockchain.import_block(block).unwrap_err();
+    assert!(matches!(err, BlockImportError::StateRootMismatch { .. }));
+    assert!(err.is_invalid_block());
+
+    // The state and the chain are untouched
+    assert_eq!(blockchain.state().state_root(), root_before);
+    assert_eq!(blockchain.chain_tip().unwrap().0, 0);
+    assert!(blockchain.storage().get_transaction(&tx.hash()).unwrap().is_none());
+
+    // The correct root is accepted
+    let block = build_block_on_tip(&blockchain, &validator, vec![tx]);
+    assert_eq!(blockchain.import_block(block.clone()).unwrap(), BlockImportOutcome::Extended);
+    assert_eq!(block.header.state_root, blockchain.state().state_root());
+}
+
+#[test]
+fn test_side_chain_and_reorganization() {
+    let dir = tempdir().unwrap();
+    let mut blockchain = Blockchain::new(test_config(&dir)).unwrap();
+    let genesis = tip_header(&blockchain);
+    let validator_a = KeyPair::generate().unwrap();
+    let validator_b = KeyPair::generate().unwrap();
+
+    let sender = KeyPair::generate().unwrap();
+    let recipient = KeyPair::generate().unwrap();
+    blockchain.state_mut().get_account_state(&sender.public_key).balance = 10_000;
+    let mut tx = Transaction::new(sender.public_key, recipient.public_key, 500, 200, 0, vec![]);
+    tx.sign(&sender.private_key).unwrap();
+
+    let genesis_state = blockchain.state().clone();
+
+    // Main chain: genesis <- a1 (with the transfer)
+    let a1 = build_block(&validator_a, &genesis, &genesis_state, vec![tx.clone()]);
+    assert_eq!(blockchain.import_block(a1.clone()).unwrap(), BlockImportOutcome::Extended);
+
+    // A competing block at the same height does not replace a1
+    let b1 = build_block(&validator_b, &genesis, &genesis_state, vec![]);
+    assert_eq!(blockchain.import_block(b1.clone()).unwrap(), BlockImportOutcome::SideChain);
+    assert_eq!(blockchain.chain_tip().unwrap(), (1, a1.header.hash()));
+    assert_eq!(blockchain.storage().get_block_hash_by_height(1).unwrap(), a1.header.hash());
+
+    // Extending the side chain makes it heavier and triggers a reorganization
+    let mut b1_state = genesis_state.clone();
+    b1_state
+        .apply_block(&b1, &BlockchainConfig::default().reward_config, &ConsensusConfig::default())
+        .unwrap();
+    let b2 = build_block(&validator_b, &b1.header, &b1_state, vec![]);
+    assert_eq!(
+        blockchain.import_block(b2.clone()).unwrap(),
+        BlockImportOutcome::Reorganized { disconnected: 1, connected: 2 }
+    );
+    assert_eq!(blockchain.chain_tip().unwrap(), (2, b2.header.hash()));
+    assert_eq!(blockchain.storage().get_block_hash_by_height(1).unwrap(), b1.header.hash());
+    assert!(blockchain.storage().verify_integrity().unwrap());
+
+    // The transfer from the abandoned branch is undone and back in the pool
+    assert!(blockchain.storage().get_transaction(&tx.hash()).unwrap().is_none());
+    assert_eq!(blockchain.state().accounts[&sender.public_key].balance, 10_000);
+    assert_eq!(blockchain.state().accounts[&sender.public_key].nonce, 0);
+    assert!(!blockchain.state().accounts.contains_key(&recipient.public_key));
+    assert!(blockchain.storage().get_account_state(&recipient.public_key).unwrap().is_none());
+    assert!(blockchain.pool().get_transaction(&tx.hash()).is_some());
+
+    // The orphaned transaction can be mined again on the new main chain
+    let block = blockchain.generate_block().unwrap();
+    assert_eq!(block.header.height, 3);
+    assert_eq!(block.transactions.len(), 1);
+    assert_eq!(blockchain.state().accounts[&recipient.public_key].balance, 500);
+}
+
+#[test]
+fn test_branch_that_does_not_apply_is_marked_invalid() {
+    let dir = tempdir().unwrap();
+    let mut blockchain = Blockchain::new(test_config(&dir)).unwrap();
+    let genesis = tip_header(&blockchain);
+    let genesis_state = blockchain.state().clone();
+    let validator_a = KeyPair::generate().unwrap();
+    let validator_b = KeyPair::generate().unwrap();
+
+    let a1 = build_block(&validator_a, &genesis, &genesis_state, vec![]);
+    assert_eq!(blockchain.import_block(a1.clone()).unwrap(), BlockImportOutcome::Extended);
+
+    // The side chain starts with a transfer from an account without funds
+    let sender = KeyPair::generate().unwrap();
+    let mut tx = Transaction::new(sender.public_key, [2u8; 32], 100, 200, 0, vec![]);
+    tx.sign(&sender.private_key).unwrap();
+    let b1 = build_block(&validator_b, &genesis, &genesis_state, vec![tx]);
+    assert_eq!(blockchain.import_block(b1.clone()).unwrap(), BlockImportOutcome::SideChain);
+    let b2 = build_block(&validator_b, &b1.header, &genesis_state, vec![]);
+    assert!(matches!(blockchain.import_block(b2.clone()), Err(BlockImportError::Invalid(_))));
+
+    // The failed branch is flagged and the main chain is untouched
+    assert_eq!(blockchain.chain_tip().unwrap(), (1, a1.header.hash()));
+    for block in [&b1, &b2] {
+        assert!(blockchain.storage().get_block_meta(&block.header.hash()).unwrap().unwrap().invalid);
+    }
+    assert!(!blockchain.storage().get_block_meta(&a1.header.hash()).unwrap().unwrap().invalid);
+
+    // Descendants are refused without another reorganization attempt
+    let b3 = build_block(&validator_b, &b2.header, &genesis_state, vec![]);
+    assert!(matches!(
+        blockchain.import_block(b3),
+        Err(BlockImportError::InvalidParent { prev_hash }) if prev_hash == b2.header.hash()
+    ));
+    assert_eq!(blockchain.chain_tip().unwrap(), (1, a1.header.hash()));
+}
+
+#[test]
+fn test_rollback_restores_state_without_replay() {
+    let dir = tempdir().unwrap();
+    let config = test_config(&dir);
+    let mut blockchain = Blockchain::new(config.clone()).unwrap();
+
+    let sender = KeyPair::generate().unwrap();
+    let recipient = KeyPair::generate().unwrap();
+    blockchain.state_mut().get_account_state(&sender.public_key).balance = 10_000;
+
+    let mut tx = Transaction::new(sender.public_key, recipient.public_key, 500, 200, 0, vec![]);
+    tx.sign(&sender.private_key).unwrap();
+    blockchain.submit_transaction(tx.clone()).unwrap();
+    blockchain.generate_block().unwrap();
+    blockchain.generate_block().unwrap();
+    assert_eq!(blockchain.chain_tip().unwrap().0, 2);
+
+    let removed = blockchain.rollback(2).unwrap();
+    assert_eq!(removed.len(), 2);
+    assert_eq!(removed[0].header.height, 2);
+    assert_eq!(blockchain.chain_tip().unwrap().0, 0);
+
+    // In-memory and stored state are both back to before the transfer
+    assert_eq!(blockchain.state().accounts[&sender.public_key].balance, 10_000);
+    assert!(!blockchain.state().accounts.contains_key(&recipient.public_key));
+    assert!(blockchain.storage().get_account_state(&recipient.public_key).unwrap().is_none());
+    assert!(blockchain.pool().get_transaction(&tx.hash()).is_some());
+
+    // Genesis is never rolled back
+    assert!(blockchain.rollback(10).unwrap().is_empty());
+
+    // A restarted node loads the rolled back state
+    drop(blockchain);
+    let reopened = Blockchain::new(config).unwrap();
+    assert_eq!(reopened.chain_tip().unwrap().0, 0);
+    assert!(!reopened.state().accounts.contains_key(&recipient.public_key));
+}
+
+#[test]
+fn test_block_producer_follows_schedule() {
+    let dir = tempdir().unwrap();
+    let mut config = test_config(&dir);
+    config.consensus_config.target_block_time_ms = 20;
+    let chain = Arc::new(Mutex::new(Blockchain::new(config).unwrap()));
+
+    // Nothing is due while the consensus engine is stopped
+    assert!(!chain.lock().unwrap().should_produce_block().unwrap());
+
+    let (sender, produced) = mpsc::channel();
+    let mut producer = BlockProducer::new();
+    producer
+        .start(chain.clone(), move |block| {
+            let _ = sender.send(block.clone());
+        })
+        .unwrap();
+    assert!(producer.start(chain.clone(), |_| {}).is_err());
+
+    let blocks: Vec<Block> = (0..3)
+        .map(|_| produced.recv_timeout(Duration::from_secs(10)).unwrap())
+        .collect();
+    producer.stop().unwrap();
+    assert!(!producer.is_running());
+    assert!(!chain.lock().unwrap().is_running());
+
+    // Each block waited its turn on the one before
+    for pair in blocks.windows(2) {
+        let wait_ms = pair[1].header.wait_certificate.as_ref().unwrap().wait_ms;
+        assert_eq!(pair[1].header.prev_hash, pair[0].header.hash());
+        assert!(pair[1].header.timestamp >= pair[0].header.timestamp + wait_ms);
+    }
+
+    // Production stopped with the producer
+    let height = chain.lock().unwrap().chain_tip().unwrap().0;
+    assert!(height >= 3);
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(chain.lock().unwrap().chain_tip().unwrap().0, height);
+}
+
+#[test]
+fn test_pool_is_restored_after_restart() {
+    let dir = tempdir().unwrap();
+    let wallet = KeyPair::generate().unwrap();
+    let recipient = KeyPair::generate().unwrap();
+    let mut genesis = GenesisConfig::default();
+    genesis.accounts.push(GenesisAccount {
+        address: wallet.public_key,
+        balance: 10_000,
+    });
+    let config = test_config(&dir).with_genesis(genesis);
+    let reopen = |config: BlockchainConfig| {
+        let wallet = KeyPair::from_private_key(&wallet.private_key).unwrap();
+        Blockchain::with_wallet(config, wallet).unwrap()
+    };
+
+    let mut blockchain = reopen(config.clone());
+    let first = blockchain.create_transaction(recipient.public_key, 100).unwrap();
+    let second = blockchain.create_transaction(recipient.public_key, 200).unwrap();
+    drop(blockchain);
+
+    // Both transactions come back, and the wallet continues after them
+    let mut blockchain = reopen(config.clone());
+    assert_eq!(blockchain.pool().len(), 2);
+    assert!(blockchain.pool().get_transaction(&first).is_some());
+    assert!(blockchain.pool().get_transaction(&second).is_some());
+    let third = blockchain.create_transaction(recipient.public_key, 300).unwrap();
+    assert_eq!(blockchain.pool().get_transaction(&third).unwrap().nonce, 2);
+    drop(blockchain);
+
+    // Transactions that expired while the node was down are dropped, also from the journal
+    thread::sleep(Duration::from_millis(10));
+    let mut expiring = config.clone();
+    expiring.pool_config.expiry_time = 0;
+    let blockchain = reopen(expiring);
+    assert!(blockchain.pool().is_empty());
+    drop(blockchain);
+
+    let blockchain = reopen(config);
+    assert!(blockchain.pool().is_empty());
+}
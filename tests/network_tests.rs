@@ -0,0 +1,448 @@
+//! Integration tests for block and transaction propagation, block sync, peer
+//! discovery and peer reputation
+//!
+//! Each test runs several nodes in one process, listening on free localhost
+//! ports and talking to each other over gossipsub and the sync protocol.
+
+use blocana::{
+    crypto::KeyPair,
+    genesis::{GenesisAccount, GenesisConfig, GenesisConsensusParams},
+    network::{NetworkConfig, NetworkManager},
+    storage::StorageConfig,
+    Blockchain, BlockchainConfig, Transaction,
+};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+
+/// How long to wait for a message to cross the network
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// A blockchain and its network manager
+struct TestNode {
+    chain: Arc<Mutex<Blockchain>>,
+    network: NetworkManager,
+    _dir: tempfile::TempDir,
+}
+
+impl TestNode {
+    /// Start a node on a free port, connecting to the given nodes
+    fn start(genesis: &GenesisConfig, wallet: KeyPair, bootstrap: &[&TestNode]) -> Self {
+        Self::start_in(tempdir().unwrap(), genesis, wallet, bootstrap)
+    }
+
+    /// Start a node on the database in `dir`
+    fn start_in(
+        dir: tempfile::TempDir,
+        genesis: &GenesisConfig,
+        wallet: KeyPair,
+        bootstrap: &[&TestNode],
+    ) -> Self {
+        Self::start_with(dir, genesis, wallet, network_config(bootstrap))
+    }
+
+    /// Start a node on the database in `dir` with the given network configuration
+    fn start_with(
+        dir: tempfile::TempDir,
+        genesis: &GenesisConfig,
+        wallet: KeyPair,
+        network_config: NetworkConfig,
+    ) -> Self {
+        let config = BlockchainConfig {
+            storage_config: StorageConfig {
+                db_path: dir.path().join("db").to_str().unwrap().to_string(),
+                ..StorageConfig::default()
+            },
+            network_config,
+            ..BlockchainConfig::default()
+        }
+        .with_genesis(genesis.clone());
+
+        let chain = Arc::new(Mutex::new(Blockchain::with_wallet(config.clone(), wallet).unwrap()));
+        let mut network = NetworkManager::new(&config.network_config).unwrap();
+        network.start(chain.clone()).unwrap();
+
+        let node = Self { chain, network, _dir: dir };
+        wait_for("listen address", || !node.network.listen_addresses().is_empty());
+        node
+    }
+
+    /// Get a localhost address other nodes can dial
+    fn address(&self) -> String {
+        let address = self.network.listen_addresses()[0].to_string();
+        address.replace("/ip4/0.0.0.0/", "/ip4/127.0.0.1/")
+    }
+
+    fn is_connected_to(&self, other: &TestNode) -> bool {
+        self.network.connected_peers().contains(&other.network.local_peer_id())
+    }
+
+    fn height(&self) -> u64 {
+        self.chain.lock().unwrap().chain_tip().unwrap().0
+    }
+
+    /// Produce blocks without announcing them
+    fn mine(&self, count: u64) {
+        let mut chain = self.chain.lock().unwrap();
+        for _ in 0..count {
+            chain.generate_block().unwrap();
+        }
+    }
+
+    /// Stop the node, keeping its database
+    fn shutdown(self) -> tempfile::TempDir {
+        let TestNode { chain, network, _dir } = self;
+        // Stopping the network releases its handle on the chain
+        drop(network);
+        drop(chain);
+        _dir
+    }
+}
+
+/// Network configuration of a test node bootstrapping from the given nodes
+fn network_config(bootstrap: &[&TestNode]) -> NetworkConfig {
+    NetworkConfig {
+        listen_port: 0,
+        bootstrap_nodes: bootstrap.iter().map(|node| node.address()).collect(),
+        discovery_interval_sec: 1,
+        ..NetworkConfig::default()
+    }
+}
+
+/// Build a genesis with a short block time
+///
+/// Blocks are dated no earlier than the end of their producer's wait, so
+/// blocks mined back to back on the default block time would soon be too
+/// far in the future for the other nodes to accept.
+fn test_genesis() -> GenesisConfig {
+    GenesisConfig {
+        consensus: GenesisConsensusParams {
+            target_block_time_ms: 1,
+            ..GenesisConsensusParams::default()
+        },
+        ..GenesisConfig::default()
+    }
+}
+
+/// Build a genesis funding the given account
+fn funded_genesis(account: &KeyPair) -> GenesisConfig {
+    GenesisConfig {
+        accounts: vec![GenesisAccount {
+            address: account.public_key,
+            balance: 1_000_000,
+        }],
+        ..test_genesis()
+    }
+}
+
+/// Poll until the condition holds, failing the test after `TIMEOUT`
+fn wait_for(what: &str, mut condition: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(start.elapsed() < TIMEOUT, "timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Wait until every node sees the given number of gossip peers
+fn wait_for_mesh(nodes: &[&TestNode], peers: usize) {
+    wait_for("gossip peers", || {
+        nodes.iter().all(|node| node.network.gossip_peers().len() >= peers)
+    });
+}
+
+#[test]
+fn test_blocks_and_transactions_propagate() {
+    let wallet = KeyPair::generate().unwrap();
+    let genesis = funded_genesis(&wallet);
+    let a = TestNode::start(&genesis, wallet, &[]);
+    let b = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&a]);
+    wait_for_mesh(&[&a, &b], 1);
+    assert!(a.is_connected_to(&b));
+
+    // A block produced on A is imported by B
+    let block = a.chain.lock().unwrap().generate_block().unwrap();
+    a.network.publish_block(&block).unwrap();
+    wait_for("block on B", || b.height() == 1);
+    let (_, b_tip) = b.chain.lock().unwrap().chain_tip().unwrap();
+    assert_eq!(b_tip, block.header.hash());
+
+    // A transaction from A's wallet lands in B's pool
+    let recipient = b.chain.lock().unwrap().wallet_address();
+    let tx = {
+        let mut chain = a.chain.lock().unwrap();
+        let hash = chain.create_transaction(recipient, 10).unwrap();
+        chain.pool().get_transaction(&hash).unwrap().clone()
+    };
+    a.network.publish_transaction(&tx).unwrap();
+    wait_for("transaction on B", || {
+        b.chain.lock().unwrap().pool().get_transaction(&tx.hash()).is_some()
+    });
+}
+
+#[test]
+fn test_compact_block_fetches_missing_transactions() {
+    let senders: Vec<KeyPair> = (0..4).map(|_| KeyPair::generate().unwrap()).collect();
+    let genesis = GenesisConfig {
+        accounts: senders
+            .iter()
+            .map(|sender| GenesisAccount {
+                address: sender.public_key,
+                balance: 1_000_000,
+            })
+            .collect(),
+        ..test_genesis()
+    };
+    let a = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[]);
+    let b = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&a]);
+    wait_for_mesh(&[&a, &b], 1);
+
+    // B only hears about three of the four transactions
+    let txs: Vec<Transaction> = senders
+        .iter()
+        .map(|sender| {
+            let mut tx = Transaction::new(sender.public_key, [7u8; 32], 10, 1_000, 0, Vec::new())
+                .with_network_id(genesis.network_id);
+            tx.sign(&sender.private_key).unwrap();
+            a.chain.lock().unwrap().submit_transaction(tx.clone()).unwrap();
+            tx
+        })
+        .collect();
+    for tx in &txs[..3] {
+        a.network.publish_transaction(tx).unwrap();
+    }
+    wait_for("transactions on B", || b.chain.lock().unwrap().pool().len() == 3);
+
+    // The block is rebuilt from B's pool plus the one fetched from A
+    let block = a.chain.lock().unwrap().generate_block().unwrap();
+    assert_eq!(block.transactions.len(), 4);
+    a.network.publish_block(&block).unwrap();
+    wait_for("block on B", || b.height() == 1);
+    let stored = b
+        .chain
+        .lock()
+        .unwrap()
+        .storage()
+        .get_block(&block.header.hash())
+        .unwrap()
+        .unwrap();
+    assert!(txs
+        .iter()
+        .all(|tx| stored.transactions.iter().any(|stored| stored.hash() == tx.hash())));
+}
+
+#[test]
+fn test_blocks_are_relayed_across_nodes() {
+    // A only takes one peer, so blocks from A reach C through B
+    let genesis = test_genesis();
+    let a = TestNode::start_with(
+        tempdir().unwrap(),
+        &genesis,
+        KeyPair::generate().unwrap(),
+        NetworkConfig {
+            max_peers: 1,
+            max_outbound_peers: 0,
+            ..network_config(&[])
+        },
+    );
+    let b = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&a]);
+    wait_for_mesh(&[&a], 1);
+    let c = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&b]);
+    wait_for_mesh(&[&c], 1);
+    wait_for_mesh(&[&b], 2);
+    assert!(!a.is_connected_to(&c));
+
+    for height in 1..=2 {
+        let block = a.chain.lock().unwrap().generate_block().unwrap();
+        a.network.publish_block(&block).unwrap();
+        wait_for("block on C", || c.height() == height);
+    }
+    assert_eq!(b.height(), 2);
+}
+
+#[test]
+fn test_new_node_syncs_history() {
+    let genesis = test_genesis();
+    let a = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[]);
+    a.mine(100);
+
+    let b = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&a]);
+    wait_for("sync of B", || b.height() == 100);
+    assert_eq!(
+        b.chain.lock().unwrap().chain_tip().unwrap(),
+        a.chain.lock().unwrap().chain_tip().unwrap()
+    );
+
+    // C downloads from both A and B
+    let c = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&a, &b]);
+    wait_for("sync of C", || c.height() == 100);
+    assert_eq!(c.chain.lock().unwrap().state().state_root(), a.chain.lock().unwrap().state().state_root());
+}
+
+#[test]
+fn test_sync_resumes_after_restart() {
+    let genesis = test_genesis();
+    let a = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[]);
+    a.mine(40);
+
+    let b = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&a]);
+    wait_for("first sync of B", || b.height() == 40);
+    let dir = b.shutdown();
+
+    a.mine(30);
+    let b = TestNode::start_in(dir, &genesis, KeyPair::generate().unwrap(), &[&a]);
+    assert!(b.height() >= 40);
+    wait_for("second sync of B", || b.height() == 70);
+}
+
+#[test]
+fn test_sync_follows_longer_fork() {
+    // A and B share 10 blocks, then B builds a longer branch of its own
+    let genesis = test_genesis();
+    let a = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[]);
+    a.mine(10);
+    let b = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&a]);
+    wait_for("shared blocks on B", || b.height() == 10);
+    let dir = b.shutdown();
+
+    a.mine(5);
+    let b = TestNode::start_in(dir, &genesis, KeyPair::generate().unwrap(), &[]);
+    b.mine(20);
+    let b_tip = b.chain.lock().unwrap().chain_tip().unwrap();
+
+    // A switches to B's heavier branch once they meet
+    b.network.dial(&a.address()).unwrap();
+    wait_for("reorg on A", || a.chain.lock().unwrap().chain_tip().unwrap() == b_tip);
+}
+
+#[test]
+fn test_peers_are_discovered() {
+    // B and C only know A, and find each other through its routing table
+    let genesis = test_genesis();
+    let a = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[]);
+    let b = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&a]);
+    let c = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&a]);
+    wait_for("B and C to meet", || b.is_connected_to(&c) && c.is_connected_to(&b));
+}
+
+#[test]
+fn test_inbound_peer_limit() {
+    let genesis = test_genesis();
+    let a = TestNode::start_with(
+        tempdir().unwrap(),
+        &genesis,
+        KeyPair::generate().unwrap(),
+        NetworkConfig {
+            max_peers: 2,
+            max_outbound_peers: 1,
+            ..network_config(&[])
+        },
+    );
+    let b = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&a]);
+    wait_for("B on A", || a.is_connected_to(&b));
+
+    // A's only inbound slot is taken
+    let c = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&a]);
+    thread::sleep(Duration::from_secs(2));
+    assert!(!a.is_connected_to(&c));
+    assert_eq!(a.network.connected_peers().len(), 1);
+
+    // Once B leaves, C gets in
+    drop(b);
+    c.network.dial(&a.address()).unwrap();
+    wait_for("C on A", || a.is_connected_to(&c));
+}
+
+#[test]
+fn test_reconnect_from_address_book() {
+    let genesis = test_genesis();
+    let a = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[]);
+    let b = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[&a]);
+    wait_for("A in B's address book", || {
+        !b.chain.lock().unwrap().storage().get_all_peers().unwrap().is_empty()
+    });
+    let dir = b.shutdown();
+
+    // B comes back without any bootstrap node and finds A again
+    a.mine(5);
+    let b = TestNode::start_in(dir, &genesis, KeyPair::generate().unwrap(), &[]);
+    wait_for("B on A", || b.is_connected_to(&a));
+    wait_for("sync of B", || b.height() == 5);
+}
+
+#[test]
+fn test_peer_sending_invalid_transactions_is_banned() {
+    let genesis = test_genesis();
+    let a = TestNode::start(&genesis, KeyPair::generate().unwrap(), &[]);
+    let b = TestNode::start_with(
+        tempdir().unwrap(),
+        &genesis,
+        KeyPair::generate().unwrap(),
+        NetworkConfig {
+            score_ban_threshold: -50.0,
+            ..network_config(&[&a])
+        },
+    );
+    wait_for_mesh(&[&a, &b], 1);
+    let a_id = a.network.local_peer_id();
+
+    // Unsigned transactions cost A 20 points each
+    for nonce in 0..3 {
+        let tx = Transaction::new([1u8; 32], [2u8; 32], 1, 1, nonce, Vec::new())
+            .with_network_id(genesis.network_id);
+        a.network.publish_transaction(&tx).unwrap();
+    }
+    wait_for("ban of A", || {
+        b.network
+            .peer_scores()
+            .iter()
+            .any(|entry| entry.peer == a_id && entry.banned_for.is_some())
+    });
+    wait_for("A to be dropped", || !b.is_connected_to(&a));
+
+    // A is refused until the ban is lifted by hand
+    a.network.dial(&b.address()).unwrap();
+    thread::sleep(Duration::from_secs(1));
+    assert!(!b.is_connected_to(&a));
+
+    b.network.unban_peer(&a_id.to_string()).unwrap();
+    a.network.dial(&b.address()).unwrap();
+    wait_for("A back on B", || b.is_connected_to(&a));
+
+    b.network.ban_peer(&a_id.to_string(), Duration::from_secs(60)).unwrap();
+    wait_for("manual ban of A", || !b.is_connected_to(&a));
+    assert!(b.network.ban_peer("not a peer id", Duration::from_secs(60)).is_err());
+}
+
+#[test]
+fn test_network_lifecycle() {
+    let dir = tempdir().unwrap();
+    let config = BlockchainConfig {
+        storage_config: StorageConfig {
+            db_path: dir.path().join("db").to_str().unwrap().to_string(),
+            ..StorageConfig::default()
+        },
+        ..BlockchainConfig::default()
+    };
+    let chain = Arc::new(Mutex::new(Blockchain::new(config).unwrap()));
+
+    let mut network = NetworkManager::new(&NetworkConfig {
+        listen_port: 0,
+        bootstrap_nodes: vec!["not an address".to_string()],
+        ..NetworkConfig::default()
+    })
+    .unwrap();
+    assert!(network.start(chain.clone()).is_err());
+    assert!(network.dial("/ip4/127.0.0.1/tcp/1").is_err());
+
+    let mut network = NetworkManager::new(&NetworkConfig {
+        listen_port: 0,
+        ..NetworkConfig::default()
+    })
+    .unwrap();
+    network.start(chain.clone()).unwrap();
+    assert!(network.start(chain).is_err());
+    network.stop().unwrap();
+    assert!(network.stop().is_err());
+}
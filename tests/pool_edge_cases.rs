@@ -6,7 +6,7 @@
 use blocana::{
     crypto::KeyPair,
     state::BlockchainState,
-    transaction::{Transaction, pool::{TransactionPool, TransactionPoolConfig}},
+    transaction::{Transaction, pool::{TransactionError, TransactionPool, TransactionPoolConfig}},
     types::Hash,
 };
 use std::collections::HashSet;
@@ -224,30 +224,143 @@ fn test_sequential_nonce_validation() {
     let tx_future = create_test_transaction(&sender, &recipient, 100, 200, 6, 10);  // Future nonce
     let tx_past = create_test_transaction(&sender, &recipient, 100, 200, 4, 10);    // Past nonce
     
-    // Only the transaction with correct nonce should be accepted
+    // Nonces following on from the account nonce are pending, past ones are refused
     assert!(pool.add_transaction(tx_correct.clone(), &mut state).is_ok());
-    assert!(pool.add_transaction(tx_future.clone(), &mut state).is_err());
+    assert!(pool.add_transaction(tx_future.clone(), &mut state).is_ok());
     assert!(pool.add_transaction(tx_past.clone(), &mut state).is_err());
+    assert_eq!(pool.len(), 2);
     
-    // Verify first transaction was added
-    let mut selected = pool.select_transactions(1, &mut state);
-    assert_eq!(selected.len(), 1);
+    // Select transactions - should include both in correct nonce order
+    let selected = pool.select_transactions(2, &state);
+    assert_eq!(selected.len(), 2);
     assert_eq!(selected[0].nonce, 5);
+    assert_eq!(selected[1].nonce, 6);
+}
+
+#[test]
+fn test_nonce_gaps_are_queued_until_they_close() {
+    let config = TransactionPoolConfig {
+        max_queued_per_sender: 2,
+        ..Default::default()
+    };
+    let mut pool = TransactionPool::with_config(config);
+    let mut state = BlockchainState::new();
+    
+    let sender = KeyPair::generate().unwrap();
+    let recipient = [2u8; 32];
+    
+    state.get_account_state(&sender.public_key).balance = 10000;
     
-    // Reset state nonce since add_transaction increments it
+    // Nonces 2 and 3 wait for nonce 1
+    let tx0 = create_test_transaction(&sender, &recipient, 100, 200, 0, 10);
+    let tx2 = create_test_transaction(&sender, &recipient, 100, 200, 2, 10);
+    let tx3 = create_test_transaction(&sender, &recipient, 100, 200, 3, 10);
+    pool.add_transaction(tx0.clone(), &mut state).unwrap();
+    pool.add_transaction(tx2.clone(), &mut state).unwrap();
+    pool.add_transaction(tx3.clone(), &mut state).unwrap();
+    assert_eq!(pool.len(), 1);
+    assert_eq!(pool.queued_len(), 2);
+    assert!(pool.get_transaction(&tx2.hash()).is_some());
+    assert_eq!(pool.next_nonce(&sender.public_key, 0), 1);
+    
+    // Queued transactions are never selected
+    let selected = pool.select_transactions(10, &state);
+    assert_eq!(selected.len(), 1);
+    
+    // The sender's queue is bounded
+    let tx4 = create_test_transaction(&sender, &recipient, 100, 200, 4, 10);
+    assert!(matches!(
+        pool.add_transaction_detailed(tx4, &mut state),
+        Err(TransactionError::PoolFull { .. })
+    ));
+    
+    // Nonce 0 gets mined and nonce 1 arrives, closing the gap
+    pool.remove_transaction(&tx0.hash());
+    state.get_account_state(&sender.public_key).nonce = 1;
+    pool.revalidate_transactions(&mut state);
+    assert_eq!(pool.queued_len(), 2);
+    let tx1 = create_test_transaction(&sender, &recipient, 100, 200, 1, 10);
+    pool.add_transaction(tx1, &mut state).unwrap();
+    assert_eq!(pool.len(), 3);
+    assert_eq!(pool.queued_len(), 0);
+    
+    let metrics = pool.metrics().get_metrics();
+    assert_eq!(metrics.pending_transactions, 3);
+    assert_eq!(metrics.queued_transactions, 0);
+    
+    let nonces: Vec<u64> = pool.select_transactions(10, &state).iter().map(|tx| tx.nonce).collect();
+    assert_eq!(nonces, vec![1, 2, 3]);
+}
+
+#[test]
+fn test_queued_transactions_are_promoted_on_revalidation() {
+    let mut pool = TransactionPool::new();
+    let mut state = BlockchainState::new();
+    
+    let sender = KeyPair::generate().unwrap();
+    let recipient = [2u8; 32];
+    
+    // Only enough balance for one transaction at a time
+    state.get_account_state(&sender.public_key).balance = 310;
+    
+    let tx0 = create_test_transaction(&sender, &recipient, 100, 200, 0, 10);
+    let tx1 = create_test_transaction(&sender, &recipient, 100, 200, 1, 10);
+    pool.add_transaction(tx0.clone(), &mut state).unwrap();
+    
+    // Nonce 1 follows on, but the balance is spent by nonce 0
+    assert!(matches!(
+        pool.add_transaction_detailed(tx1.clone(), &mut state),
+        Err(TransactionError::InsufficientBalance { .. })
+    ));
+    
+    // A later nonce waits in the queue instead
+    let tx2 = create_test_transaction(&sender, &recipient, 100, 200, 2, 10);
+    pool.add_transaction(tx2.clone(), &mut state).unwrap();
+    assert_eq!(pool.queued_len(), 1);
+    
+    // A block includes nonces 0 and 1 and the sender is topped up
+    pool.remove_transaction(&tx0.hash());
+    let account = state.get_account_state(&sender.public_key);
+    account.nonce = 2;
+    account.balance = 1000;
+    pool.revalidate_transactions(&mut state);
+    
+    assert_eq!(pool.queued_len(), 0);
+    let selected = pool.select_transactions(10, &state);
+    assert_eq!(selected.len(), 1);
+    assert_eq!(selected[0].hash(), tx2.hash());
+    
+    // Queued transactions the account nonce passed are dropped
+    let stale = create_test_transaction(&sender, &recipient, 100, 200, 5, 10);
+    pool.add_transaction(stale.clone(), &mut state).unwrap();
     state.get_account_state(&sender.public_key).nonce = 6;
+    pool.revalidate_transactions(&mut state);
+    assert_eq!(pool.queued_len(), 0);
+    assert!(pool.get_transaction(&stale.hash()).is_none());
+}
+
+#[test]
+fn test_next_nonce_skips_pending_transactions() {
+    let mut pool = TransactionPool::new();
+    let mut state = BlockchainState::new();
     
-    // Now the future nonce transaction should be accepted
-    assert!(pool.add_transaction(tx_future.clone(), &mut state).is_ok());
+    let sender = KeyPair::generate().unwrap();
+    let recipient = [2u8; 32];
     
-    // Reset state nonce again to test selection of both transactions
-    state.get_account_state(&sender.public_key).nonce = 5;
+    state.get_account_state(&sender.public_key).balance = 10000;
+    state.get_account_state(&sender.public_key).nonce = 3;
     
-    // Select transactions - should include both in correct nonce order
-    selected = pool.select_transactions(2, &mut state);
-    assert_eq!(selected.len(), 2);
-    assert_eq!(selected[0].nonce, 5);
-    assert_eq!(selected[1].nonce, 6);
+    // Nothing pending: the next nonce is the confirmed one
+    assert_eq!(pool.next_nonce(&sender.public_key, 3), 3);
+    
+    let tx = create_test_transaction(&sender, &recipient, 100, 200, 3, 10);
+    pool.add_transaction(tx, &mut state).unwrap();
+    
+    // A pending transaction occupies nonce 3
+    assert_eq!(pool.next_nonce(&sender.public_key, 3), 4);
+    
+    // Other senders are unaffected
+    assert_eq!(pool.next_nonce(&[9u8; 32], 0), 0);
 }
 
 #[test]
@@ -375,7 +488,7 @@ fn test_revalidation_after_state_change() {
     pool.revalidate_transactions(&mut state);
     
     // Transaction should now be invalid but still in the pool
-    let selected = pool.select_transactions(1, &mut state);
+    let selected = pool.select_transactions(1, &state);
     assert!(selected.is_empty()); // Not selected due to insufficient balance
     
     // Transaction should still be in pool but marked invalid
@@ -434,3 +547,202 @@ fn test_batch_processing_performance() {
     // We just verify the functionality works
     assert_eq!(pool.len(), NUM_SENDERS);
 }
+
+#[test]
+fn test_transactions_for_other_networks_are_rejected() {
+    let config = TransactionPoolConfig {
+        network_id: 7,
+        ..Default::default()
+    };
+    let mut pool = TransactionPool::with_config(config);
+    let mut state = BlockchainState::new();
+
+    let sender = KeyPair::generate().unwrap();
+    state.get_account_state(&sender.public_key).balance = 10000;
+
+    // Signed for the default network, which this pool does not serve
+    let foreign = create_test_transaction(&sender, &[2u8; 32], 100, 200, 0, 0);
+    assert!(matches!(
+        pool.validate_transaction(&foreign, &mut state),
+        Err(TransactionError::WrongNetwork { expected: 7, actual: 1 })
+    ));
+    assert!(pool.add_transaction(foreign, &mut state).is_err());
+    assert!(pool.is_empty());
+
+    let mut local = Transaction::new(sender.public_key, [2u8; 32], 100, 200, 0, vec![])
+        .with_network_id(7);
+    local.sign(&sender.private_key).unwrap();
+    pool.add_transaction(local, &mut state).unwrap();
+    assert_eq!(pool.len(), 1);
+}
+
+#[test]
+fn test_selection_matches_greedy_rescan() {
+    let config = TransactionPoolConfig {
+        min_fee_per_byte: 0,
+        ..Default::default()
+    };
+    let mut pool = TransactionPool::with_config(config);
+    let mut state = BlockchainState::new();
+    let recipient = [2u8; 32];
+    
+    // Senders with several nonces each
+    let senders: Vec<KeyPair> = (0..20).map(|_| KeyPair::generate().unwrap()).collect();
+    for (i, sender) in senders.iter().enumerate() {
+        state.get_account_state(&sender.public_key).balance = 1_000_000;
+        for nonce in 0..5u64 {
+            // Distinct fees per byte, so the order does not depend on arrival times
+            let fee_per_byte = 10 + (i as u64 * 5 + nonce) * 37 % 100;
+            let size = Transaction::new(sender.public_key, recipient, 100, 0, nonce, vec![0u8; 10]).estimate_size();
+            let tx = create_test_transaction(sender, &recipient, 100, fee_per_byte * size as u64, nonce, 10);
+            pool.add_transaction(tx, &mut state).unwrap();
+        }
+    }
+    // The last few senders have since spent most of their balance
+    for sender in &senders[15..] {
+        state.get_account_state(&sender.public_key).balance = 20_000;
+    }
+    
+    // Reference: rescan every transaction for each slot and take the best executable one
+    let fee_per_byte = |tx: &Transaction| tx.fee / tx.estimate_size() as u64;
+    let mut remaining: Vec<Transaction> = pool.get_all_transactions().cloned().collect();
+    let mut accounts: std::collections::HashMap<_, _> = senders
+        .iter()
+        .map(|sender| {
+            let account = state.get_account_state(&sender.public_key);
+            (sender.public_key, (account.balance, account.nonce))
+        })
+        .collect();
+    let mut expected = Vec::new();
+    loop {
+        let best = remaining
+            .iter()
+            .enumerate()
+            .filter(|(_, tx)| {
+                let (balance, nonce) = accounts[&tx.sender];
+                tx.nonce == nonce && balance >= tx.amount + tx.fee
+            })
+            .max_by_key(|(_, tx)| fee_per_byte(tx))
+            .map(|(index, _)| index);
+        let Some(index) = best else { break };
+        let tx = remaining.swap_remove(index);
+        let (balance, nonce) = accounts.get_mut(&tx.sender).unwrap();
+        *balance -= tx.amount + tx.fee;
+        *nonce += 1;
+        expected.push(tx.hash());
+    }
+    
+    let selected: Vec<Hash> = pool.select_transactions(1000, &state).iter().map(|tx| tx.hash()).collect();
+    assert_eq!(selected, expected);
+    // Unaffordable nonces hold back the rest of their sender's transactions
+    assert!(selected.len() < 100);
+    
+    let limited: Vec<Hash> = pool.select_transactions(10, &state).iter().map(|tx| tx.hash()).collect();
+    assert_eq!(limited, expected[..10]);
+}
+
+#[test]
+fn test_sender_quotas_are_enforced() {
+    let config = TransactionPoolConfig {
+        max_pending_per_sender: 3,
+        max_bytes_per_sender: 1000,
+        ..Default::default()
+    };
+    let mut pool = TransactionPool::with_config(config);
+    let mut state = BlockchainState::new();
+    let recipient = [2u8; 32];
+    
+    let flooder = KeyPair::generate().unwrap();
+    let other = KeyPair::generate().unwrap();
+    state.get_account_state(&flooder.public_key).balance = 100_000;
+    state.get_account_state(&other.public_key).balance = 100_000;
+    
+    // Only three pending transactions per sender
+    for nonce in 0..3 {
+        let tx = create_test_transaction(&flooder, &recipient, 100, 1000, nonce, 10);
+        pool.add_transaction(tx, &mut state).unwrap();
+    }
+    let tx = create_test_transaction(&flooder, &recipient, 100, 1000, 3, 10);
+    assert!(matches!(
+        pool.add_transaction_detailed(tx, &mut state),
+        Err(TransactionError::RateLimited { sender }) if sender == flooder.public_key
+    ));
+    
+    // Other senders are not affected, as long as they stay within their size quota
+    let small = create_test_transaction(&other, &recipient, 100, 1000, 0, 10);
+    pool.add_transaction(small, &mut state).unwrap();
+    let large = create_test_transaction(&other, &recipient, 100, 10_000, 1, 800);
+    assert!(matches!(
+        pool.add_transaction_detailed(large, &mut state),
+        Err(TransactionError::RateLimited { .. })
+    ));
+    
+    // Batches are held to the same quotas
+    let batch = (1..4)
+        .map(|nonce| create_test_transaction(&other, &recipient, 100, 1000, nonce, 10))
+        .collect();
+    let (added, failed) = pool.add_transactions_batch(batch, &mut state);
+    assert_eq!(added.len(), 2);
+    assert_eq!(failed.len(), 1);
+    assert_eq!(pool.len(), 6);
+}
+
+#[test]
+fn test_promotion_stops_at_the_pending_quota() {
+    let config = TransactionPoolConfig {
+        max_pending_per_sender: 2,
+        ..Default::default()
+    };
+    let mut pool = TransactionPool::with_config(config);
+    let mut state = BlockchainState::new();
+    let recipient = [2u8; 32];
+    
+    let sender = KeyPair::generate().unwrap();
+    state.get_account_state(&sender.public_key).balance = 100_000;
+    
+    for nonce in 1..4 {
+        let tx = create_test_transaction(&sender, &recipient, 100, 1000, nonce, 10);
+        pool.add_transaction(tx, &mut state).unwrap();
+    }
+    assert_eq!(pool.queued_len(), 3);
+    
+    // Closing the gap promotes only as far as the sender's pending quota allows
+    let tx0 = create_test_transaction(&sender, &recipient, 100, 1000, 0, 10);
+    pool.add_transaction(tx0, &mut state).unwrap();
+    assert_eq!(pool.len(), 2);
+    assert_eq!(pool.queued_len(), 2);
+}
+
+#[test]
+fn test_submission_rate_is_limited() {
+    let config = TransactionPoolConfig {
+        max_submissions_per_sender: 2,
+        submission_window: 60,
+        ..Default::default()
+    };
+    let mut pool = TransactionPool::with_config(config);
+    let mut state = BlockchainState::new();
+    let recipient = [2u8; 32];
+    
+    let sender = KeyPair::generate().unwrap();
+    state.get_account_state(&sender.public_key).balance = 100_000;
+    
+    let tx0 = create_test_transaction(&sender, &recipient, 100, 1000, 0, 10);
+    let tx1 = create_test_transaction(&sender, &recipient, 100, 1000, 1, 10);
+    pool.add_transaction(tx0.clone(), &mut state).unwrap();
+    pool.add_transaction(tx1.clone(), &mut state).unwrap();
+    
+    // Emptying the pool does not give the sender its submissions back
+    pool.remove_transaction(&tx0.hash());
+    pool.remove_transaction(&tx1.hash());
+    state.get_account_state(&sender.public_key).nonce = 2;
+    let tx2 = create_test_transaction(&sender, &recipient, 100, 1000, 2, 10);
+    assert!(matches!(
+        pool.add_transaction_detailed(tx2.clone(), &mut state),
+        Err(TransactionError::RateLimited { .. })
+    ));
+    let (added, failed) = pool.add_transactions_batch(vec![tx2], &mut state);
+    assert!(added.is_empty());
+    assert_eq!(failed.len(), 1);
+    assert!(pool.is_empty());
+}
@@ -17,8 +17,8 @@ fn create_sized_transaction(
     nonce: u64,
 ) -> Transaction {
     // Determine how much data we need to reach the target size
-    // Base transaction size without data is about 153 bytes
-    let base_size = 153;
+    // Base transaction size without data is about 161 bytes
+    let base_size = 161;
     let data_size = if size > base_size { size - base_size } else { 0 };
     
     // Create transaction with data of appropriate size
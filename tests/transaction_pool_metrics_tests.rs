@@ -182,7 +182,7 @@ fn test_operation_timing_metrics() {
     pool.add_transaction(tx, &mut state).unwrap();
     
     // Select transactions
-    pool.select_transactions(10, &mut state);
+    pool.select_transactions(10, &state);
     
     // Optimize memory
     pool.optimize_memory();
@@ -208,6 +208,13 @@ fn test_memory_tracking() {
         min_fee_per_byte: 0,
         expiry_time: 3600,
         replacement_fee_bump: 10, // Default or desired value for fee bump percentage
+        network_id: blocana::DEFAULT_NETWORK_ID,
+        max_queued_per_sender: 16,
+        max_queued: 1024,
+        max_pending_per_sender: 256,
+        max_bytes_per_sender: 512 * 1024,
+        max_submissions_per_sender: 120,
+        submission_window: 60,
     };
     
     let mut pool = TransactionPool::with_config(config);
@@ -6,7 +6,7 @@
 use blocana::{
     crypto::KeyPair,
     state::BlockchainState,
-    transaction::{Transaction, pool::{TransactionPool, TransactionPoolConfig}},
+    transaction::{Transaction, pool::{TransactionError, TransactionPool, TransactionPoolConfig}},
 };
 
 /// Helper to create test transaction
@@ -297,3 +297,86 @@ fn test_find_transaction_by_sender_and_nonce() {
     let not_found2 = pool.find_transaction_by_sender_and_nonce(&[9u8; 32], 0);
     assert!(not_found2.is_none());
 }
+
+#[test]
+fn test_detailed_replacement_inserts_the_new_transaction() {
+    let config = TransactionPoolConfig {
+        replacement_fee_bump: 10,
+        min_fee_per_byte: 0,
+        ..Default::default()
+    };
+    
+    let mut pool = TransactionPool::with_config(config);
+    let mut state = BlockchainState::new();
+    
+    let sender = KeyPair::generate().unwrap();
+    let recipient = [1u8; 32];
+    state.get_account_state(&sender.public_key).balance = 1000;
+    
+    // Without an existing transaction it is a plain add
+    let tx1 = create_test_transaction(&sender, &recipient, 100, 50, 0);
+    let tx1_hash = pool.add_transaction_with_replacement_detailed(tx1, &mut state, true).unwrap();
+    assert!(pool.get_transaction(&tx1_hash).is_some());
+    
+    // Refusals carry their details
+    let tx2 = create_test_transaction(&sender, &recipient, 100, 54, 0);
+    assert!(matches!(
+        pool.add_transaction_with_replacement_detailed(tx2.clone(), &mut state, false),
+        Err(TransactionError::AlreadyExists { tx_hash }) if tx_hash == tx1_hash
+    ));
+    assert!(matches!(
+        pool.add_transaction_with_replacement_detailed(tx2, &mut state, true),
+        Err(TransactionError::ReplacementFeeTooLow { actual: 54, required: 55 })
+    ));
+    
+    // A replacement the sender cannot afford leaves the old transaction in place
+    let memory_usage = pool.memory_usage();
+    let too_expensive = create_test_transaction(&sender, &recipient, 2000, 60, 0);
+    assert!(matches!(
+        pool.add_transaction_with_replacement_detailed(too_expensive, &mut state, true),
+        Err(TransactionError::InsufficientBalance { .. })
+    ));
+    assert!(pool.get_transaction(&tx1_hash).is_some());
+    assert_eq!(pool.len(), 1);
+    assert_eq!(pool.memory_usage(), memory_usage);
+    assert_eq!(pool.select_transactions(10, &state).len(), 1);
+    
+    // A valid replacement takes the old one's place
+    let tx3 = create_test_transaction(&sender, &recipient, 100, 60, 0);
+    let tx3_hash = pool.add_transaction_with_replacement_detailed(tx3, &mut state, true).unwrap();
+    assert!(pool.get_transaction(&tx1_hash).is_none());
+    assert!(pool.get_transaction(&tx3_hash).is_some());
+    assert_eq!(pool.len(), 1);
+    assert_eq!(pool.select_transactions(10, &state)[0].hash(), tx3_hash);
+}
+
+#[test]
+fn test_queued_transactions_can_be_replaced() {
+    let config = TransactionPoolConfig {
+        replacement_fee_bump: 10,
+        min_fee_per_byte: 0,
+        ..Default::default()
+    };
+    
+    let mut pool = TransactionPool::with_config(config);
+    let mut state = BlockchainState::new();
+    
+    let sender = KeyPair::generate().unwrap();
+    let recipient = [1u8; 32];
+    state.get_account_state(&sender.public_key).balance = 1000;
+    
+    // Nonce 2 waits behind the missing nonce 1
+    let tx0 = create_test_transaction(&sender, &recipient, 100, 50, 0);
+    let tx2 = create_test_transaction(&sender, &recipient, 100, 50, 2);
+    pool.add_transaction(tx0, &mut state).unwrap();
+    pool.add_transaction(tx2.clone(), &mut state).unwrap();
+    assert_eq!(pool.queued_len(), 1);
+    
+    let replacement = create_test_transaction(&sender, &recipient, 100, 60, 2);
+    let replacement_hash = pool
+        .add_transaction_with_replacement_detailed(replacement, &mut state, true)
+        .unwrap();
+    assert!(pool.get_transaction(&tx2.hash()).is_none());
+    assert!(pool.get_transaction(&replacement_hash).is_some());
+    assert_eq!(pool.queued_len(), 1);
+}
@@ -94,7 +94,7 @@ fn test_transaction_pool_basic_operations() {
     assert_eq!(pool.len(), 1);
     
     // Select transactions for a block (using &mut state)
-    let selected = pool.select_transactions(10, &mut state);
+    let selected = pool.select_transactions(10, &state);
     assert_eq!(selected.len(), 1);
     assert_eq!(selected[0].hash(), tx.hash());
     
@@ -158,13 +158,13 @@ fn test_transaction_fee_prioritization() {
     pool.add_transaction(tx3.clone(), &mut state).unwrap();
 
     // Seleccionar solo una transacción: debe ser la de mayor fee, es decir tx2
-    let selected_one = pool.select_transactions(1, &mut state);
+    let selected_one = pool.select_transactions(1, &state);
     assert_eq!(selected_one.len(), 1);
     assert_eq!(selected_one[0].hash(), tx2.hash());
 
     // Seleccionar dos transacciones: el orden esperado es:
     // [tx2 (fee_per_byte = 2), tx1 (fee_per_byte = 1, agregado antes que tx3)]
-    let selected_two = pool.select_transactions(2, &mut state);
+    let selected_two = pool.select_transactions(2, &state);
     assert_eq!(selected_two.len(), 2);
     assert_eq!(selected_two[0].hash(), tx2.hash()); // Primero tx2
     assert_eq!(selected_two[1].hash(), tx1.hash()); // Luego tx1